@@ -0,0 +1,48 @@
+use crate::AffectedError;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Settings read from an `affected.toml` (or `.affected.toml`) at the
+/// workspace root, so long lists can live in the repository instead of the
+/// workflow file.
+///
+/// Every key is optional; unknown keys are rejected to catch typos.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AffectedConfig {
+    /// Patterns in the [`crate::check_force_triggers`] syntax.
+    pub force_triggers: Vec<String>,
+    /// Entries in the [`crate::AffectedOptions::excluded`] syntax.
+    pub excluded_members: Vec<String>,
+    /// Entries in the [`crate::AffectedOptions::included`] syntax.
+    pub included_members: Vec<String>,
+    /// Globs mapped to crate names, in the
+    /// [`crate::AffectedOptions::codegen_inputs`] syntax: a table such as
+    /// `"fixtures/" = ["lib-core"]` marks those crates changed when a shared
+    /// file outside their directories changes.
+    pub codegen_inputs: BTreeMap<String, Vec<String>>,
+}
+
+impl AffectedConfig {
+    /// The file names looked for, in order; the first one present is used.
+    pub const FILE_NAMES: [&'static str; 2] = ["affected.toml", ".affected.toml"];
+
+    /// Load the config file from `workspace_dir`, or the default (empty)
+    /// config when there is none.
+    pub fn load(workspace_dir: &Path) -> Result<Self, AffectedError> {
+        let Some(path) = Self::FILE_NAMES
+            .iter()
+            .map(|name| workspace_dir.join(name))
+            .find(|path| path.is_file())
+        else {
+            return Ok(Self::default());
+        };
+        let config_error = |message: String| AffectedError::Config {
+            path: path.clone(),
+            message,
+        };
+        let contents = std::fs::read_to_string(&path).map_err(|e| config_error(e.to_string()))?;
+        toml::from_str(&contents).map_err(|e| config_error(e.to_string()))
+    }
+}
@@ -0,0 +1,116 @@
+use std::error::Error;
+use std::fmt;
+
+/// Errors reported by the fallible parts of the library.
+#[derive(Debug)]
+pub enum AffectedError {
+    /// A force-trigger pattern is not a valid glob.
+    InvalidGlob {
+        /// The pattern as it was passed in.
+        pattern: String,
+        source: globset::Error,
+    },
+    /// The member kind after a force trigger's `=>` is neither `libraries`
+    /// nor `binaries`.
+    InvalidTriggerKind {
+        /// The trigger as it was passed in.
+        trigger: String,
+    },
+    /// The target triple isn't one guppy knows how to evaluate.
+    InvalidTarget {
+        /// The triple as it was passed in.
+        triple: String,
+        message: String,
+    },
+    /// A setting given to the binary, e.g. an environment variable, isn't
+    /// valid.
+    InvalidInput {
+        /// The environment variable or flag, e.g. `OUTPUT_FORMAT`.
+        name: String,
+        message: String,
+    },
+    /// The package graph couldn't be loaded, e.g. outside a Cargo workspace.
+    Metadata(guppy::Error),
+    /// A git command failed, e.g. because a revision range doesn't exist.
+    Git(std::io::Error),
+    /// A config file couldn't be read or isn't valid.
+    Config {
+        path: std::path::PathBuf,
+        message: String,
+    },
+    /// A member's sources couldn't be read for its source hash.
+    SourceHash {
+        /// The member's crate name.
+        name: String,
+        source: std::io::Error,
+    },
+    /// An output couldn't be written, e.g. to `GITHUB_OUTPUT`.
+    Output {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    /// One of several workspaces failed.
+    Workspace {
+        /// The workspace directory as it was passed in.
+        dir: std::path::PathBuf,
+        source: Box<AffectedError>,
+    },
+}
+
+impl fmt::Display for AffectedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AffectedError::InvalidGlob { pattern, source } => {
+                write!(f, "invalid glob pattern {pattern:?}: {source}")
+            }
+            AffectedError::InvalidTriggerKind { trigger } => {
+                write!(
+                    f,
+                    "invalid member kind in force trigger {trigger:?}; expected \"libraries\" or \"binaries\""
+                )
+            }
+            AffectedError::InvalidTarget { triple, message } => {
+                write!(f, "invalid target triple {triple:?}: {message}")
+            }
+            AffectedError::InvalidInput { name, message } => {
+                write!(f, "invalid {name}: {message}")
+            }
+            AffectedError::Metadata(source) => {
+                write!(
+                    f,
+                    "not a Cargo workspace or cargo metadata failed: {source}"
+                )
+            }
+            AffectedError::Git(source) => write!(f, "{source}"),
+            AffectedError::Config { path, message } => {
+                write!(f, "invalid config file {}: {message}", path.display())
+            }
+            AffectedError::SourceHash { name, source } => {
+                write!(f, "failed to hash the sources of {name}: {source}")
+            }
+            AffectedError::Output { path, source } => {
+                write!(f, "failed to write {}: {source}", path.display())
+            }
+            AffectedError::Workspace { dir, source } => {
+                write!(f, "workspace {}: {source}", dir.display())
+            }
+        }
+    }
+}
+
+impl Error for AffectedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AffectedError::InvalidGlob { source, .. } => Some(source),
+            AffectedError::Metadata(source) => Some(source),
+            AffectedError::Git(source) => Some(source),
+            AffectedError::SourceHash { source, .. } => Some(source),
+            AffectedError::Output { source, .. } => Some(source),
+            AffectedError::Workspace { source, .. } => Some(source.as_ref()),
+            AffectedError::InvalidTriggerKind { .. }
+            | AffectedError::InvalidTarget { .. }
+            | AffectedError::InvalidInput { .. }
+            | AffectedError::Config { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Affected-status history of one crate across runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlapHistory {
+    /// Runs recorded since the crate was first affected.
+    pub runs: usize,
+    /// How many of those runs affected the crate.
+    pub affected_runs: usize,
+    /// How often the crate switched between affected and not affected.
+    pub flips: usize,
+    /// Whether the most recent run affected the crate.
+    pub last_affected: bool,
+}
+
+/// Per-crate affected history accumulated across runs, for spotting crates
+/// whose affected status keeps flip-flopping (e.g. nondeterministic diffs).
+///
+/// A crate is tracked from the first run that affects it; crates that were
+/// never affected can't flap and aren't stored. Serializes to a JSON object
+/// keyed by crate name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FlapState {
+    pub crates: BTreeMap<String, FlapHistory>,
+}
+
+impl FlapState {
+    /// Record one run in which exactly the `affected` crates were affected.
+    pub fn record<S: AsRef<str>>(&mut self, affected: &[S]) {
+        for name in affected {
+            self.crates.entry(name.as_ref().to_string()).or_default();
+        }
+        for (name, history) in &mut self.crates {
+            let is_affected = affected.iter().any(|a| a.as_ref() == name);
+            if history.runs > 0 && history.last_affected != is_affected {
+                history.flips += 1;
+            }
+            history.runs += 1;
+            history.affected_runs += usize::from(is_affected);
+            history.last_affected = is_affected;
+        }
+    }
+
+    /// Crates whose affected status changed more than `threshold` times,
+    /// sorted by name.
+    pub fn flapping(&self, threshold: usize) -> Vec<String> {
+        self.crates
+            .iter()
+            .filter(|(_, history)| history.flips > threshold)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
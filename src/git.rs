@@ -0,0 +1,110 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Run `git` in `repo_dir` and return its stdout.
+///
+/// A non-zero exit status is reported as an error carrying git's stderr.
+fn git(repo_dir: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(repo_dir)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// List the files that changed between the previous position of `HEAD` recorded
+/// in the reflog (`HEAD@{1}`) and the current `HEAD`.
+///
+/// This answers "what changed in this push" on force-pushed or rebased branches,
+/// where a diff against the base ref would include unrelated commits.
+///
+/// Paths and renames are reported like [`changed_files_in_range`], relative to
+/// `repo_dir`.
+///
+/// Returns `Ok(None)` when the reflog has no previous entry (e.g. a fresh clone),
+/// so callers can fall back to another source of changed files.
+pub fn changed_files_since_reflog(repo_dir: &Path) -> io::Result<Option<Vec<String>>> {
+    let has_previous = Command::new("git")
+        .args(["rev-parse", "--verify", "--quiet", "HEAD@{1}"])
+        .current_dir(repo_dir)
+        .output()?
+        .status
+        .success();
+    if !has_previous {
+        return Ok(None);
+    }
+
+    changed_files_in_range(repo_dir, "HEAD@{1}..HEAD").map(Some)
+}
+
+/// List the files that changed in a git revision range such as `main..HEAD`
+/// or `main...HEAD`, relative to `repo_dir`.
+///
+/// Paths are reported relative to `repo_dir` and limited to files below it,
+/// so a workspace nested inside a larger repository sees workspace-relative
+/// paths. A renamed file contributes both its old and its new path, since the
+/// crates at either end are affected.
+pub fn changed_files_in_range(repo_dir: &Path, range: &str) -> io::Result<Vec<String>> {
+    let stdout = git(
+        repo_dir,
+        &["diff", "--name-status", "-M", "--relative", range, "--"],
+    )?;
+    let mut files = Vec::new();
+    for line in stdout.lines() {
+        // `<status>\t<path>`, or `R<score>\t<old>\t<new>` for renames and
+        // `C<score>\t<source>\t<copy>` for copies.
+        let mut fields = line.split('\t');
+        let status = fields.next().unwrap_or_default();
+        let paths: Vec<&str> = fields.collect();
+        if status.starts_with('C') {
+            // The copy source is unchanged; only the new file is.
+            files.extend(paths.last().map(|p| p.to_string()));
+        } else {
+            files.extend(paths.iter().map(|p| p.to_string()));
+        }
+    }
+    Ok(files)
+}
+
+/// Read a file as it was at revision `rev`, e.g. the base lockfile of a pull
+/// request. `path` is relative to `repo_dir`, which may be a subdirectory of
+/// the repository.
+///
+/// Fails when the revision is unknown or the file didn't exist at it.
+pub fn file_at_rev(repo_dir: &Path, rev: &str, path: &str) -> io::Result<String> {
+    git(repo_dir, &["show", &format!("{rev}:./{path}")])
+}
+
+/// Extract the paths from `git status --porcelain` output, e.g. `M lib.rs` or
+/// `R old.rs -> new.rs`.
+///
+/// The one- or two-letter status code is dropped, as are blank lines and the
+/// quotes git puts around unusual paths. A rename or copy contributes its
+/// destination, and also its source when `rename_sources` is set.
+pub fn parse_porcelain(output: &str, rename_sources: bool) -> Vec<String> {
+    let mut files = Vec::new();
+    for line in output.lines() {
+        let Some((_status, paths)) = line.trim().split_once(char::is_whitespace) else {
+            continue;
+        };
+        let unquote = |path: &str| path.trim().trim_matches('"').to_string();
+        match paths.split_once(" -> ") {
+            Some((from, to)) => {
+                if rename_sources {
+                    files.push(unquote(from));
+                }
+                files.push(unquote(to));
+            }
+            None => files.push(unquote(paths)),
+        }
+    }
+    files
+}
@@ -0,0 +1,76 @@
+use guppy::graph::PackageGraph;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// How a single workspace member's direct dependencies differ between two graphs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberDiff {
+    pub name: String,
+    /// Dependencies present in the new graph but not in the old one.
+    pub added: Vec<String>,
+    /// Dependencies present in the old graph but not in the new one.
+    pub removed: Vec<String>,
+}
+
+/// Workspace members whose direct dependency sets differ between two graphs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphDiff {
+    /// Members with at least one added or removed dependency, sorted by name.
+    pub changed_members: Vec<MemberDiff>,
+    /// Names of the members that lost at least one dependency, sorted. These
+    /// may be left with dead `use` statements or unused dependencies, so a
+    /// cleanup job can target them.
+    pub deps_removed: Vec<String>,
+}
+
+/// Map each workspace member's name to the names of its direct dependencies.
+fn member_dependencies(graph: &PackageGraph) -> BTreeMap<String, BTreeSet<String>> {
+    graph
+        .workspace()
+        .iter()
+        .map(|pkg| {
+            let deps = pkg
+                .direct_links()
+                .map(|link| link.to().name().to_string())
+                .collect();
+            (pkg.name().to_string(), deps)
+        })
+        .collect()
+}
+
+/// Compare two package graphs (e.g. before and after a dependency bump) and
+/// report which workspace members gained or lost direct dependencies.
+///
+/// Dependencies are compared by package name, across all dependency kinds. A
+/// member that only exists in one of the graphs is reported with all of its
+/// dependencies added (or removed). This is independent of any file changes.
+pub fn graph_diff(old: &PackageGraph, new: &PackageGraph) -> GraphDiff {
+    let old_deps = member_dependencies(old);
+    let new_deps = member_dependencies(new);
+    let none = BTreeSet::new();
+
+    let names: BTreeSet<&String> = old_deps.keys().chain(new_deps.keys()).collect();
+    let changed_members = names
+        .into_iter()
+        .filter_map(|name| {
+            let before = old_deps.get(name).unwrap_or(&none);
+            let after = new_deps.get(name).unwrap_or(&none);
+            let added: Vec<String> = after.difference(before).cloned().collect();
+            let removed: Vec<String> = before.difference(after).cloned().collect();
+            (!added.is_empty() || !removed.is_empty()).then(|| MemberDiff {
+                name: name.clone(),
+                added,
+                removed,
+            })
+        })
+        .collect::<Vec<_>>();
+    let deps_removed = changed_members
+        .iter()
+        .filter(|member| !member.removed.is_empty())
+        .map(|member| member.name.clone())
+        .collect();
+
+    GraphDiff {
+        changed_members,
+        deps_removed,
+    }
+}
@@ -0,0 +1,41 @@
+use serde_json::{Value, json};
+
+/// Escape an object key as a JSON Pointer reference token (RFC 6901).
+fn escape_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn diff_into(path: &str, baseline: &Value, current: &Value, ops: &mut Vec<Value>) {
+    if baseline == current {
+        return;
+    }
+    match (baseline, current) {
+        (Value::Object(before), Value::Object(after)) => {
+            for key in before.keys().filter(|key| !after.contains_key(*key)) {
+                let path = format!("{path}/{}", escape_token(key));
+                ops.push(json!({ "op": "remove", "path": path }));
+            }
+            for (key, value) in after {
+                let path = format!("{path}/{}", escape_token(key));
+                match before.get(key) {
+                    Some(old) => diff_into(&path, old, value, ops),
+                    None => ops.push(json!({ "op": "add", "path": path, "value": value })),
+                }
+            }
+        }
+        // Lists are replaced whole: they are short, and an element-wise diff
+        // would depend on positions that shift with every insertion.
+        _ => ops.push(json!({ "op": "replace", "path": path, "value": current })),
+    }
+}
+
+/// Compute a JSON Patch (RFC 6902) that transforms `baseline` into `current`.
+///
+/// Objects are diffed key by key with `add`, `remove` and `replace`
+/// operations; any other differing value, including arrays, is replaced
+/// whole. Identical documents give an empty patch.
+pub fn json_patch(baseline: &Value, current: &Value) -> Value {
+    let mut ops = Vec::new();
+    diff_into("", baseline, current, &mut ops);
+    Value::Array(ops)
+}
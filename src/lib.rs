@@ -1,14 +1,617 @@
-use globset::{Glob, GlobSetBuilder};
-use guppy::graph::PackageGraph;
-use std::collections::HashSet;
-use std::path::Path;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use guppy::graph::{DependencyDirection, PackageGraph, PackageLink, PackageMetadata, PackageSet};
+use guppy::platform::{EnabledTernary, Platform, PlatformSpec, TargetFeatures};
+use guppy::{DependencyKind, PackageId};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+mod config;
+mod error;
+mod flap;
+pub mod git;
+mod graph_diff;
+mod json_patch;
+mod lockfile;
+mod manifest;
+mod member_patterns;
+mod metadata_cache;
+mod source_hash;
+
+pub use config::AffectedConfig;
+pub use error::AffectedError;
+pub use flap::{FlapHistory, FlapState};
+pub use graph_diff::{GraphDiff, MemberDiff, graph_diff};
+pub use json_patch::json_patch;
+use member_patterns::MemberPatterns;
+pub use metadata_cache::cached_package_graph;
+pub use source_hash::source_hash;
+
+/// The outcome of an affected-crates computation.
+///
+/// Serializes to the same JSON object the binary prints, with snake_case keys
+/// matching the action outputs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
 pub struct AffectedResult {
+    /// Always [`SCHEMA_VERSION`], so consumers of the JSON can check they
+    /// understand its shape.
+    pub schema_version: SchemaVersion,
     pub force_all: bool,
+    /// The force trigger that set `force_all` and the file it matched, e.g.
+    /// `infra/ (matched infra/deploy.yml)`. `None` when no trigger matched,
+    /// even if `force_all` was set another way, e.g. by
+    /// [`AffectedOptions::force_all_ratio`]; see `force_all_cause`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub force_trigger_reason: Option<String>,
+    /// Why `force_all` was set when no force trigger matched, e.g. `Cargo.lock
+    /// changed and couldn't be compared with its base version` or the
+    /// affected ratio reaching [`AffectedOptions::force_all_ratio`]. `None`
+    /// when `force_all` is unset or `force_trigger_reason` explains it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub force_all_cause: Option<String>,
+    /// The kind of member a force trigger limited to one kind
+    /// (`<pattern> => libraries` or `<pattern> => binaries`) filled in: every
+    /// member of that kind is affected, while the other list comes from the
+    /// usual dependency traversal. `None` when no such trigger matched or
+    /// `force_all` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forced_kind: Option<MemberKind>,
+    /// Whether CI has anything to do: `force_all` is set or any of
+    /// `changed_crates`, `affected_library_members` and
+    /// `affected_binary_members` is non-empty.
+    pub any_affected: bool,
     pub changed_crates: Vec<String>,
+    /// Crates whose directory was deleted, e.g. for cleaning up their
+    /// artifacts. Detected from a changed `Cargo.toml` that no workspace member
+    /// owns and that no longer exists on disk. Named after the base manifest
+    /// given to [`AffectedOptions::base_manifests`], or else the directory.
+    pub removed_crates: Vec<String>,
     pub affected_library_members: Vec<String>,
     pub affected_binary_members: Vec<String>,
+    /// Every `[[bin]]` target of `affected_binary_members` as a
+    /// `(crate_name, binary_target_name)` pair, sorted, for building or
+    /// deploying individual binaries rather than whole crates. Serialized as
+    /// `{"crate": "...", "bin": "..."}` objects.
+    #[serde(serialize_with = "serialize_bin_targets")]
+    pub affected_bin_targets: Vec<(String, String)>,
+    /// `affected_library_members` ordered so that every crate comes after the
+    /// crates it depends on, for staged builds. Ties are sorted alphabetically.
+    pub affected_build_order: Vec<String>,
+    /// `affected_library_members` that are only affected through a dependency,
+    /// i.e. without `changed_crates`. A crate that changed directly counts as
+    /// changed even if it also depends on another changed crate.
+    pub transitively_affected_members: Vec<String>,
+    /// `affected_library_members` that are procedural macro crates, e.g. for
+    /// always rebuilding their dependents. They stay in
+    /// `affected_library_members` as well.
+    pub affected_proc_macro_members: Vec<String>,
+    /// Affected members whose library target is built as a `cdylib` or
+    /// `staticlib`, for packaging FFI artifacts. A member also producing an
+    /// rlib stays in `affected_library_members` as well.
+    pub affected_cdylib_members: Vec<String>,
+    /// Workspace-relative directory of each affected member, keyed by crate
+    /// name, e.g. for locating a binary's Dockerfile. Uses `/` separators;
+    /// a member at the workspace root maps to `.`.
+    pub affected_paths: BTreeMap<String, String>,
+    /// Hash of each affected member's source tree, keyed by crate name. Only
+    /// filled in when [`AffectedOptions::source_hashes`] is enabled.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub source_hashes: BTreeMap<String, String>,
+    /// Every crate the changed crates depend on, transitively — workspace
+    /// members and external packages alike, sorted and deduplicated by name —
+    /// for pre-building or prefetching artifacts. Only filled in when
+    /// [`AffectedOptions::dependencies_of_changed`] is enabled.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies_of_changed: Vec<String>,
+    /// `(in, out)` degree of each affected member within the workspace: how many
+    /// members directly depend on it, and how many members it directly depends
+    /// on. Only filled in when [`AffectedOptions::degrees`] is enabled.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub degrees: BTreeMap<String, (usize, usize)>,
+    /// Affected members reached through normal and build dependency edges
+    /// only, to drive build and deploy jobs. Only filled in when
+    /// [`AffectedOptions::split_build_test`] is enabled.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub affected_build: Vec<String>,
+    /// Affected members reached through every dependency edge, including
+    /// dev-dependencies, to drive test jobs. Only filled in when
+    /// [`AffectedOptions::split_build_test`] is enabled.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub affected_test: Vec<String>,
+    /// Rust edition of each affected member, keyed by crate name, e.g. for
+    /// routing edition-2015 crates to a compatibility job. Only filled in when
+    /// [`AffectedOptions::editions`] is enabled.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub editions: BTreeMap<String, String>,
+    /// What to test, as opposed to `affected_binary_members`, what to deploy:
+    /// the affected binaries plus the `changed_crates` with a library or test
+    /// target that runs under `cargo test`, sorted. Only filled in when
+    /// [`AffectedOptions::testable_members`] is enabled.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub testable_members: Vec<String>,
+    /// Affected members whose version still equals their last published
+    /// version, i.e. they changed but weren't bumped. Only filled in for the
+    /// crates listed in [`AffectedOptions::published_versions`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub needs_version_bump: Vec<String>,
+    /// Every affected member (libraries and binaries) paired with its position
+    /// in sorted order, numbered from 0, for CI systems that index matrix
+    /// entries. Serialized as `{"index": 0, "name": "..."}` objects so jobs can
+    /// use `matrix.index`. Only filled in when [`AffectedOptions::indexed`] is
+    /// enabled.
+    #[serde(
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_indexed"
+    )]
+    pub indexed: Vec<(usize, String)>,
+    /// Crates whose affected status keeps changing across runs, according to
+    /// a [`FlapState`]. Never filled in by [`AffectedOptions::compute`]; the
+    /// binary sets it when `FLAP_STATE` is configured.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub flapping: Vec<String>,
+}
+
+/// Version of the JSON shape of [`AffectedResult`], emitted as
+/// `schema_version`. It is bumped when an existing output is removed, renamed
+/// or changes type or meaning; adding a new output does not bump it, so
+/// consumers should ignore keys they don't know.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Marker for [`AffectedResult::schema_version`] that serializes as
+/// [`SCHEMA_VERSION`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SchemaVersion;
+
+impl SchemaVersion {
+    /// The version number, i.e. [`SCHEMA_VERSION`].
+    pub const fn get(self) -> u32 {
+        SCHEMA_VERSION
+    }
+}
+
+impl Serialize for SchemaVersion {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(SCHEMA_VERSION)
+    }
+}
+
+fn serialize_bin_targets<S: serde::Serializer>(
+    bin_targets: &[(String, String)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        #[serde(rename = "crate")]
+        krate: &'a str,
+        bin: &'a str,
+    }
+    serializer.collect_seq(bin_targets.iter().map(|(krate, bin)| Entry { krate, bin }))
+}
+
+fn serialize_indexed<S: serde::Serializer>(
+    indexed: &[(usize, String)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        index: usize,
+        name: &'a str,
+    }
+    serializer.collect_seq(indexed.iter().map(|(index, name)| Entry {
+        index: *index,
+        name,
+    }))
+}
+
+/// The result as the JSON object the binary emits, for splicing into a larger
+/// report without a round trip through a string. Keys are the action outputs;
+/// optional fields that weren't filled in are left out.
+pub fn to_json_value(result: &AffectedResult) -> serde_json::Value {
+    serde_json::to_value(result).expect("AffectedResult serializes to JSON")
+}
+
+/// Root files that change how every crate builds, applied as force triggers
+/// when [`AffectedOptions::default_force_triggers`] is enabled.
+pub const DEFAULT_FORCE_TRIGGERS: &[&str] = &[
+    "Cargo.lock",
+    "Cargo.toml",
+    "rust-toolchain.toml",
+    ".cargo/config.toml",
+];
+
+/// Builder for the options that control how affected crates are computed.
+///
+/// Start from [`AffectedOptions::new`], chain the options you need and finish
+/// with [`AffectedOptions::compute`]:
+///
+/// ```no_run
+/// # use guppy::{MetadataCommand, graph::PackageGraph};
+/// # use rust_affected::AffectedOptions;
+/// let graph = PackageGraph::from_command(&mut MetadataCommand::new()).unwrap();
+/// let changed = vec!["lib-core/src/lib.rs".to_string()];
+/// let result = AffectedOptions::new()
+///     .force_triggers(["Cargo.lock", ".github/"])
+///     .excluded(["tools/"])
+///     .target("x86_64-unknown-linux-gnu")
+///     .include_dev(false)
+///     .compute(&graph, &changed)?;
+/// # Ok::<(), rust_affected::AffectedError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct AffectedOptions {
+    force_triggers: Vec<String>,
+    default_force_triggers: bool,
+    excluded: MemberPatterns,
+    included: MemberPatterns,
+    target: Option<String>,
+    dependency_kinds: Vec<DependencyKind>,
+    prune_excluded: bool,
+    api_changed: HashMap<String, bool>,
+    source_hashes: bool,
+    codegen_inputs: Vec<(String, Vec<String>)>,
+    scoped_triggers: Vec<(String, Vec<String>)>,
+    max_depth: Option<usize>,
+    force_all_ratio: Option<f64>,
+    ratio_denominator: RatioDenominator,
+    dependencies_of_changed: bool,
+    degrees: bool,
+    indexed: bool,
+    editions: bool,
+    testable_members: bool,
+    split_build_test: bool,
+    lints_change_forces_all: bool,
+    only_default_members: bool,
+    only_publishable: bool,
+    case_insensitive_paths: bool,
+    base_manifests: HashMap<String, String>,
+    workflow_crate_map: Vec<(String, Vec<String>)>,
+    submodule_crate_map: Vec<(String, Vec<String>)>,
+    rebuild_marker: Option<String>,
+    /// `Some` when a changed `Cargo.lock` is refined, holding the base
+    /// lockfile if one is available.
+    base_lockfile: Option<Option<String>>,
+    always_affected: HashSet<String>,
+    published_versions: HashMap<String, String>,
+    select: Option<Selector>,
+}
+
+/// The kind of workspace member a [`Selector`] keeps, or a force trigger
+/// limited to one kind fills in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberKind {
+    /// Members without a binary target (`affected_library_members`).
+    Library,
+    /// Members with a binary target (`affected_binary_members`).
+    Binary,
+}
+
+/// A composite filter on the affected members, e.g. "binaries under
+/// `services/`". Both parts are optional; a member must match every part that
+/// is set. Deserializes from `{"kind": "binary", "path": "services/**"}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Selector {
+    /// Keep only library or only binary members.
+    pub kind: Option<MemberKind>,
+    /// Keep only members whose directory, relative to the workspace root,
+    /// matches this glob. Uses the force-trigger syntax.
+    pub path: Option<String>,
+}
+
+/// Which workspace members the force-all escalation ratio is measured against.
+/// See [`AffectedOptions::force_all_ratio`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RatioDenominator {
+    /// Every workspace member (the default).
+    #[default]
+    All,
+    /// Only the members that aren't excluded.
+    NonExcluded,
+}
+
+impl Default for AffectedOptions {
+    fn default() -> Self {
+        Self {
+            force_triggers: Vec::new(),
+            default_force_triggers: true,
+            excluded: MemberPatterns::default(),
+            included: MemberPatterns::default(),
+            target: None,
+            dependency_kinds: vec![
+                DependencyKind::Normal,
+                DependencyKind::Build,
+                DependencyKind::Development,
+            ],
+            prune_excluded: false,
+            api_changed: HashMap::new(),
+            source_hashes: false,
+            codegen_inputs: Vec::new(),
+            scoped_triggers: Vec::new(),
+            max_depth: None,
+            force_all_ratio: None,
+            ratio_denominator: RatioDenominator::All,
+            dependencies_of_changed: false,
+            degrees: false,
+            indexed: false,
+            editions: false,
+            testable_members: false,
+            split_build_test: false,
+            lints_change_forces_all: false,
+            only_default_members: false,
+            only_publishable: false,
+            case_insensitive_paths: false,
+            base_manifests: HashMap::new(),
+            workflow_crate_map: Vec::new(),
+            submodule_crate_map: Vec::new(),
+            rebuild_marker: None,
+            base_lockfile: None,
+            always_affected: HashSet::new(),
+            published_versions: HashMap::new(),
+            select: None,
+        }
+    }
+}
+
+/// A package's directory relative to the workspace root.
+///
+/// Paths are compared component-wise and rebuilt from their components, so a
+/// workspace root reported with or without a trailing separator gives the same
+/// result, and the result never has one.
+fn relative_dir(pkg: &PackageMetadata) -> PathBuf {
+    let workspace_root = pkg.graph().workspace().root().as_std_path();
+    let dir = pkg
+        .manifest_path()
+        .parent()
+        .expect("manifest has no parent")
+        .as_std_path();
+    strip_root(dir, workspace_root)
+        .unwrap_or_else(|| dir.to_path_buf())
+        .components()
+        .collect()
+}
+
+/// `path` relative to `root`. When `path` isn't below `root` as written, e.g.
+/// because cargo reports the real workspace root but `path` goes through a
+/// symlink to it, both are resolved with [`resolve_symlinks`] and compared
+/// again. `None` when `path` is outside `root` either way.
+fn strip_root(path: &Path, root: &Path) -> Option<PathBuf> {
+    if let Ok(relative) = path.strip_prefix(root) {
+        return Some(relative.to_path_buf());
+    }
+    let path = resolve_symlinks(path)?;
+    let root = resolve_symlinks(root)?;
+    path.strip_prefix(&root).ok().map(Path::to_path_buf)
+}
+
+/// Canonicalize the deepest existing ancestor of `path` and append the rest,
+/// so a deleted file still resolves through a symlinked directory. `None` when
+/// no ancestor exists.
+fn resolve_symlinks(path: &Path) -> Option<PathBuf> {
+    path.ancestors().find_map(|ancestor| {
+        let rest = path.strip_prefix(ancestor).ok()?;
+        Some(ancestor.canonicalize().ok()?.join(rest))
+    })
+}
+
+/// A member's directory relative to the workspace root with symlinks
+/// resolved, when that differs from [`relative_dir`]: a member declared as
+/// `vendor/shim` where `vendor` links to `third_party` also owns the files
+/// git reports below `third_party/shim`.
+fn resolved_relative_dir(pkg: &PackageMetadata) -> Option<PathBuf> {
+    let workspace_root = pkg.graph().workspace().root().as_std_path();
+    let dir = pkg
+        .manifest_path()
+        .parent()
+        .expect("manifest has no parent")
+        .as_std_path();
+    let resolved: PathBuf = resolve_symlinks(dir)?
+        .strip_prefix(resolve_symlinks(workspace_root)?)
+        .ok()?
+        .components()
+        .collect();
+    (resolved != relative_dir(pkg)).then_some(resolved)
+}
+
+/// [`relative_dir`] joined with `/` on every platform, for output.
+fn relative_dir_slashed(pkg: &PackageMetadata) -> String {
+    relative_dir(pkg)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Normalize a changed file path for matching against crate directories.
+///
+/// Windows runners report paths like `lib-core\src\lib.rs`; backslashes are
+/// converted to forward slashes so they match the workspace-relative crate
+/// directories cargo metadata reports. `.` components and repeated slashes are
+/// dropped, so `./lib-core//src/./lib.rs` becomes `lib-core/src/lib.rs`. `..`
+/// components are kept as-is rather than resolved.
+pub fn normalize_changed_file(file: &str) -> String {
+    let file = file.replace('\\', "/");
+    let path = file
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect::<Vec<_>>()
+        .join("/");
+    if file.starts_with('/') {
+        format!("/{path}")
+    } else {
+        path
+    }
+}
+
+/// Normalize a changed file and make it relative to the workspace root.
+///
+/// Absolute paths under the workspace root have the root stripped; absolute
+/// paths elsewhere can't belong to any member and yield `None`.
+fn workspace_relative(graph: &PackageGraph, file: &str) -> Option<String> {
+    let file = normalize_changed_file(file);
+    let root = normalize_changed_file(graph.workspace().root().as_str());
+    match Path::new(&file).strip_prefix(&root) {
+        Ok(relative) => Some(relative.to_string_lossy().into_owned()),
+        Err(_) if Path::new(&file).has_root() => {
+            // The file may reach the workspace through a symlink.
+            let relative = strip_root(Path::new(&file), Path::new(&root))?;
+            Some(normalize_changed_file(&relative.to_string_lossy()))
+        }
+        Err(_) => Some(file),
+    }
+}
+
+/// The workspace members a file belongs to, using the same attribution as
+/// [`AffectedOptions::compute`]. `file` is relative to the workspace root, or
+/// an absolute path below it.
+///
+/// Crate directories are matched component-wise, so `lib-core-ext/src/lib.rs`
+/// does not belong to `lib-core`. When crates are nested (e.g. `plugins/` and
+/// `plugins/foo/`), the file belongs only to the deepest crate containing it,
+/// so the result holds at most one crate.
+pub fn crates_for_file(graph: &PackageGraph, file: &str) -> Vec<PackageId> {
+    CrateDirs::new(graph, false)
+        .owner(file)
+        .into_iter()
+        .collect()
+}
+
+/// Every workspace member's name and directory relative to the workspace root,
+/// sorted by name, without computing anything affected. Directories are the
+/// ones used for attributing files, so the workspace root itself is an empty
+/// path.
+pub fn workspace_members(graph: &PackageGraph) -> Vec<(String, PathBuf)> {
+    let mut members: Vec<(String, PathBuf)> = graph
+        .workspace()
+        .iter()
+        .map(|pkg| (pkg.name().to_string(), relative_dir(&pkg)))
+        .collect();
+    members.sort();
+    members
+}
+
+/// Workspace members keyed by their directory relative to the workspace root,
+/// for attributing files without scanning every member. Directories come from
+/// each member's manifest path, never from its package name, so a crate named
+/// `engine` in `crates/core-impl/` owns the files below `crates/core-impl/`.
+struct CrateDirs<'g> {
+    graph: &'g PackageGraph,
+    dirs: HashMap<PathBuf, PackageId>,
+    /// Whether directories and files are lowercased before lookup.
+    case_insensitive: bool,
+}
+
+impl<'g> CrateDirs<'g> {
+    fn new(graph: &'g PackageGraph, case_insensitive: bool) -> Self {
+        // A member behind a symlink is also keyed by its resolved directory.
+        let dirs = graph
+            .workspace()
+            .iter()
+            .flat_map(|pkg| {
+                std::iter::once(relative_dir(&pkg))
+                    .chain(resolved_relative_dir(&pkg))
+                    .map(move |dir| {
+                        let dir = if case_insensitive {
+                            PathBuf::from(dir.to_string_lossy().to_lowercase())
+                        } else {
+                            dir
+                        };
+                        (dir, pkg.id().clone())
+                    })
+            })
+            .collect();
+        CrateDirs {
+            graph,
+            dirs,
+            case_insensitive,
+        }
+    }
+
+    /// The member owning `file`: the first directory found walking up from the
+    /// file itself, so the deepest of nested crates wins. Costs one lookup per
+    /// path component rather than one comparison per member.
+    fn owner(&self, file: &str) -> Option<PackageId> {
+        let mut file = workspace_relative(self.graph, file)?;
+        if self.case_insensitive {
+            file = file.to_lowercase();
+        }
+        Path::new(&file)
+            .ancestors()
+            .find_map(|dir| self.dirs.get(dir))
+            .cloned()
+    }
+
+    /// Whether `dir`, relative to the workspace root, is a member's directory.
+    fn is_member_dir(&self, dir: &str) -> bool {
+        if self.case_insensitive {
+            self.dirs.contains_key(Path::new(&dir.to_lowercase()))
+        } else {
+            self.dirs.contains_key(Path::new(dir))
+        }
+    }
+}
+
+/// Names of the `packages` that `keep` accepts, sorted.
+fn member_names<'g, F>(packages: &[PackageMetadata<'g>], keep: F) -> Vec<String>
+where
+    F: Fn(&PackageMetadata<'g>) -> bool,
+{
+    let mut names: Vec<String> = packages
+        .iter()
+        .filter(|pkg| keep(pkg))
+        .map(|pkg| pkg.name().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+/// The `[package.metadata]` table that opts a member out of the results:
+/// `[package.metadata.affected] skip = true` excludes it like an entry in
+/// [`AffectedOptions::excluded`].
+pub const SKIP_METADATA_KEY: &str = "affected";
+
+/// Whether the package sets `skip = true` in its [`SKIP_METADATA_KEY`] table.
+fn skipped_by_metadata(pkg: &PackageMetadata) -> bool {
+    pkg.metadata_table()
+        .get(SKIP_METADATA_KEY)
+        .and_then(|table| table.get("skip"))
+        .and_then(|skip| skip.as_bool())
+        == Some(true)
+}
+
+/// The names of the package's binary targets, sorted.
+fn binary_target_names(pkg: &PackageMetadata) -> Vec<String> {
+    let mut names: Vec<String> = pkg
+        .build_targets()
+        .filter_map(|target| match target.id() {
+            guppy::graph::BuildTargetId::Binary(name) => Some(name.to_string()),
+            _ => None,
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Whether `cargo test` runs any of the package's library or test targets,
+/// i.e. one of them doesn't set `test = false`.
+fn has_tests(pkg: &PackageMetadata) -> bool {
+    pkg.build_targets().any(|target| {
+        matches!(
+            target.id(),
+            guppy::graph::BuildTargetId::Library | guppy::graph::BuildTargetId::Test(_)
+        ) && target.test_by_default()
+    })
+}
+
+/// Whether the package's library target is built as a `cdylib` or `staticlib`.
+fn has_ffi_library(pkg: &PackageMetadata) -> bool {
+    pkg.build_targets().any(|target| {
+        matches!(target.id(), guppy::graph::BuildTargetId::Library)
+            && matches!(target.kind(), guppy::graph::BuildTargetKind::LibraryOrExample(crate_types)
+                if crate_types.iter().any(|ty| ty == "cdylib" || ty == "staticlib"))
+    })
 }
 
 /// Check whether a package should be excluded from results.
@@ -22,47 +625,1556 @@ pub struct AffectedResult {
 /// Entries without a `/` are compared against the **crate name** directly
 /// (e.g. `resource-clone` excludes a crate named `resource-clone` regardless of
 /// where it lives in the workspace).
-fn is_excluded(pkg_name: &str, pkg_relative_dir: &Path, excluded: &HashSet<String>) -> bool {
-    for entry in excluded {
-        if entry.contains('/') {
-            // Path-based exclusion
-            let prefix = entry.strip_suffix('/').unwrap_or(entry.as_str());
-            let dir_str = pkg_relative_dir.to_str().unwrap_or("");
-            if dir_str == prefix || dir_str.starts_with(&format!("{prefix}/")) {
-                return true;
-            }
-        } else if pkg_name == entry {
-            return true;
+///
+/// Either kind of entry may be a glob (`*`, `?`, `[...]`, `{a,b}`): `*-generated`
+/// excludes every crate whose name ends in `-generated`, and `tools/*-gen`
+/// excludes every crate below a matching directory. `*` never crosses a `/`.
+/// Entries without glob metacharacters, or that are not valid globs, are
+/// matched literally.
+///
+/// This is the matching behind [`AffectedOptions::excluded`], exposed so other
+/// tools can apply the same rules. It compiles `excluded` on every call, while
+/// [`AffectedOptions`] compiles its lists once.
+pub fn is_excluded(pkg_name: &str, pkg_relative_dir: &Path, excluded: &HashSet<String>) -> bool {
+    MemberPatterns::new(excluded).matches(pkg_name, pkg_relative_dir)
+}
+
+/// Check whether any changed file matches one of the force-trigger patterns.
+///
+/// Patterns are globs (`*`, `**`, `?`); a trailing `/` matches the directory
+/// and everything inside it, and blank patterns are ignored. A pattern starting
+/// with `!` is negative: a file only triggers when it matches a positive
+/// pattern and no negative one, so `docs/** !docs/internal/**` triggers on
+/// docs changes except under `docs/internal`.
+///
+/// A pattern may end in a member kind, `<pattern> => libraries` or
+/// `<pattern> => binaries` (spaces around `=>` are optional), which
+/// [`AffectedOptions::compute`] uses to force only that kind of member; here
+/// the kind is ignored and only the pattern is matched.
+///
+/// Returns [`AffectedError::InvalidGlob`] naming the offending pattern when a
+/// trigger is not a valid glob, and [`AffectedError::InvalidTriggerKind`]
+/// when its kind is unknown.
+pub fn check_force_triggers(
+    changed_files: &[String],
+    force_triggers: &[String],
+) -> Result<bool, AffectedError> {
+    Ok(force_trigger_reason(changed_files, force_triggers)?.is_some())
+}
+
+/// Name the first force trigger that matches a changed file, together with
+/// that file, as `<pattern> (matched <file>)`; `None` if no trigger matches.
+///
+/// Files are checked in order, and for each file the patterns in order, with
+/// the same rules and errors as [`check_force_triggers`].
+pub fn force_trigger_reason(
+    changed_files: &[String],
+    force_triggers: &[String],
+) -> Result<Option<String>, AffectedError> {
+    let triggers = TriggerSet::new(force_triggers)?;
+    Ok(changed_files
+        .iter()
+        .find_map(|f| Some(format!("{} (matched {f})", triggers.matching(f)?))))
+}
+
+/// Force triggers compiled once, for matching many files.
+struct TriggerSet {
+    /// The positive triggers as given, indexed like `positive_set`.
+    positive: Vec<String>,
+    positive_set: GlobSet,
+    negative_set: GlobSet,
+}
+
+impl TriggerSet {
+    fn new(force_triggers: &[String]) -> Result<Self, AffectedError> {
+        // Blank patterns are dropped up front so that glob set indices line up.
+        let (negative, positive): (Vec<String>, Vec<String>) = force_triggers
+            .iter()
+            .filter(|trigger| !trigger.trim().is_empty())
+            .cloned()
+            .partition(|trigger| trigger.starts_with('!'));
+        let negative: Vec<String> = negative
+            .iter()
+            .map(|t| Ok(trigger_kind(&t[1..])?.0.to_string()))
+            .collect::<Result<_, AffectedError>>()?;
+        let patterns: Vec<String> = positive
+            .iter()
+            .map(|t| Ok(trigger_kind(t)?.0.to_string()))
+            .collect::<Result<_, AffectedError>>()?;
+        Ok(TriggerSet {
+            positive_set: build_glob_set(&patterns)?,
+            negative_set: build_glob_set(&negative)?,
+            positive,
+        })
+    }
+
+    /// The first positive trigger matching `file`, unless a negative one
+    /// matches it too.
+    fn matching(&self, file: &str) -> Option<&str> {
+        if self.negative_set.is_match(file) {
+            return None;
         }
+        let first = *self.positive_set.matches(file).first()?;
+        Some(&self.positive[first])
     }
-    false
 }
 
-pub fn check_force_triggers(changed_files: &[String], force_triggers: &[String]) -> bool {
-    if force_triggers.is_empty() {
-        return false;
-    }
+/// Split a force trigger into its pattern and the kind of member its
+/// `=> libraries` or `=> binaries` suffix limits it to; `None` for a trigger
+/// without one, which forces every member.
+fn trigger_kind(trigger: &str) -> Result<(&str, Option<MemberKind>), AffectedError> {
+    let Some((pattern, kind)) = trigger.split_once("=>") else {
+        return Ok((trigger, None));
+    };
+    let kind = match kind.trim() {
+        "libraries" | "library" => MemberKind::Library,
+        "binaries" | "binary" => MemberKind::Binary,
+        _ => {
+            return Err(AffectedError::InvalidTriggerKind {
+                trigger: trigger.to_string(),
+            });
+        }
+    };
+    Ok((pattern.trim(), Some(kind)))
+}
 
+/// Compile trigger-style patterns into a glob set. A trailing `/` matches the
+/// directory and everything inside it; blank entries are skipped.
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, AffectedError> {
     let mut builder = GlobSetBuilder::new();
-    for trigger in force_triggers {
+    // Blank entries (e.g. from a trailing newline in YAML) are not patterns.
+    for trigger in patterns.iter().filter(|t| !t.trim().is_empty()) {
         let pattern = if trigger.ends_with('/') {
             format!("{}**", trigger)
         } else {
             trigger.clone()
         };
-        builder.add(
-            Glob::new(&pattern)
-                .unwrap_or_else(|e| panic!("Invalid force_trigger glob pattern {pattern:?}: {e}")),
-        );
+        let glob = Glob::new(&pattern).map_err(|source| AffectedError::InvalidGlob {
+            pattern: trigger.clone(),
+            source,
+        })?;
+        builder.add(glob);
     }
-    let globset = builder
+    builder
         .build()
-        .expect("Failed to build force_triggers glob set");
-    changed_files.iter().any(|f| globset.is_match(f))
+        .map_err(|source| AffectedError::InvalidGlob {
+            pattern: patterns.join(" "),
+            source,
+        })
+}
+
+/// Check whether the reverse traversal should follow a dependency edge.
+///
+/// With a `platform`, an edge is followed when at least one of its considered
+/// dependency kinds is active on that platform. Edges whose status can't be
+/// determined (e.g. gated on target features) are treated as active, so an
+/// uncertain edge never hides a dependent. Only the requirements of the given
+/// dependency `kinds` are considered.
+fn follow_link(
+    link: &PackageLink,
+    platform: Option<&PlatformSpec>,
+    kinds: &[DependencyKind],
+) -> bool {
+    kinds.iter().any(|kind| match platform {
+        Some(platform) => !matches!(
+            link.req_for_kind(*kind).status().enabled_on(platform),
+            EnabledTernary::Disabled
+        ),
+        None => link.req_for_kind(*kind).is_present(),
+    })
+}
+
+impl AffectedOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Glob patterns that mark the entire workspace affected when any changed
+    /// file matches. See [`check_force_triggers`] for the pattern syntax.
+    pub fn force_triggers<I, S>(mut self, triggers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.force_triggers = triggers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether [`DEFAULT_FORCE_TRIGGERS`] apply in addition to
+    /// [`AffectedOptions::force_triggers`] (default `true`, like the binary's
+    /// `DEFAULT_FORCE_TRIGGERS`), so that the lockfile, root manifest and
+    /// toolchain files force a full rebuild without being listed. A negative
+    /// force trigger such as `!Cargo.lock` still vetoes them.
+    pub fn default_force_triggers(mut self, default_force_triggers: bool) -> Self {
+        self.default_force_triggers = default_force_triggers;
+        self
+    }
+
+    /// Crate names or path prefixes to filter from all three output lists
+    /// (changed_crates, affected_library_members, affected_binary_members).
+    ///
+    /// Entries that contain `/` are matched as path prefixes against each
+    /// package's directory relative to the workspace root (e.g. `tools/` excludes
+    /// every crate under `tools/`). Entries without `/` are matched against the
+    /// crate name directly.
+    ///
+    /// Members that opt out in their own manifest with
+    /// `[package.metadata.affected] skip = true` are excluded as well.
+    pub fn excluded<I, S>(mut self, excluded: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.excluded = MemberPatterns::new(excluded.into_iter().map(Into::into));
+        self
+    }
+
+    /// Add exclusions read line by line from `reader`, e.g. a generated file
+    /// with tens of thousands of entries. Each line holds whitespace-separated
+    /// entries in the [`AffectedOptions::excluded`] syntax; blank lines and
+    /// lines starting with `#` are skipped. Entries are added to the existing
+    /// exclusions one at a time, so the file is never held in memory whole,
+    /// and glob entries are compiled once at the end.
+    pub fn excluded_from_reader<R: BufRead>(mut self, reader: R) -> io::Result<Self> {
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            for entry in line.split_whitespace() {
+                self.excluded.insert(entry);
+            }
+        }
+        self.excluded.compile();
+        Ok(self)
+    }
+
+    /// Crate names or path prefixes to restrict all three output lists to.
+    ///
+    /// When non-empty, only crates matching an entry are reported, using the
+    /// same matching rules as [`AffectedOptions::excluded`]. Exclusions apply
+    /// on top, so a crate that is both included and excluded is dropped.
+    /// Like exclusions, inclusions don't affect the graph traversal.
+    pub fn included<I, S>(mut self, included: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.included = MemberPatterns::new(included.into_iter().map(Into::into));
+        self
+    }
+
+    /// Restrict the reverse traversal to dependency edges that apply to the given
+    /// target triple (e.g. `x86_64-unknown-linux-gnu`), so dependencies declared
+    /// under `[target.'cfg(...)'.dependencies]` are only followed when they match.
+    /// Without a target, every edge is followed regardless of platform.
+    pub fn target(mut self, triple: impl Into<String>) -> Self {
+        self.target = Some(triple.into());
+        self
+    }
+
+    /// Whether `[dev-dependencies]` edges are followed (default `true`). When
+    /// `false`, a crate that only dev-depends on a changed crate is not affected.
+    pub fn include_dev(mut self, include_dev: bool) -> Self {
+        self.dependency_kinds
+            .retain(|kind| *kind != DependencyKind::Development);
+        if include_dev {
+            self.dependency_kinds.push(DependencyKind::Development);
+        }
+        self
+    }
+
+    /// The kinds of dependency edges the reverse traversal follows (default:
+    /// all of them). With only [`DependencyKind::Normal`], a crate that uses a
+    /// changed crate only as a build- or dev-dependency is not affected, which
+    /// gives a minimal "what ships" view. Replaces the setting of
+    /// [`AffectedOptions::include_dev`].
+    pub fn dependency_kinds<I>(mut self, kinds: I) -> Self
+    where
+        I: IntoIterator<Item = DependencyKind>,
+    {
+        self.dependency_kinds.clear();
+        for kind in kinds {
+            if !self.dependency_kinds.contains(&kind) {
+                self.dependency_kinds.push(kind);
+            }
+        }
+        self
+    }
+
+    /// Whether excluded crates cut the graph (default `false`).
+    ///
+    /// By default an excluded crate is still traversed when resolving transitive
+    /// dependents — it simply won't appear in the results. When pruning, excluded
+    /// crates are neither a starting point of the reverse traversal nor traversed
+    /// through, so dependents only reachable via an excluded crate are not affected.
+    pub fn prune_excluded(mut self, prune_excluded: bool) -> Self {
+        self.prune_excluded = prune_excluded;
+        self
+    }
+
+    /// Whether each changed crate's public API changed, keyed by crate name
+    /// (e.g. from comparing rustdoc JSON before and after).
+    ///
+    /// A changed crate flagged `false` is still affected itself, but its change
+    /// doesn't propagate to its dependents. Crates missing from the map are
+    /// assumed to have changed their API.
+    pub fn api_changed<I, S>(mut self, api_changed: I) -> Self
+    where
+        I: IntoIterator<Item = (S, bool)>,
+        S: Into<String>,
+    {
+        self.api_changed = api_changed
+            .into_iter()
+            .map(|(name, changed)| (name.into(), changed))
+            .collect();
+        self
+    }
+
+    /// Whether to hash the source tree of every affected member into
+    /// `source_hashes` (default `false`). See [`source_hash`] for what is hashed;
+    /// this reads every file of every affected crate, so it's opt-in.
+    pub fn source_hashes(mut self, source_hashes: bool) -> Self {
+        self.source_hashes = source_hashes;
+        self
+    }
+
+    /// The platform dependency edges are evaluated against, if a target is set.
+    fn platform(&self) -> Result<Option<PlatformSpec>, AffectedError> {
+        let Some(triple) = self.target.as_deref() else {
+            return Ok(None);
+        };
+        let platform = Platform::new(triple.to_owned(), TargetFeatures::Unknown).map_err(|e| {
+            AffectedError::InvalidTarget {
+                triple: triple.to_string(),
+                message: e.to_string(),
+            }
+        })?;
+        Ok(Some(PlatformSpec::Platform(Arc::new(platform))))
+    }
+
+    /// The mapped workflow path of a `workflow_crate_map` key.
+    fn workflow_path(workflow: &str) -> String {
+        if workflow.contains('/') {
+            workflow.to_string()
+        } else {
+            format!(".github/workflows/{workflow}")
+        }
+    }
+
+    /// Whether a changed file is a workflow with an entry in the workflow map.
+    fn is_mapped_workflow(&self, file: &str) -> bool {
+        self.workflow_crate_map
+            .iter()
+            .any(|(workflow, _)| Self::workflow_path(workflow) == file)
+    }
+
+    /// Workspace members containing at least one of the changed files, plus the
+    /// members mapped to a changed codegen input, workflow or submodule and
+    /// those holding a rebuild marker.
+    fn direct_ids(
+        &self,
+        graph: &PackageGraph,
+        changed_files: &[String],
+    ) -> Result<Vec<PackageId>, AffectedError> {
+        let mut codegen_crates = Vec::new();
+        for (glob, crates) in &self.codegen_inputs {
+            let globset = build_glob_set(std::slice::from_ref(glob))?;
+            if changed_files.iter().any(|f| globset.is_match(f)) {
+                codegen_crates.extend(crates);
+            }
+        }
+        let workflow_crates = self
+            .workflow_crate_map
+            .iter()
+            .filter(|(workflow, _)| changed_files.contains(&Self::workflow_path(workflow)))
+            .flat_map(|(_, crates)| crates);
+        let submodule_crates = self
+            .submodule_crate_map
+            .iter()
+            .filter(|(submodule, _)| {
+                let submodule = normalize_changed_file(submodule);
+                changed_files
+                    .iter()
+                    .any(|f| Path::new(f).starts_with(&submodule))
+            })
+            .flat_map(|(_, crates)| crates);
+        let seeded_crates: HashSet<&str> = codegen_crates
+            .into_iter()
+            .chain(workflow_crates)
+            .chain(submodule_crates)
+            .map(String::as_str)
+            .collect();
+
+        let crate_dirs = CrateDirs::new(graph, self.case_insensitive_paths);
+        let owning_ids: HashSet<PackageId> = changed_files
+            .iter()
+            .filter_map(|f| crate_dirs.owner(f))
+            .collect();
+
+        Ok(graph
+            .workspace()
+            .iter()
+            .filter(|pkg| {
+                seeded_crates.contains(pkg.name())
+                    || owning_ids.contains(pkg.id())
+                    || self.has_rebuild_marker(pkg)
+            })
+            .map(|pkg| pkg.id().clone())
+            .collect())
+    }
+
+    /// Whether the member's directory contains the configured rebuild marker.
+    fn has_rebuild_marker(&self, pkg: &PackageMetadata) -> bool {
+        self.rebuild_marker.as_ref().is_some_and(|marker| {
+            pkg.manifest_path()
+                .parent()
+                .is_some_and(|dir| dir.join(marker).exists())
+        })
+    }
+
+    /// Whether a changed file is a manifest that only changed in ways that
+    /// don't affect the build, judged against its base manifest.
+    fn is_ignorable_manifest_change(&self, graph: &PackageGraph, file: &str) -> bool {
+        let Some(base) = self.base_manifests.get(file) else {
+            return false;
+        };
+        std::fs::read_to_string(graph.workspace().root().join(file))
+            .is_ok_and(|current| manifest::only_ignorable_changes(base, &current))
+    }
+
+    /// Names of the crates whose manifest is among the changed files but was
+    /// deleted along with the crate, sorted and without excluded ones.
+    fn removed_crates(&self, graph: &PackageGraph, changed_files: &[String]) -> Vec<String> {
+        let root = graph.workspace().root();
+        let crate_dirs = CrateDirs::new(graph, self.case_insensitive_paths);
+        let mut removed: Vec<String> = changed_files
+            .iter()
+            .filter_map(|file| Some((file, file.strip_suffix("/Cargo.toml")?)))
+            .filter(|(file, dir)| !root.join(file).exists() && !crate_dirs.is_member_dir(dir))
+            .map(|(file, dir)| {
+                let name = self
+                    .base_manifests
+                    .get(file.as_str())
+                    .and_then(|base| manifest::package_name(base))
+                    .unwrap_or_else(|| dir.rsplit('/').next().unwrap_or(dir).to_string());
+                (name, dir)
+            })
+            .filter(|(name, dir)| !self.excluded.matches(name, Path::new(dir)))
+            .map(|(name, _)| name)
+            .collect();
+        removed.sort();
+        removed.dedup();
+        removed
+    }
+
+    /// Packages whose entry in the workspace's `Cargo.lock` differs from the
+    /// base lockfile, or `None` if either lockfile is missing or unreadable.
+    fn lockfile_ids(&self, graph: &PackageGraph) -> Option<Vec<PackageId>> {
+        let base = self.base_lockfile.as_ref()?.as_deref()?;
+        let lockfile = graph.workspace().root().join("Cargo.lock");
+        let current = std::fs::read_to_string(lockfile).ok()?;
+        let changed = lockfile::changed_packages(base, &current)?;
+        Some(
+            graph
+                .packages()
+                .filter(|pkg| {
+                    changed.contains(&(pkg.name().to_string(), pkg.version().to_string()))
+                })
+                .map(|pkg| pkg.id().clone())
+                .collect(),
+        )
+    }
+
+    /// Check the options for everything [`AffectedOptions::compute`] would
+    /// reject, without a package graph: the force triggers and their kinds,
+    /// the scoped trigger, codegen input and [`Selector::path`] globs, and the
+    /// target triple.
+    pub fn validate(&self) -> Result<(), AffectedError> {
+        force_trigger_reason(&[], &self.force_triggers)?;
+        let select_path = self.select.as_ref().and_then(|select| select.path.as_ref());
+        for glob in self
+            .scoped_triggers
+            .iter()
+            .chain(&self.codegen_inputs)
+            .map(|(glob, _)| glob)
+            .chain(select_path)
+        {
+            build_glob_set(std::slice::from_ref(glob))?;
+        }
+        self.platform()?;
+        Ok(())
+    }
+
+    /// Members inheriting a `[workspace.dependencies]` entry that differs from
+    /// the base root manifest, through an edge of a followed kind, or `None`
+    /// if the root manifest changed in another way that matters or can't be
+    /// read.
+    fn workspace_dependency_ids(&self, graph: &PackageGraph) -> Option<Vec<PackageId>> {
+        let base = self.base_manifests.get("Cargo.toml")?;
+        let root = graph.workspace().root().join("Cargo.toml");
+        let current = std::fs::read_to_string(root).ok()?;
+        let keys = manifest::changed_workspace_dependencies(base, &current)?;
+        Some(
+            graph
+                .workspace()
+                .iter()
+                .filter(|pkg| {
+                    std::fs::read_to_string(pkg.manifest_path()).is_ok_and(|manifest| {
+                        manifest::inherits_any(&manifest, &keys, &self.dependency_kinds)
+                    })
+                })
+                .map(|pkg| pkg.id().clone())
+                .collect(),
+        )
+    }
+
+    /// Resolve the given changed crates and every crate that transitively
+    /// depends on them, honouring pruning, the public API gate, the target
+    /// platform and dev-dependency settings.
+    fn dependents<'g>(
+        &self,
+        graph: &'g PackageGraph,
+        platform: Option<&PlatformSpec>,
+        direct_ids: &[PackageId],
+    ) -> PackageSet<'g> {
+        let pruned = |pkg: &PackageMetadata| self.prune_excluded && self.excludes(pkg);
+        let start_ids: Vec<_> = direct_ids
+            .iter()
+            .filter(|id| graph.metadata(id).is_ok_and(|pkg| !pruned(&pkg)))
+            .collect();
+        // Crates whose public API didn't change only affect themselves.
+        let (contained_ids, propagating_ids): (Vec<_>, Vec<_>) =
+            start_ids.into_iter().partition(|id| {
+                graph
+                    .metadata(id)
+                    .is_ok_and(|pkg| self.api_changed.get(pkg.name()) == Some(&false))
+            });
+        // In the reverse direction, `link.from()` is the dependent being reached.
+        let follow = |link: &PackageLink| {
+            !pruned(&link.from()) && follow_link(link, platform, &self.dependency_kinds)
+        };
+        let reached = match self.max_depth {
+            None => graph
+                .query_reverse(propagating_ids)
+                .expect("reverse query failed")
+                .resolve_with_fn(|_, link| follow(&link)),
+            Some(max_depth) => {
+                // Breadth-first, one hop of dependents per level.
+                let mut seen: HashSet<&PackageId> = propagating_ids.iter().copied().collect();
+                let mut frontier = propagating_ids;
+                for _ in 0..max_depth {
+                    let mut next = Vec::new();
+                    for id in frontier {
+                        let pkg = graph.metadata(id).expect("package is in the graph");
+                        for link in pkg.reverse_direct_links() {
+                            if follow(&link) && seen.insert(link.from().id()) {
+                                next.push(link.from().id());
+                            }
+                        }
+                    }
+                    if next.is_empty() {
+                        break;
+                    }
+                    frontier = next;
+                }
+                graph.resolve_ids(seen).expect("resolve ids failed")
+            }
+        };
+        reached.union(
+            &graph
+                .resolve_ids(contained_ids)
+                .expect("resolve ids failed"),
+        )
+    }
+
+    /// Count, for each directly changed crate, how many other workspace crates
+    /// depend on it transitively — its blast radius.
+    ///
+    /// Each crate is traversed on its own rather than unioned with the other
+    /// changes. Excluded crates neither get an entry nor count towards one, and
+    /// the traversal honours the same pruning, API gate, target and
+    /// dev-dependency options as [`AffectedOptions::compute`], and returns its
+    /// errors. Force triggers are ignored.
+    pub fn reverse_dependent_counts(
+        &self,
+        graph: &PackageGraph,
+        changed_files: &[String],
+    ) -> Result<BTreeMap<String, usize>, AffectedError> {
+        let changed_files: Vec<String> = changed_files
+            .iter()
+            .filter_map(|f| workspace_relative(graph, f))
+            .collect();
+        let platform = self.platform()?;
+        let workspace = graph.workspace();
+        Ok(self
+            .direct_ids(graph, &changed_files)?
+            .into_iter()
+            .filter_map(|id| graph.metadata(&id).ok())
+            .filter(|pkg| !self.excludes(pkg))
+            .map(|pkg| {
+                let count = self
+                    .dependents(graph, platform.as_ref(), &[pkg.id().clone()])
+                    .packages(DependencyDirection::Forward)
+                    .filter(|dependent| {
+                        dependent.id() != pkg.id()
+                            && workspace.contains_name(dependent.name())
+                            && !self.excludes(dependent)
+                    })
+                    .count();
+                (pkg.name().to_string(), count)
+            })
+            .collect())
+    }
+
+    /// Map GitHub Actions workflow files to the crates they build, so a change to
+    /// `ci-app-alpha.yml` seeds `app-alpha` instead of forcing a full rebuild.
+    ///
+    /// Keys without a `/` are file names under `.github/workflows/`; other keys
+    /// are paths relative to the workspace root. A changed mapped workflow marks
+    /// its crates as changed and is ignored by the force triggers. Changes to
+    /// unmapped workflows still go through the force triggers as usual, so keep
+    /// e.g. `.github/` as a trigger for the safe fall-back.
+    pub fn workflow_crate_map<I, W, C, S>(mut self, workflow_crate_map: I) -> Self
+    where
+        I: IntoIterator<Item = (W, C)>,
+        W: Into<String>,
+        C: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.workflow_crate_map = workflow_crate_map
+            .into_iter()
+            .map(|(workflow, crates)| {
+                (
+                    workflow.into(),
+                    crates.into_iter().map(Into::into).collect(),
+                )
+            })
+            .collect();
+        self
+    }
+
+    /// Crates that are affected whenever any file changed, whether or not the
+    /// change reaches them through the dependency graph.
+    ///
+    /// Exclusion wins: a crate that is also matched by
+    /// [`AffectedOptions::excluded`] is left out of the results. Use
+    /// [`AffectedOptions::always_affected_conflicts`] to report such crates.
+    pub fn always_affected<I, S>(mut self, always_affected: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.always_affected = always_affected.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Always-affected crates that are also excluded, sorted by name. These are
+    /// dropped from the results, which usually means the configuration
+    /// contradicts itself.
+    pub fn always_affected_conflicts(&self, graph: &PackageGraph) -> Vec<String> {
+        let mut conflicts: Vec<String> = graph
+            .workspace()
+            .iter()
+            .filter(|pkg| self.always_affected.contains(pkg.name()) && self.excludes(pkg))
+            .map(|pkg| pkg.name().to_string())
+            .collect();
+        conflicts.sort();
+        conflicts
+    }
+
+    /// Whether `pkg` is excluded, by the exclusion list or by its own
+    /// [`SKIP_METADATA_KEY`].
+    fn excludes(&self, pkg: &PackageMetadata) -> bool {
+        self.excluded.matches(pkg.name(), &relative_dir(pkg)) || skipped_by_metadata(pkg)
+    }
+
+    /// Workspace members matched by the exclusion list or skipped through
+    /// their metadata, sorted by name.
+    pub fn excluded_members(&self, graph: &PackageGraph) -> Vec<String> {
+        let mut excluded: Vec<String> = graph
+            .workspace()
+            .iter()
+            .filter(|pkg| self.excludes(pkg))
+            .map(|pkg| pkg.name().to_string())
+            .collect();
+        excluded.sort();
+        excluded
+    }
+
+    /// The changed files that [`AffectedOptions::compute`] attributes to
+    /// nothing: they belong to no member, match no force trigger (including
+    /// the defaults when enabled), scoped trigger or codegen input, are no
+    /// mapped workflow, lie in no mapped submodule, and aren't a root
+    /// `Cargo.toml` or `Cargo.lock` that is diffed against its base. Such a
+    /// file usually means a typo or a wrong prefix in the changed-file list.
+    ///
+    /// Returns the same errors as [`AffectedOptions::validate`] for invalid
+    /// patterns.
+    pub fn unmatched_files(
+        &self,
+        graph: &PackageGraph,
+        changed_files: &[String],
+    ) -> Result<Vec<String>, AffectedError> {
+        let mut triggers = self.force_triggers.clone();
+        if self.default_force_triggers {
+            triggers.extend(DEFAULT_FORCE_TRIGGERS.iter().map(|t| t.to_string()));
+        }
+        let triggers = TriggerSet::new(&triggers)?;
+        let globs = |map: &[(String, Vec<String>)]| {
+            build_glob_set(&map.iter().map(|(glob, _)| glob.clone()).collect::<Vec<_>>())
+        };
+        let scoped = globs(&self.scoped_triggers)?;
+        let codegen = globs(&self.codegen_inputs)?;
+        let submodules: Vec<String> = self
+            .submodule_crate_map
+            .iter()
+            .map(|(submodule, _)| normalize_changed_file(submodule))
+            .collect();
+        let crate_dirs = CrateDirs::new(graph, self.case_insensitive_paths);
+
+        Ok(changed_files
+            .iter()
+            .filter(|file| {
+                // Files outside the workspace root are ignored by compute.
+                let Some(path) = workspace_relative(graph, file) else {
+                    return true;
+                };
+                let refined = match path.as_str() {
+                    "Cargo.lock" => self.base_lockfile.is_some(),
+                    "Cargo.toml" => {
+                        self.lints_change_forces_all
+                            || self.base_manifests.contains_key("Cargo.toml")
+                    }
+                    _ => false,
+                };
+                !refined
+                    && crate_dirs.owner(&path).is_none()
+                    && triggers.matching(&path).is_none()
+                    && !scoped.is_match(&path)
+                    && !codegen.is_match(&path)
+                    && !self.is_mapped_workflow(&path)
+                    && !submodules
+                        .iter()
+                        .any(|submodule| Path::new(&path).starts_with(submodule))
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// The member each of `files` belongs to, in order, or `None`, using the
+    /// attribution of [`AffectedOptions::compute`] (including
+    /// [`AffectedOptions::case_insensitive_paths`]). The member directories are
+    /// indexed once for all files, unlike calling [`crates_for_file`] per file.
+    pub fn file_owners(&self, graph: &PackageGraph, files: &[String]) -> Vec<Option<PackageId>> {
+        let crate_dirs = CrateDirs::new(graph, self.case_insensitive_paths);
+        files.iter().map(|file| crate_dirs.owner(file)).collect()
+    }
+
+    /// The last published version of each crate, e.g. `{"lib-core": "0.1.0"}`.
+    ///
+    /// Affected members whose current version equals their published version
+    /// are reported in [`AffectedResult::needs_version_bump`]. Crates that
+    /// aren't listed are never flagged.
+    pub fn published_versions<I, C, V>(mut self, published_versions: I) -> Self
+    where
+        I: IntoIterator<Item = (C, V)>,
+        C: Into<String>,
+        V: Into<String>,
+    {
+        self.published_versions = published_versions
+            .into_iter()
+            .map(|(name, version)| (name.into(), version.into()))
+            .collect();
+        self
+    }
+
+    /// Narrow `affected_library_members` and `affected_binary_members` (and the
+    /// lists derived from them) to the members matching `selector`.
+    /// `changed_crates` is not filtered.
+    pub fn select(mut self, selector: Selector) -> Self {
+        self.select = Some(selector);
+        self
+    }
+
+    /// Map git submodule paths to the crates that live in or depend on them.
+    ///
+    /// A submodule bump shows up in the diff as the submodule path itself (its
+    /// commit pointer changed) rather than as individual files, so it can't be
+    /// attributed to a crate directory. A changed path equal to, or inside, a
+    /// mapped submodule marks the mapped crates as changed.
+    pub fn submodule_crate_map<I, P, C, S>(mut self, submodule_crate_map: I) -> Self
+    where
+        I: IntoIterator<Item = (P, C)>,
+        P: Into<String>,
+        C: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.submodule_crate_map = submodule_crate_map
+            .into_iter()
+            .map(|(submodule, crates)| {
+                (
+                    submodule.into(),
+                    crates.into_iter().map(Into::into).collect(),
+                )
+            })
+            .collect();
+        self
+    }
+
+    /// Cap how many reverse-dependency hops are followed from the changed crates.
+    ///
+    /// At depth 0 only the directly changed crates are affected, at depth 1 also
+    /// their immediate dependents, and so on. Unlimited by default; ignored when
+    /// a force trigger marks the whole workspace affected.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Escalate to `force_all` when at least this fraction (`0.0`–`1.0`) of the
+    /// workspace is affected anyway, e.g. `0.8` for 80%.
+    ///
+    /// The ratio is the number of affected (non-excluded) members over the
+    /// members chosen by [`AffectedOptions::ratio_denominator`]. Disabled by
+    /// default.
+    pub fn force_all_ratio(mut self, ratio: f64) -> Self {
+        self.force_all_ratio = Some(ratio);
+        self
+    }
+
+    /// Which members the force-all ratio is measured against (default
+    /// [`RatioDenominator::All`]). Use [`RatioDenominator::NonExcluded`] so
+    /// that large excluded subtrees don't dilute the ratio.
+    pub fn ratio_denominator(mut self, denominator: RatioDenominator) -> Self {
+        self.ratio_denominator = denominator;
+        self
+    }
+
+    /// The fraction of the workspace a result affects, as used for the
+    /// force-all escalation: affected library and binary members over the
+    /// members chosen by [`AffectedOptions::ratio_denominator`].
+    pub fn affected_ratio(&self, graph: &PackageGraph, result: &AffectedResult) -> f64 {
+        let affected = result.affected_library_members.len() + result.affected_binary_members.len();
+        self.ratio(graph, affected)
+    }
+
+    fn ratio(&self, graph: &PackageGraph, affected: usize) -> f64 {
+        let workspace = graph.workspace();
+        let total = match self.ratio_denominator {
+            RatioDenominator::All => workspace.member_count(),
+            RatioDenominator::NonExcluded => {
+                workspace.iter().filter(|pkg| !self.excludes(pkg)).count()
+            }
+        };
+        if total == 0 {
+            0.0
+        } else {
+            affected as f64 / total as f64
+        }
+    }
+
+    /// Whether to also resolve the crates the changed crates depend on into
+    /// `dependencies_of_changed` (default `false`) — the forward counterpart of
+    /// the reverse traversal behind the `affected_*` lists.
+    ///
+    /// The target platform and dev-dependency options apply; dev-dependencies
+    /// are only followed from the changed crates themselves, as cargo never
+    /// builds the dev-dependencies of a dependency.
+    pub fn dependencies_of_changed(mut self, dependencies_of_changed: bool) -> Self {
+        self.dependencies_of_changed = dependencies_of_changed;
+        self
+    }
+
+    /// Whether to annotate every affected member with its direct in/out degree
+    /// among workspace members into `degrees` (default `false`). Every
+    /// dependency kind counts; a crate with a high in-degree is riskier to change.
+    pub fn degrees(mut self, degrees: bool) -> Self {
+        self.degrees = degrees;
+        self
+    }
+
+    /// Whether to number the affected members into `indexed` (default `false`).
+    pub fn indexed(mut self, indexed: bool) -> Self {
+        self.indexed = indexed;
+        self
+    }
+
+    /// Whether to report the Rust edition of every affected member in
+    /// `editions` (default `false`).
+    pub fn editions(mut self, editions: bool) -> Self {
+        self.editions = editions;
+        self
+    }
+
+    /// Whether to report `testable_members`: the affected binaries plus the
+    /// directly changed crates whose library or test targets have tests
+    /// (default `false`).
+    pub fn testable_members(mut self, testable_members: bool) -> Self {
+        self.testable_members = testable_members;
+        self
+    }
+
+    /// Whether to also compute `affected_build` and `affected_test` in the same
+    /// pass (default `false`).
+    ///
+    /// `affected_build` follows only normal and build dependency edges, so a
+    /// crate that merely dev-depends on a change is left out; `affected_test`
+    /// follows every edge. Both honour the remaining options, and both list
+    /// libraries and binaries together. [`AffectedOptions::include_dev`] and
+    /// [`AffectedOptions::dependency_kinds`] only apply to the main output
+    /// lists.
+    pub fn split_build_test(mut self, split_build_test: bool) -> Self {
+        self.split_build_test = split_build_test;
+        self
+    }
+
+    /// Treat every member whose directory contains a file named `marker`
+    /// (e.g. `.rebuild`) as directly changed, whether or not any of its files
+    /// changed. Lets developers force a crate's rebuild by committing the
+    /// marker. Off by default.
+    pub fn rebuild_marker(mut self, marker: impl Into<String>) -> Self {
+        self.rebuild_marker = Some(marker.into());
+        self
+    }
+
+    /// Narrow a changed `Cargo.lock` down to the members whose resolved
+    /// dependencies changed, given the lockfile's contents before the change.
+    ///
+    /// A package whose version, source or dependency list differs from `base`
+    /// counts as changed, and the members depending on it, directly or
+    /// transitively, are affected. `Cargo.lock` is then no longer matched
+    /// against the force triggers. With `None`, e.g. when the base ref has no
+    /// lockfile, or when either lockfile can't be parsed, a changed
+    /// `Cargo.lock` marks the whole workspace affected.
+    pub fn base_lockfile(mut self, base: Option<String>) -> Self {
+        self.base_lockfile = Some(base);
+        self
+    }
+
+    /// The contents of changed `Cargo.toml` files before the change, keyed by
+    /// their path relative to the workspace root.
+    ///
+    /// A changed manifest whose base is given and which differs from it only
+    /// in formatting, comments or `[package.metadata]` doesn't mark its crate
+    /// changed. It is still matched against the force triggers. Manifests
+    /// without a base, e.g. new ones, always count as changed.
+    ///
+    /// A changed root manifest with a base is diffed instead of matched
+    /// against the force triggers: when only `[workspace.dependencies]`
+    /// entries changed, the members inheriting them (`workspace = true`) and
+    /// their dependents are affected, without counting as changed crates. Any
+    /// other change to it, apart from formatting, comments and
+    /// `[workspace.metadata]`, marks the whole workspace affected.
+    pub fn base_manifests<I, P, C>(mut self, base_manifests: I) -> Self
+    where
+        I: IntoIterator<Item = (P, C)>,
+        P: AsRef<str>,
+        C: Into<String>,
+    {
+        self.base_manifests = base_manifests
+            .into_iter()
+            .map(|(path, contents)| (normalize_changed_file(path.as_ref()), contents.into()))
+            .collect();
+        self
+    }
+
+    /// Whether a change to the root `Cargo.toml` forces a full rebuild
+    /// (default `false`). The default force triggers already do so, so this
+    /// only matters with [`AffectedOptions::default_force_triggers`] off.
+    ///
+    /// Members that inherit `[workspace.lints]` compile differently when that
+    /// table changes, but the root manifest lies outside every member. Without
+    /// the base manifest to diff against, this conservatively treats any root
+    /// manifest change as a lints change.
+    pub fn lints_change_forces_all(mut self, lints_change_forces_all: bool) -> Self {
+        self.lints_change_forces_all = lints_change_forces_all;
+        self
+    }
+
+    /// Whether to restrict all three output lists to the workspace's
+    /// `default-members` (default `false`), the crates `cargo build` builds
+    /// without `--workspace`. Like exclusions, this doesn't affect the graph
+    /// traversal. Without a `default-members` list every member is a default
+    /// member; when `cargo metadata` predates the field, nothing is filtered.
+    pub fn only_default_members(mut self, only_default_members: bool) -> Self {
+        self.only_default_members = only_default_members;
+        self
+    }
+
+    /// Whether to drop members that can't be published (`publish = false`)
+    /// from `affected_library_members` and `affected_binary_members` (default
+    /// `false`), e.g. for release pipelines. A member restricted to specific
+    /// registries (`publish = ["internal-registry"]`) is publishable.
+    /// `changed_crates` is not filtered, and the traversal still passes
+    /// through private members.
+    pub fn only_publishable(mut self, only_publishable: bool) -> Self {
+        self.only_publishable = only_publishable;
+        self
+    }
+
+    /// Whether to attribute changed files to crates ignoring case (default
+    /// `false`), for diff tools on case-insensitive filesystems that report
+    /// `Lib-Core/src/lib.rs` for the `lib-core` directory. Both the changed
+    /// path and the crate directory are lowercased before comparison.
+    ///
+    /// Only attribution is affected: path prefixes in
+    /// [`AffectedOptions::excluded`] and [`AffectedOptions::included`] are
+    /// still matched case-sensitively against the directory as `cargo
+    /// metadata` reports it, and force triggers against the changed path as
+    /// given.
+    pub fn case_insensitive_paths(mut self, case_insensitive_paths: bool) -> Self {
+        self.case_insensitive_paths = case_insensitive_paths;
+        self
+    }
+
+    /// Map input file globs to the crates whose code generation consumes them
+    /// (e.g. `schemas/*.json` → `lib-core`).
+    ///
+    /// A changed file matching a glob marks the mapped crates as changed, so they
+    /// and all of their dependents are affected even though the file lives
+    /// outside their directories. Globs follow the [`check_force_triggers`]
+    /// syntax; crate names that aren't workspace members are ignored.
+    pub fn codegen_inputs<I, G, C, S>(mut self, codegen_inputs: I) -> Self
+    where
+        I: IntoIterator<Item = (G, C)>,
+        G: Into<String>,
+        C: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.codegen_inputs = codegen_inputs
+            .into_iter()
+            .map(|(glob, crates)| (glob.into(), crates.into_iter().map(Into::into).collect()))
+            .collect();
+        self
+    }
+
+    /// Map trigger globs to the crates they force (e.g. `infra/app-alpha/**` →
+    /// `app-alpha`), for external config that governs specific services.
+    ///
+    /// A changed file matching a glob adds the mapped crates to the affected
+    /// lists as-is, like [`AffectedOptions::always_affected`]: their dependents
+    /// are not pulled in and `force_all` is not set. Globs follow the
+    /// [`check_force_triggers`] syntax; crate names that aren't workspace
+    /// members are ignored.
+    pub fn scoped_triggers<I, G, C, S>(mut self, scoped_triggers: I) -> Self
+    where
+        I: IntoIterator<Item = (G, C)>,
+        G: Into<String>,
+        C: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.scoped_triggers = scoped_triggers
+            .into_iter()
+            .map(|(glob, crates)| (glob.into(), crates.into_iter().map(Into::into).collect()))
+            .collect();
+        self
+    }
+
+    /// Compute which workspace crates are affected by a set of changed files.
+    ///
+    /// `affected_library_members` and `affected_binary_members` are mutually
+    /// exclusive: a crate with a binary target appears only in
+    /// `affected_binary_members`, while pure library crates appear only in
+    /// `affected_library_members`.
+    ///
+    /// Blank entries and entries containing a NUL byte are skipped, and a
+    /// path listed more than once is only considered once. A list with
+    /// nothing else in it counts as empty.
+    ///
+    /// Returns the errors of [`AffectedOptions::validate`] for invalid
+    /// options, and [`AffectedError::SourceHash`] when
+    /// [`AffectedOptions::source_hashes`] can't read a member's sources.
+    pub fn compute(
+        &self,
+        graph: &PackageGraph,
+        changed_files: &[String],
+    ) -> Result<AffectedResult, AffectedError> {
+        let valid: Vec<&String> = changed_files
+            .iter()
+            .filter(|f| !f.trim().is_empty() && !f.contains('\0'))
+            .collect();
+        if valid.is_empty() {
+            return Ok(AffectedResult::default());
+        }
+        let mut seen = HashSet::new();
+        let changed_files: Vec<String> = valid
+            .into_iter()
+            .filter_map(|f| workspace_relative(graph, f))
+            .filter(|f| seen.insert(f.clone()))
+            .collect();
+
+        // A refined lockfile is diffed below instead of matching the triggers.
+        // So is a root manifest with a base version.
+        let refine_lockfile = self.base_lockfile.is_some();
+        let refine_root_manifest = self.base_manifests.contains_key("Cargo.toml");
+        // Mapped workflows seed their crates instead of forcing a full rebuild.
+        let trigger_files: Vec<String> = changed_files
+            .iter()
+            .filter(|f| !self.is_mapped_workflow(f))
+            .filter(|f| !(refine_lockfile && *f == "Cargo.lock"))
+            .filter(|f| !(refine_root_manifest && *f == "Cargo.toml"))
+            .cloned()
+            .collect();
+        let mut triggers = self.force_triggers.clone();
+        if self.default_force_triggers {
+            triggers.extend(DEFAULT_FORCE_TRIGGERS.iter().map(|t| t.to_string()));
+        }
+        // Triggers limited to a kind only fill in that kind of member;
+        // negative patterns apply to every trigger.
+        let trigger_kinds: Vec<Option<MemberKind>> = triggers
+            .iter()
+            .map(|t| Ok(trigger_kind(t)?.1))
+            .collect::<Result<_, AffectedError>>()?;
+        let triggers_for = |kinds: &[Option<MemberKind>]| -> Vec<String> {
+            triggers
+                .iter()
+                .zip(&trigger_kinds)
+                .filter(|(t, kind)| t.starts_with('!') || kinds.contains(kind))
+                .map(|(t, _)| t.clone())
+                .collect()
+        };
+        let mut trigger_reason = force_trigger_reason(&trigger_files, &triggers_for(&[None]))?;
+        let mut force_all = trigger_reason.is_some();
+        let mut forced_kinds = Vec::new();
+        for kind in [MemberKind::Library, MemberKind::Binary] {
+            if check_force_triggers(&trigger_files, &triggers_for(&[Some(kind)]))? {
+                forced_kinds.push(kind);
+            }
+        }
+        // Both kinds forced is everything, reported like any other trigger.
+        if forced_kinds.len() == 2 {
+            force_all = true;
+            if trigger_reason.is_none() {
+                let kinds = [Some(MemberKind::Library), Some(MemberKind::Binary)];
+                trigger_reason = force_trigger_reason(&trigger_files, &triggers_for(&kinds))?;
+            }
+        }
+        // The first fallback to a full rebuild; only reported without a
+        // trigger match.
+        let mut force_all_cause = None;
+        if self.lints_change_forces_all
+            && !refine_root_manifest
+            && changed_files.iter().any(|f| f == "Cargo.toml")
+        {
+            force_all = true;
+            force_all_cause.get_or_insert_with(|| "the root Cargo.toml changed".to_string());
+        }
+
+        // Cosmetic manifest edits don't make their crate changed.
+        let code_files: Vec<String> = changed_files
+            .iter()
+            .filter(|f| !self.is_ignorable_manifest_change(graph, f))
+            .cloned()
+            .collect();
+        let direct_ids = self.direct_ids(graph, &code_files)?;
+        // Packages with a changed lockfile entry seed the traversal without
+        // counting as changed crates themselves.
+        let mut seed_ids = direct_ids.clone();
+        if refine_lockfile && changed_files.iter().any(|f| f == "Cargo.lock") {
+            match self.lockfile_ids(graph) {
+                Some(ids) => seed_ids.extend(ids),
+                None => {
+                    force_all = true;
+                    force_all_cause.get_or_insert_with(|| {
+                        "Cargo.lock changed and couldn't be compared with its base version"
+                            .to_string()
+                    });
+                }
+            }
+        }
+        if refine_root_manifest && changed_files.iter().any(|f| f == "Cargo.toml") {
+            match self.workspace_dependency_ids(graph) {
+                Some(ids) => seed_ids.extend(ids),
+                None => {
+                    force_all = true;
+                    force_all_cause.get_or_insert_with(|| {
+                        "the root Cargo.toml changed and couldn't be compared with its base version"
+                            .to_string()
+                    });
+                }
+            }
+        }
+
+        let platform = self.platform()?;
+        let mut affected_set = if force_all {
+            graph.query_workspace().resolve()
+        } else {
+            self.dependents(graph, platform.as_ref(), &seed_ids)
+        };
+
+        let workspace = graph.workspace();
+
+        // Escalate to a full rebuild when most of the workspace is affected anyway.
+        if !force_all && let Some(threshold) = self.force_all_ratio {
+            let affected = affected_set
+                .packages(DependencyDirection::Forward)
+                .filter(|pkg| workspace.contains_name(pkg.name()) && !self.excludes(pkg))
+                .count();
+            let ratio = self.ratio(graph, affected);
+            if ratio >= threshold {
+                force_all = true;
+                force_all_cause = Some(format!(
+                    "{:.0}% of the workspace was affected, at least the force-all ratio of {:.0}%",
+                    ratio * 100.0,
+                    threshold * 100.0
+                ));
+                affected_set = graph.query_workspace().resolve();
+            }
+        }
+
+        // Always-affected crates and those of matching scoped triggers are
+        // added as-is; they didn't change, so their dependents are not pulled
+        // in. Exclusion below still removes them.
+        let mut scoped_crates: HashSet<&str> = HashSet::new();
+        for (glob, crates) in &self.scoped_triggers {
+            if check_force_triggers(&changed_files, std::slice::from_ref(glob))? {
+                scoped_crates.extend(crates.iter().map(String::as_str));
+            }
+        }
+        let always_ids: Vec<PackageId> = workspace
+            .iter()
+            .filter(|pkg| {
+                self.always_affected.contains(pkg.name()) || scoped_crates.contains(pkg.name())
+            })
+            .map(|pkg| pkg.id().clone())
+            .collect();
+        if !always_ids.is_empty() {
+            affected_set =
+                affected_set.union(&graph.resolve_ids(&always_ids).expect("resolve ids failed"));
+        }
+
+        // Members left out of the output lists: non-default members when
+        // asked, those outside a non-empty include list, then those excluded.
+        let default_ids: HashSet<&PackageId> = workspace.default_member_ids().collect();
+        let dropped = |pkg: &PackageMetadata| {
+            let dir = relative_dir(pkg);
+            (self.only_default_members
+                && !default_ids.is_empty()
+                && !default_ids.contains(pkg.id()))
+                || (!self.included.is_empty() && !self.included.matches(pkg.name(), &dir))
+                || self.excludes(pkg)
+        };
+
+        let mut changed_crates: Vec<String> = direct_ids
+            .iter()
+            .filter_map(|id| graph.metadata(id).ok())
+            .filter(|pkg| workspace.contains_name(pkg.name()) && !dropped(pkg))
+            .map(|pkg| pkg.name().to_string())
+            .collect();
+        changed_crates.sort();
+        let removed_crates = self.removed_crates(graph, &changed_files);
+
+        let select_kind = self.select.as_ref().and_then(|select| select.kind);
+        let select_path = self
+            .select
+            .as_ref()
+            .and_then(|select| select.path.as_ref())
+            .map(|path| build_glob_set(std::slice::from_ref(path)))
+            .transpose()?;
+        let selected = |pkg: &PackageMetadata| {
+            select_path
+                .as_ref()
+                .is_none_or(|glob| glob.is_match(relative_dir(pkg)))
+        };
+
+        let affected_packages: Vec<PackageMetadata> = affected_set
+            .packages(DependencyDirection::Forward)
+            .collect();
+        let forced_kind = if force_all {
+            None
+        } else {
+            forced_kinds.first().copied()
+        };
+        let all_packages: Vec<PackageMetadata> = if forced_kind.is_some() {
+            workspace.iter().collect()
+        } else {
+            Vec::new()
+        };
+        let candidates = |kind: MemberKind| {
+            if forced_kind == Some(kind) {
+                &all_packages
+            } else {
+                &affected_packages
+            }
+        };
+        let affected_library_members = member_names(candidates(MemberKind::Library), |pkg| {
+            workspace.contains_name(pkg.name())
+                && !dropped(pkg)
+                && !(self.only_publishable && pkg.publish().is_never())
+                && select_kind != Some(MemberKind::Binary)
+                && selected(pkg)
+                && !pkg
+                    .build_targets()
+                    .any(|t| matches!(t.id(), guppy::graph::BuildTargetId::Binary(_)))
+        });
+        let affected_binary_members = member_names(candidates(MemberKind::Binary), |pkg| {
+            workspace.contains_name(pkg.name())
+                && !dropped(pkg)
+                && !(self.only_publishable && pkg.publish().is_never())
+                && select_kind != Some(MemberKind::Library)
+                && selected(pkg)
+                && pkg
+                    .build_targets()
+                    .any(|t| matches!(t.id(), guppy::graph::BuildTargetId::Binary(_)))
+        });
+
+        let affected_build_order = dependency_levels(graph, &affected_library_members).concat();
+
+        let mut affected_build = Vec::new();
+        let mut affected_test = Vec::new();
+        if self.split_build_test {
+            // Both lists pick their own edge kinds, whatever `dependency_kinds` says.
+            let members = |kinds: &[DependencyKind]| -> Vec<String> {
+                let set = if force_all {
+                    graph.query_workspace().resolve()
+                } else {
+                    self.clone()
+                        .dependency_kinds(kinds.iter().copied())
+                        .dependents(graph, platform.as_ref(), &seed_ids)
+                        .union(&graph.resolve_ids(&always_ids).expect("resolve ids failed"))
+                };
+                let packages: Vec<PackageMetadata> =
+                    set.packages(DependencyDirection::Forward).collect();
+                member_names(&packages, |pkg| {
+                    workspace.contains_name(pkg.name()) && !dropped(pkg)
+                })
+            };
+            affected_build = members(&[DependencyKind::Normal, DependencyKind::Build]);
+            affected_test = members(&[
+                DependencyKind::Normal,
+                DependencyKind::Build,
+                DependencyKind::Development,
+            ]);
+        }
+
+        let affected_proc_macro_members = affected_library_members
+            .iter()
+            .filter(|name| {
+                workspace
+                    .member_by_name(name)
+                    .is_ok_and(|pkg| pkg.is_proc_macro())
+            })
+            .cloned()
+            .collect();
+
+        let mut affected_cdylib_members: Vec<String> = affected_library_members
+            .iter()
+            .chain(&affected_binary_members)
+            .filter(|name| {
+                workspace
+                    .member_by_name(name)
+                    .is_ok_and(|pkg| has_ffi_library(&pkg))
+            })
+            .cloned()
+            .collect();
+        affected_cdylib_members.sort();
+
+        let affected_bin_targets = affected_binary_members
+            .iter()
+            .flat_map(|name| {
+                let pkg = workspace
+                    .member_by_name(name)
+                    .expect("affected member is in the workspace");
+                binary_target_names(&pkg)
+                    .into_iter()
+                    .map(|bin| (name.clone(), bin))
+            })
+            .collect();
+
+        let transitively_affected_members = affected_library_members
+            .iter()
+            .filter(|name| !changed_crates.contains(name))
+            .cloned()
+            .collect();
+
+        let any_affected = force_all
+            || !changed_crates.is_empty()
+            || !affected_library_members.is_empty()
+            || !affected_binary_members.is_empty();
+
+        let mut source_hashes = BTreeMap::new();
+        if self.source_hashes {
+            for name in affected_library_members
+                .iter()
+                .chain(&affected_binary_members)
+            {
+                let pkg = workspace
+                    .member_by_name(name)
+                    .expect("affected member is in the workspace");
+                let dir = pkg
+                    .manifest_path()
+                    .parent()
+                    .expect("manifest has no parent")
+                    .as_std_path();
+                let hash = source_hash(dir).map_err(|source| AffectedError::SourceHash {
+                    name: name.clone(),
+                    source,
+                })?;
+                source_hashes.insert(name.clone(), hash);
+            }
+        }
+
+        let mut dependencies_of_changed = Vec::new();
+        if self.dependencies_of_changed {
+            let excluded_member =
+                |pkg: &PackageMetadata| workspace.contains_name(pkg.name()) && self.excludes(pkg);
+            // Dev-dependencies only matter for the changed crates themselves.
+            let non_dev_kinds: Vec<DependencyKind> = self
+                .dependency_kinds
+                .iter()
+                .copied()
+                .filter(|kind| *kind != DependencyKind::Development)
+                .collect();
+            dependencies_of_changed = graph
+                .query_forward(&direct_ids)
+                .expect("forward query failed")
+                .resolve_with_fn(|_, link| {
+                    let kinds = if direct_ids.contains(link.from().id()) {
+                        &self.dependency_kinds
+                    } else {
+                        &non_dev_kinds
+                    };
+                    follow_link(&link, platform.as_ref(), kinds)
+                })
+                .packages(DependencyDirection::Forward)
+                .filter(|pkg| !direct_ids.contains(pkg.id()) && !excluded_member(pkg))
+                .map(|pkg| pkg.name().to_string())
+                .collect();
+            dependencies_of_changed.sort();
+            dependencies_of_changed.dedup();
+        }
+
+        let mut degrees = BTreeMap::new();
+        if self.degrees {
+            for name in affected_library_members
+                .iter()
+                .chain(&affected_binary_members)
+            {
+                let pkg = workspace
+                    .member_by_name(name)
+                    .expect("affected member is in the workspace");
+                let in_degree = pkg
+                    .reverse_direct_links()
+                    .filter(|link| link.from().in_workspace())
+                    .count();
+                let out_degree = pkg
+                    .direct_links()
+                    .filter(|link| link.to().in_workspace())
+                    .count();
+                degrees.insert(name.clone(), (in_degree, out_degree));
+            }
+        }
+
+        let affected_paths = affected_library_members
+            .iter()
+            .chain(&affected_binary_members)
+            .map(|name| {
+                let pkg = workspace
+                    .member_by_name(name)
+                    .expect("affected member is in the workspace");
+                let dir = relative_dir_slashed(&pkg);
+                (
+                    name.clone(),
+                    if dir.is_empty() { ".".to_string() } else { dir },
+                )
+            })
+            .collect();
+
+        let mut editions = BTreeMap::new();
+        if self.editions {
+            for name in affected_library_members
+                .iter()
+                .chain(&affected_binary_members)
+            {
+                let pkg = workspace
+                    .member_by_name(name)
+                    .expect("affected member is in the workspace");
+                editions.insert(name.clone(), pkg.edition().to_string());
+            }
+        }
+
+        let mut testable_members = Vec::new();
+        if self.testable_members {
+            testable_members = affected_binary_members.clone();
+            testable_members.extend(
+                changed_crates
+                    .iter()
+                    .filter(|name| {
+                        !affected_binary_members.contains(name)
+                            && workspace
+                                .member_by_name(name)
+                                .is_ok_and(|pkg| has_tests(&pkg))
+                    })
+                    .cloned(),
+            );
+            testable_members.sort();
+        }
+
+        let mut needs_version_bump: Vec<String> = affected_library_members
+            .iter()
+            .chain(&affected_binary_members)
+            .filter(|name| {
+                self.published_versions
+                    .get(name.as_str())
+                    .is_some_and(|published| {
+                        let pkg = workspace
+                            .member_by_name(name)
+                            .expect("affected member is in the workspace");
+                        pkg.version().to_string() == published.trim()
+                    })
+            })
+            .cloned()
+            .collect();
+        needs_version_bump.sort();
+
+        let mut indexed = Vec::new();
+        if self.indexed {
+            let mut members: Vec<&String> = affected_library_members
+                .iter()
+                .chain(&affected_binary_members)
+                .collect();
+            members.sort();
+            indexed = members.into_iter().cloned().enumerate().collect();
+        }
+
+        Ok(AffectedResult {
+            schema_version: SchemaVersion,
+            force_all,
+            force_all_cause: force_all_cause.filter(|_| trigger_reason.is_none()),
+            force_trigger_reason: trigger_reason,
+            forced_kind,
+            any_affected,
+            changed_crates,
+            removed_crates,
+            affected_library_members,
+            affected_binary_members,
+            affected_bin_targets,
+            affected_build_order,
+            transitively_affected_members,
+            affected_proc_macro_members,
+            affected_cdylib_members,
+            affected_paths,
+            source_hashes,
+            dependencies_of_changed,
+            degrees,
+            affected_build,
+            affected_test,
+            editions,
+            testable_members,
+            needs_version_bump,
+            indexed,
+            flapping: Vec::new(),
+        })
+    }
 }
 
 /// Compute which workspace crates are affected by a set of changed files.
 ///
+/// This is a shorthand for [`AffectedOptions`] with only force triggers and
+/// exclusions set, so the default force triggers apply; use the builder for
+/// the remaining options.
+///
 /// `affected_library_members` and `affected_binary_members` are mutually
 /// exclusive: a crate with a binary target appears only in `affected_binary_members`,
 /// while pure library crates appear only in `affected_library_members`.
@@ -76,101 +2188,192 @@ pub fn check_force_triggers(changed_files: &[String], force_triggers: &[String])
 /// package's directory relative to the workspace root (e.g. `tools/` excludes
 /// every crate under `tools/`). Entries without `/` are matched against the crate
 /// name directly.
+///
+/// Returns [`AffectedError::InvalidGlob`] or
+/// [`AffectedError::InvalidTriggerKind`] for an invalid force trigger.
 pub fn compute_affected(
     graph: &PackageGraph,
     changed_files: &[String],
     force_triggers: &[String],
     excluded: &HashSet<String>,
-) -> AffectedResult {
-    if changed_files.is_empty() {
-        return AffectedResult {
-            force_all: false,
-            changed_crates: vec![],
-            affected_library_members: vec![],
-            affected_binary_members: vec![],
-        };
-    }
-
-    let force_all = check_force_triggers(changed_files, force_triggers);
+) -> Result<AffectedResult, AffectedError> {
+    AffectedOptions::new()
+        .force_triggers(force_triggers.iter().cloned())
+        .excluded(excluded.iter().cloned())
+        .compute(graph, changed_files)
+}
 
-    let workspace_root = graph.workspace().root().as_std_path();
+/// Load the package graph of the workspace in `manifest_dir` and compute the
+/// affected crates with `options`, for callers that don't already hold a
+/// graph.
+///
+/// Returns [`AffectedError::Metadata`] when `manifest_dir` isn't inside a
+/// Cargo workspace or `cargo metadata` fails, the error of
+/// [`AffectedOptions::validate`] for invalid options before loading the
+/// workspace, and otherwise the errors of [`AffectedOptions::compute`].
+pub fn compute_affected_from_path(
+    manifest_dir: impl AsRef<Path>,
+    changed_files: &[String],
+    options: &AffectedOptions,
+) -> Result<AffectedResult, AffectedError> {
+    options.validate()?;
+    let mut cmd = guppy::MetadataCommand::new();
+    cmd.current_dir(manifest_dir.as_ref());
+    let graph = PackageGraph::from_command(&mut cmd).map_err(AffectedError::Metadata)?;
+    options.compute(&graph, changed_files)
+}
 
-    // Helper: compute the relative directory for a package.
-    let relative_dir = |pkg: &guppy::graph::PackageMetadata| -> std::path::PathBuf {
-        let dir = pkg
-            .manifest_path()
-            .parent()
-            .expect("manifest has no parent")
-            .as_std_path();
-        dir.strip_prefix(workspace_root)
-            .unwrap_or(dir)
-            .to_path_buf()
-    };
+/// Group workspace crates into dependency levels.
+///
+/// Every crate in a level depends (transitively, through normal and build
+/// dependencies) only on crates in earlier levels. Dev-dependencies are ignored
+/// since they may legitimately form cycles. Names within a level are sorted, and
+/// names that aren't workspace members are skipped.
+fn dependency_levels(graph: &PackageGraph, names: &[String]) -> Vec<Vec<String>> {
+    let workspace = graph.workspace();
+    let members: Vec<_> = names
+        .iter()
+        .filter_map(|name| workspace.member_by_name(name).ok())
+        .collect();
 
-    let mut direct_ids = Vec::new();
-    for pkg in graph.workspace().iter() {
-        let pkg_dir = relative_dir(&pkg);
+    // For each crate, the other given crates it depends on.
+    let mut pending: BTreeMap<&str, BTreeSet<&str>> = members
+        .iter()
+        .map(|pkg| {
+            let deps = graph
+                .query_forward([pkg.id()])
+                .expect("forward query failed")
+                .resolve_with_fn(|_, link| !link.dev_only());
+            let deps = members
+                .iter()
+                .filter(|other| {
+                    other.id() != pkg.id() && deps.contains(other.id()).unwrap_or(false)
+                })
+                .map(|other| other.name())
+                .collect();
+            (pkg.name(), deps)
+        })
+        .collect();
 
-        if changed_files
+    let mut levels = Vec::new();
+    while !pending.is_empty() {
+        let mut level: Vec<&str> = pending
             .iter()
-            .any(|f| Path::new(f).starts_with(&pkg_dir))
-        {
-            direct_ids.push(pkg.id().clone());
+            .filter(|(_, deps)| deps.is_empty())
+            .map(|(name, _)| *name)
+            .collect();
+        // Only reachable through a cycle of normal dependencies, which cargo
+        // rejects; emit the rest as a single level rather than looping forever.
+        if level.is_empty() {
+            level = pending.keys().copied().collect();
+        }
+        for name in &level {
+            pending.remove(name);
+        }
+        for deps in pending.values_mut() {
+            for name in &level {
+                deps.remove(name);
+            }
         }
+        levels.push(level.into_iter().map(String::from).collect());
     }
+    levels
+}
 
-    let affected_set = if force_all {
-        graph.query_workspace().resolve()
-    } else {
-        graph
-            .query_reverse(direct_ids.iter())
-            .expect("reverse query failed")
-            .resolve()
-    };
-
-    let workspace = graph.workspace();
-
-    let mut changed_crates: Vec<String> = direct_ids
+/// Affected members (libraries and binaries) grouped into waves for staged CI.
+///
+/// The first level holds the affected crates with no affected dependencies — the
+/// directly changed crates, unless one of them depends on another. Every later
+/// level only depends on crates in earlier levels, so crates within a level can
+/// be built in parallel while the levels run one after another. Dev-dependencies
+/// don't order levels, and names within a level are sorted.
+pub fn affected_levels(graph: &PackageGraph, result: &AffectedResult) -> Vec<Vec<String>> {
+    let affected: Vec<String> = result
+        .affected_library_members
         .iter()
-        .filter_map(|id| graph.metadata(id).ok())
-        .filter(|pkg| {
-            workspace.contains_name(pkg.name())
-                && !is_excluded(pkg.name(), &relative_dir(pkg), excluded)
-        })
-        .map(|pkg| pkg.name().to_string())
+        .chain(&result.affected_binary_members)
+        .cloned()
         .collect();
-    changed_crates.sort();
+    dependency_levels(graph, &affected)
+}
 
-    let mut affected_library_members: Vec<String> = affected_set
-        .packages(guppy::graph::DependencyDirection::Forward)
-        .filter(|pkg| {
-            workspace.contains_name(pkg.name())
-                && !is_excluded(pkg.name(), &relative_dir(pkg), excluded)
-                && !pkg
-                    .build_targets()
-                    .any(|t| matches!(t.id(), guppy::graph::BuildTargetId::Binary(_)))
+/// Affected crates in the order `cargo-release` publishes a workspace:
+/// dependencies before their dependents, ties broken alphabetically.
+///
+/// Both library and binary members are included. Crates with `publish = false`
+/// are skipped; a crate limited to specific registries still counts as publishable.
+pub fn release_order(graph: &PackageGraph, result: &AffectedResult) -> Vec<String> {
+    affected_levels(graph, result)
+        .into_iter()
+        .flatten()
+        .filter(|name| {
+            graph
+                .workspace()
+                .member_by_name(name)
+                .is_ok_and(|pkg| !pkg.publish().is_never())
         })
-        .map(|pkg| pkg.name().to_string())
-        .collect();
-    affected_library_members.sort();
+        .collect()
+}
 
-    let mut affected_binary_members: Vec<String> = affected_set
-        .packages(guppy::graph::DependencyDirection::Forward)
-        .filter(|pkg| {
-            workspace.contains_name(pkg.name())
-                && !is_excluded(pkg.name(), &relative_dir(pkg), excluded)
-                && pkg
-                    .build_targets()
-                    .any(|t| matches!(t.id(), guppy::graph::BuildTargetId::Binary(_)))
-        })
-        .map(|pkg| pkg.name().to_string())
+/// Render the root `Cargo.toml` with `[workspace] members` listing only the
+/// affected members' directories, for building a trimmed workspace.
+///
+/// Everything else in the root manifest, e.g. `resolver`,
+/// `[workspace.dependencies]` or `[patch]`, is kept, except that
+/// `default-members` outside the new list are dropped. Member paths are
+/// relative to the workspace root, so the manifest is meant to replace the
+/// root `Cargo.toml`. They're sorted and always use `/` separators; comments
+/// and formatting of the original are not preserved.
+///
+/// Returns [`AffectedError::Config`] when the root manifest can't be read or
+/// parsed.
+pub fn workspace_manifest(
+    graph: &PackageGraph,
+    result: &AffectedResult,
+) -> Result<String, AffectedError> {
+    let workspace = graph.workspace();
+    let mut dirs: Vec<String> = result
+        .affected_library_members
+        .iter()
+        .chain(&result.affected_binary_members)
+        .filter_map(|name| workspace.member_by_name(name).ok())
+        .map(|pkg| relative_dir_slashed(&pkg))
         .collect();
-    affected_binary_members.sort();
+    dirs.sort();
 
-    AffectedResult {
-        force_all,
-        changed_crates,
-        affected_library_members,
-        affected_binary_members,
+    let path = workspace.root().join("Cargo.toml").into_std_path_buf();
+    let config_error = |message: String| AffectedError::Config {
+        path: path.clone(),
+        message,
+    };
+    let contents = std::fs::read_to_string(&path).map_err(|e| config_error(e.to_string()))?;
+    let mut manifest: toml::Table = contents
+        .parse()
+        .map_err(|e: toml::de::Error| config_error(e.to_string()))?;
+    let table = manifest
+        .entry("workspace")
+        .or_insert_with(|| toml::Table::new().into())
+        .as_table_mut()
+        .ok_or_else(|| config_error("`workspace` is not a table".to_string()))?;
+    if let Some(default_members) = table
+        .get_mut("default-members")
+        .and_then(|members| members.as_array_mut())
+    {
+        default_members.retain(|member| {
+            member
+                .as_str()
+                .is_some_and(|member| dirs.iter().any(|dir| dir == member.trim_end_matches('/')))
+        });
+        if default_members.is_empty() {
+            table.remove("default-members");
+        }
     }
+    table.insert(
+        "members".to_string(),
+        dirs.into_iter()
+            .map(toml::Value::from)
+            .collect::<Vec<_>>()
+            .into(),
+    );
+    toml::to_string(&manifest).map_err(|e| config_error(e.to_string()))
 }
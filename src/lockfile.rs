@@ -0,0 +1,51 @@
+use std::collections::{HashMap, HashSet};
+
+/// The `[[package]]` entries of a lockfile: name and version to source and
+/// sorted dependency list.
+type Entries = HashMap<(String, String), (Option<String>, Vec<String>)>;
+
+fn entries(lockfile: &str) -> Option<Entries> {
+    let table: toml::Table = lockfile.parse().ok()?;
+    let Some(packages) = table.get("package") else {
+        return Some(Entries::new());
+    };
+    packages
+        .as_array()?
+        .iter()
+        .map(|package| {
+            let package = package.as_table()?;
+            let field = |key: &str| package.get(key).and_then(|v| v.as_str()).map(String::from);
+            let mut dependencies: Vec<String> = package
+                .get("dependencies")
+                .and_then(|deps| deps.as_array())
+                .map(|deps| {
+                    deps.iter()
+                        .filter_map(|dep| dep.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default();
+            dependencies.sort();
+            Some((
+                (field("name")?, field("version")?),
+                (field("source"), dependencies),
+            ))
+        })
+        .collect()
+}
+
+/// Name and version of every package in the `current` lockfile that is new or
+/// differs from its entry in `base`: a version that wasn't locked before, or
+/// the same version from another source or with other dependencies.
+///
+/// Returns `None` if either lockfile can't be parsed.
+pub(crate) fn changed_packages(base: &str, current: &str) -> Option<HashSet<(String, String)>> {
+    let base = entries(base)?;
+    let current = entries(current)?;
+    Some(
+        current
+            .into_iter()
+            .filter(|(key, entry)| base.get(key) != Some(entry))
+            .map(|(key, _)| key)
+            .collect(),
+    )
+}
@@ -1,140 +1,1398 @@
-use guppy::{graph::PackageGraph, MetadataCommand};
-use rust_affected::compute_affected;
+use guppy::{DependencyKind, MetadataCommand, PackageId, graph::PackageGraph};
+use rust_affected::{
+    AffectedConfig, AffectedError, AffectedOptions, AffectedResult, DEFAULT_FORCE_TRIGGERS,
+    FlapState, RatioDenominator, SCHEMA_VERSION, Selector, cached_package_graph,
+    check_force_triggers, git, json_patch, normalize_changed_file, release_order, to_json_value,
+    workspace_manifest,
+};
+use std::collections::HashMap;
 use std::env;
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-fn main() {
-    let changed_files: Vec<String> = env::var("CHANGED_FILES")
+/// Whether debug logging is on, set from `-v` or `RUST_AFFECTED_LOG`.
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Print a debug line to stderr when verbose logging is on. Stdout is left to
+/// the output itself.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if VERBOSE.load(Ordering::Relaxed) {
+            eprintln!("rust-affected: debug: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+/// How results are emitted outside GitHub Actions, selected with `OUTPUT_FORMAT`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    /// A single JSON object with every output list (default).
+    Json,
+    /// Affected, publishable crates one per line in `cargo-release` publish order.
+    ReleaseOrder,
+    /// The JSON object, plus a trimmed workspace manifest listing only the
+    /// affected members, written to `WORKSPACE_MANIFEST_PATH`.
+    WorkspaceManifest,
+    /// Newline-delimited JSON: a `force_all` line, then one object per affected crate.
+    Ndjson,
+    /// `KEY=value` lines with comma-separated lists, for GitLab `dotenv` artifacts.
+    Dotenv,
+    /// Bare crate names one per line: the affected libraries, or the list
+    /// picked with `--kind`.
+    Lines,
+}
+
+impl OutputFormat {
+    fn from_env() -> Result<Self, AffectedError> {
+        Ok(match env::var("OUTPUT_FORMAT").unwrap_or_default().trim() {
+            "" | "json" => OutputFormat::Json,
+            "release-order" => OutputFormat::ReleaseOrder,
+            "workspace-manifest" => OutputFormat::WorkspaceManifest,
+            "ndjson" => OutputFormat::Ndjson,
+            "dotenv" => OutputFormat::Dotenv,
+            "lines" => OutputFormat::Lines,
+            other => {
+                return Err(invalid_input(
+                    "OUTPUT_FORMAT",
+                    format!(
+                        "unknown format {other:?}; expected \"json\", \"release-order\", \"workspace-manifest\", \"ndjson\", \"dotenv\" or \"lines\""
+                    ),
+                ));
+            }
+        })
+    }
+}
+
+/// Which crate list(s) are emitted, selected with `--kind` or `OUTPUT_KIND`.
+/// Without either, `OUTPUT_FORMAT=lines` prints the libraries and every other
+/// format emits all lists.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// `affected_library_members`.
+    Library,
+    /// `affected_binary_members`.
+    Binary,
+    /// `changed_crates`.
+    Changed,
+    /// Every list.
+    All,
+}
+
+impl Kind {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "library" => Some(Kind::Library),
+            "binary" => Some(Kind::Binary),
+            "changed" => Some(Kind::Changed),
+            "all" => Some(Kind::All),
+            _ => None,
+        }
+    }
+
+    /// The `--kind` flag, else `OUTPUT_KIND`, else `None`.
+    fn from_cli_or_env(cli: Option<Kind>) -> Result<Option<Self>, AffectedError> {
+        if cli.is_some() {
+            return Ok(cli);
+        }
+        let value = env::var("OUTPUT_KIND").unwrap_or_default();
+        if value.trim().is_empty() {
+            return Ok(None);
+        }
+        Kind::parse(&value).map(Some).ok_or_else(|| {
+            invalid_input(
+                "OUTPUT_KIND",
+                format!(
+                    "unknown kind {value:?}; expected \"library\", \"binary\", \"changed\" or \"all\""
+                ),
+            )
+        })
+    }
+
+    /// Whether the list under the output key `key` is emitted.
+    fn selects(self, key: &str) -> bool {
+        match self {
+            Kind::Library => key == "affected_library_members",
+            Kind::Binary => key == "affected_binary_members",
+            Kind::Changed => key == "changed_crates",
+            Kind::All => true,
+        }
+    }
+}
+
+/// The output keys of the lists `--kind` chooses between.
+const KIND_LISTS: &[&str] = &[
+    "changed_crates",
+    "affected_library_members",
+    "affected_binary_members",
+];
+
+const USAGE: &str = "\
+Usage: rust-affected [--changed <FILE>]... [--force-trigger <PATTERN>]... [--exclude <MEMBER>]...
+                     [--git-range <RANGE>] [--workspace <DIR>... | --manifest-path <PATH>]
+                     [--cache <DIR>]
+                     [--output <FILE>] [--pretty] [--exit-code] [--dry-run]
+                     [--warn-unmatched] [--kind <library|binary|changed|all>] [-v]
+       rust-affected --check-workspace
+
+Each list flag may be repeated and its values accumulate. A flag given on the
+command line replaces the matching environment variable (CHANGED_FILES,
+FORCE_TRIGGERS, EXCLUDED_MEMBERS, GIT_DIFF_RANGE, WORKSPACE_DIR, CACHE_DIR,
+OUTPUT_FILE); the environment is used when it is absent. Force triggers and
+exclusions from an affected.toml at the workspace root are added to them.
+Cargo.lock, the root Cargo.toml, rust-toolchain.toml and .cargo/config.toml
+always force a full rebuild unless DEFAULT_FORCE_TRIGGERS=false. --workspace (or
+--manifest-path) points at a workspace other than the current directory; given
+more than once, changed files are relative to the current directory, each goes
+to the workspace containing it, and the JSON groups the results under workspaces
+by directory. --cache reuses cargo metadata output across runs until a manifest
+or Cargo.lock changes. --git-range (e.g. main..HEAD) lists the changed files
+with git when no changed files are given. --output writes the JSON to a file
+instead of stdout, and --pretty (or PRETTY=true) indents it. --exit-code exits
+with 78 when nothing is affected (EXIT_CODE_ON_EMPTY picks another code);
+force_all always counts as affected. -v (or RUST_AFFECTED_LOG=1) logs how each
+crate was attributed, triggered or excluded to stderr. --dry-run (or
+DRY_RUN=true) only prints which member each changed file belongs to (null for
+none) and the files that matched no member, without computing the affected
+crates. --warn-unmatched (or WARN_UNMATCHED=true) warns on stderr about changed
+files that belong to no member and match no force trigger, e.g. because of a
+typo or the wrong case. OUTPUT_FORMAT=lines prints bare crate names one per
+line. --kind (or OUTPUT_KIND) picks the list(s) to emit in the json, ndjson,
+dotenv and lines formats: library, binary, changed or all. It defaults to all,
+except that lines prints the libraries.
+
+--check-workspace (or CHECK_WORKSPACE=1) only loads the workspace and reports
+its member count, exiting non-zero if it can't be resolved.
+
+Exit codes: 0 on success, 1 on errors, 2 on usage errors (an unknown flag or a
+missing flag value) and 78 with --exit-code when nothing is affected.";
+
+/// Lists given on the command line. An empty list falls back to the matching
+/// environment variable.
+#[derive(Default)]
+struct CliArgs {
+    changed: Vec<String>,
+    force_triggers: Vec<String>,
+    excluded: Vec<String>,
+    git_range: Option<String>,
+    workspaces: Vec<String>,
+    manifest_path: Option<String>,
+    cache: Option<String>,
+    output: Option<String>,
+    pretty: bool,
+    exit_code: bool,
+    verbose: bool,
+    check_workspace: bool,
+    dry_run: bool,
+    warn_unmatched: bool,
+    kind: Option<Kind>,
+}
+
+impl CliArgs {
+    /// Parse `--flag value` and `--flag=value` pairs; repeated flags accumulate.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut cli = CliArgs::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            if matches!(
+                flag.as_str(),
+                "--check-workspace"
+                    | "--dry-run"
+                    | "--warn-unmatched"
+                    | "--pretty"
+                    | "--exit-code"
+                    | "-v"
+                    | "--verbose"
+            ) {
+                if inline_value.is_some() {
+                    return Err(format!("{flag} takes no value"));
+                }
+                match flag.as_str() {
+                    "--pretty" => cli.pretty = true,
+                    "--exit-code" => cli.exit_code = true,
+                    "-v" | "--verbose" => cli.verbose = true,
+                    "--dry-run" => cli.dry_run = true,
+                    "--warn-unmatched" => cli.warn_unmatched = true,
+                    _ => cli.check_workspace = true,
+                }
+                continue;
+            }
+            if !matches!(
+                flag.as_str(),
+                "--changed"
+                    | "--force-trigger"
+                    | "--exclude"
+                    | "--git-range"
+                    | "--workspace"
+                    | "--manifest-path"
+                    | "--cache"
+                    | "--output"
+                    | "--kind"
+            ) {
+                return Err(format!("unknown argument {flag:?}"));
+            }
+            let value = inline_value
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{flag} requires a value"))?;
+            match flag.as_str() {
+                "--changed" => cli.changed.push(value),
+                "--force-trigger" => cli.force_triggers.push(value),
+                "--exclude" => cli.excluded.push(value),
+                "--workspace" => cli.workspaces.push(value),
+                "--manifest-path" => cli.manifest_path = Some(value),
+                "--cache" => cli.cache = Some(value),
+                "--output" => cli.output = Some(value),
+                "--kind" => {
+                    cli.kind = Some(Kind::parse(&value).ok_or_else(|| {
+                        format!(
+                            "unknown --kind {value:?}; expected \"library\", \"binary\", \"changed\" or \"all\""
+                        )
+                    })?)
+                }
+                _ => cli.git_range = Some(value),
+            }
+        }
+        Ok(cli)
+    }
+}
+
+/// Where the workspace is: `--manifest-path`, else the first `--workspace` or
+/// `WORKSPACE_DIR`, else the current directory.
+struct WorkspaceLocation {
+    /// The directory `cargo metadata` and `git` run in.
+    dir: PathBuf,
+    manifest_path: Option<PathBuf>,
+    /// Where `cargo metadata` output is cached, from `--cache` or `CACHE_DIR`.
+    cache_dir: Option<PathBuf>,
+}
+
+impl WorkspaceLocation {
+    fn new(cli: &CliArgs) -> Self {
+        let cache_dir = cli
+            .cache
+            .clone()
+            .or_else(|| env::var("CACHE_DIR").ok())
+            .filter(|dir| !dir.trim().is_empty())
+            .map(|dir| PathBuf::from(dir.trim()));
+        if let Some(manifest_path) = &cli.manifest_path {
+            let manifest_path = PathBuf::from(manifest_path);
+            let dir = match manifest_path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            return WorkspaceLocation {
+                dir,
+                manifest_path: Some(manifest_path),
+                cache_dir,
+            };
+        }
+        let dir = cli
+            .workspaces
+            .first()
+            .cloned()
+            .or_else(|| env::var("WORKSPACE_DIR").ok())
+            .filter(|dir| !dir.trim().is_empty())
+            .unwrap_or_else(|| ".".to_string());
+        WorkspaceLocation {
+            dir: PathBuf::from(dir.trim()),
+            manifest_path: None,
+            cache_dir,
+        }
+    }
+
+    fn metadata_command(&self) -> MetadataCommand {
+        let mut cmd = MetadataCommand::new();
+        cmd.current_dir(&self.dir);
+        if let Some(manifest_path) = &self.manifest_path {
+            cmd.manifest_path(manifest_path);
+        }
+        cmd
+    }
+
+    fn package_graph(&self) -> Result<PackageGraph, AffectedError> {
+        let mut cmd = self.metadata_command();
+        match &self.cache_dir {
+            Some(cache_dir) => cached_package_graph(&cmd, &self.dir, cache_dir),
+            None => PackageGraph::from_command(&mut cmd).map_err(AffectedError::Metadata),
+        }
+    }
+}
+
+/// The command-line list if any values were given, otherwise the
+/// whitespace-separated entries of the environment variable `name`.
+fn list_or_env(cli: Vec<String>, name: &str) -> Vec<String> {
+    if !cli.is_empty() {
+        return cli;
+    }
+    // split_whitespace never yields empty entries, so blank lines are skipped.
+    env::var(name)
+        .map(|v| v.split_whitespace().map(String::from).collect())
         .unwrap_or_default()
-        .split_whitespace()
-        .map(String::from)
-        .collect();
+}
 
-    if changed_files.is_empty() {
-        emit_output(false, vec![], vec![], vec![]);
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
         return;
     }
+    let cli = CliArgs::parse(args).unwrap_or_else(|e| {
+        eprintln!("rust-affected: {e}\n\n{USAGE}");
+        std::process::exit(2);
+    });
 
-    let force_triggers: Vec<String> = env::var("FORCE_TRIGGERS")
-        .map(|v| v.split_whitespace().map(String::from).collect())
-        .unwrap_or_default();
+    let log_env = env::var("RUST_AFFECTED_LOG").unwrap_or_default();
+    VERBOSE.store(
+        cli.verbose || !matches!(log_env.trim(), "" | "0" | "false" | "off"),
+        Ordering::Relaxed,
+    );
+
+    if cli.check_workspace || env_flag("CHECK_WORKSPACE", false) {
+        match check_workspace(&WorkspaceLocation::new(&cli)) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("rust-affected: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Exit code when nothing is affected; 0 keeps the default of always
+    // succeeding. The --exit-code default stays clear of the usage error's 2.
+    let empty_exit_code = match env_parse::<i32>("EXIT_CODE_ON_EMPTY", "an exit code") {
+        Ok(Some(code)) => code,
+        Ok(None) if cli.exit_code => 78,
+        Ok(None) => 0,
+        Err(e) => {
+            eprintln!("rust-affected: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    match run(cli) {
+        Ok(false) if empty_exit_code != 0 => std::process::exit(empty_exit_code),
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("rust-affected: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Compute and emit the result, returning whether anything is affected.
+fn run(cli: CliArgs) -> Result<bool, AffectedError> {
+    let location = WorkspaceLocation::new(&cli);
+    let format = OutputFormat::from_env()?;
+    let output_file = cli
+        .output
+        .clone()
+        .or_else(|| env::var("OUTPUT_FILE").ok())
+        .filter(|path| !path.trim().is_empty());
+    let output_file = output_file.as_deref();
+    let pretty = cli.pretty || env_flag("PRETTY", false);
+    let kind = Kind::from_cli_or_env(cli.kind)?;
+    // With several workspaces, changed files are relative to the current
+    // directory rather than to a workspace root.
+    let git_dir = if cli.workspaces.len() > 1 {
+        Path::new(".")
+    } else {
+        location.dir.as_path()
+    };
+
+    // Porcelain lines contain spaces, so they're split by line instead.
+    let changed = if cli.changed.is_empty() && env_flag("CHANGED_FILES_PORCELAIN", false) {
+        git::parse_porcelain(
+            &env::var("CHANGED_FILES").unwrap_or_default(),
+            env_flag("PORCELAIN_RENAME_SOURCES", false),
+        )
+    } else {
+        list_or_env(cli.changed.clone(), "CHANGED_FILES")
+    };
+    let mut changed_files: Vec<String> =
+        changed.iter().map(|f| normalize_changed_file(f)).collect();
+
+    // An explicit changed-file list always wins; a git range and then the
+    // reflog are only consulted when no files were provided.
+    let git_range = cli
+        .git_range
+        .clone()
+        .or_else(|| env::var("GIT_DIFF_RANGE").ok())
+        .filter(|range| !range.trim().is_empty());
+    if changed_files.is_empty()
+        && let Some(range) = git_range
+    {
+        changed_files =
+            git::changed_files_in_range(git_dir, range.trim()).map_err(AffectedError::Git)?;
+    }
+
+    if changed_files.is_empty() && env_flag("DIFF_REFLOG", false) {
+        let since_reflog = git::changed_files_since_reflog(git_dir).map_err(|e| {
+            AffectedError::Git(std::io::Error::new(
+                e.kind(),
+                format!("failed to read changed files from the git reflog: {e}"),
+            ))
+        })?;
+        match since_reflog {
+            Some(files) => changed_files = files,
+            None => eprintln!(
+                "rust-affected: DIFF_REFLOG is set but the reflog has no previous HEAD; no files changed"
+            ),
+        }
+    }
+
+    if cli.workspaces.len() > 1 {
+        if format != OutputFormat::Json {
+            return Err(invalid_input(
+                "OUTPUT_FORMAT",
+                "several --workspace flags only support \"json\"",
+            ));
+        }
+        // These act on a single result, so rather than ignore them they're
+        // rejected.
+        let env_set = |name| env::var(name).is_ok_and(|value| !value.trim().is_empty());
+        let unsupported = [
+            ("DRY_RUN", cli.dry_run || env_flag("DRY_RUN", false)),
+            ("PREVIEW", env_flag("PREVIEW", false)),
+            ("OUTPUT_MATRIX", env_flag("OUTPUT_MATRIX", false)),
+            ("FLAP_STATE", env_set("FLAP_STATE")),
+            ("BASELINE", env_set("BASELINE")),
+        ];
+        if let Some((name, _)) = unsupported.into_iter().find(|(_, set)| *set) {
+            return Err(invalid_input(
+                name,
+                "not supported with several --workspace flags",
+            ));
+        }
+        let (output, results) = workspaces_output(&location, &cli, &changed_files, kind)?;
+        emit_workspaces(&output, output_file, pretty)?;
+        if let Ok(path) = env::var("GITHUB_STEP_SUMMARY") {
+            for (dir, result) in &results {
+                write_step_summary(Path::new(&path), &format!("rust-affected: {dir}"), result)
+                    .map_err(|source| AffectedError::Output {
+                        path: PathBuf::from(&path),
+                        source,
+                    })?;
+            }
+        }
+        return Ok(output["any_affected"] == true);
+    }
+
+    if cli.dry_run || env_flag("DRY_RUN", false) {
+        let graph = location.package_graph()?;
+        let options = AffectedOptions::new()
+            .case_insensitive_paths(env_flag("CASE_INSENSITIVE_PATHS", false));
+        let owners = options.file_owners(&graph, &changed_files);
+        println!(
+            "{}",
+            to_json(&attribution(&graph, &changed_files, owners), pretty)
+        );
+        // Nothing is resolved, so a dry run never counts as an empty result.
+        return Ok(true);
+    }
+
+    // Without changed files the workspace isn't loaded, except for the
+    // workspace manifest: it is written even when nothing changed, so that a
+    // downstream job can always rely on the file existing.
+    let (graph, mut result) =
+        if changed_files.is_empty() && format != OutputFormat::WorkspaceManifest {
+            debug!("no changed files; nothing is affected");
+            (None, AffectedResult::default())
+        } else {
+            let (graph, result) = affected(&location, &cli, &changed_files)?;
+            (Some(graph), result)
+        };
+    record_flaps(&mut result)?;
+
+    let lines = match format {
+        OutputFormat::Json => None,
+        // Nothing is affected without a graph, so there is nothing to order.
+        OutputFormat::ReleaseOrder => Some(
+            graph
+                .as_ref()
+                .map(|graph| release_order(graph, &result))
+                .unwrap_or_default(),
+        ),
+        OutputFormat::Ndjson => Some(ndjson(&result, kind.unwrap_or(Kind::All))),
+        OutputFormat::Dotenv => Some(dotenv(&result, kind.unwrap_or(Kind::All))),
+        OutputFormat::Lines => Some(names(&result, kind.unwrap_or(Kind::Library))),
+        OutputFormat::WorkspaceManifest => {
+            let graph = graph.expect("the workspace is loaded for the workspace manifest");
+            let path = env::var("WORKSPACE_MANIFEST_PATH")
+                .ok()
+                .filter(|p| !p.trim().is_empty())
+                .ok_or_else(|| {
+                    invalid_input(
+                        "WORKSPACE_MANIFEST_PATH",
+                        "OUTPUT_FORMAT=workspace-manifest requires a path",
+                    )
+                })?;
+            std::fs::write(&path, workspace_manifest(&graph, &result)?).map_err(|source| {
+                AffectedError::Output {
+                    path: PathBuf::from(&path),
+                    source,
+                }
+            })?;
+            None
+        }
+    };
+
+    emit_output(&result, lines, kind, output_file, pretty)?;
+    Ok(result.any_affected)
+}
+
+/// Build the options for the workspace at `location` from its config file, the
+/// flags and the environment, and compute the result for `changed_files`,
+/// which are relative to the workspace root.
+fn affected(
+    location: &WorkspaceLocation,
+    cli: &CliArgs,
+    changed_files: &[String],
+) -> Result<(PackageGraph, AffectedResult), AffectedError> {
+    // Lists from affected.toml come first; flags and the environment add to them.
+    let config = AffectedConfig::load(&location.dir)?;
+    let force_triggers: Vec<String> = config
+        .force_triggers
+        .into_iter()
+        .chain(list_or_env(cli.force_triggers.clone(), "FORCE_TRIGGERS"))
+        .collect();
+
+    // Report an invalid pattern cleanly rather than panicking mid-computation.
+    check_force_triggers(changed_files, &force_triggers)?;
+    let default_force_triggers = env_flag("DEFAULT_FORCE_TRIGGERS", true);
+    let defaults: Vec<String> = if default_force_triggers {
+        DEFAULT_FORCE_TRIGGERS
+            .iter()
+            .map(|t| t.to_string())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let all_triggers: Vec<String> = force_triggers.iter().chain(&defaults).cloned().collect();
+    let (negative_triggers, positive_triggers): (Vec<&String>, Vec<&String>) = all_triggers
+        .iter()
+        .partition(|trigger| trigger.starts_with('!'));
+    // Matching each file against each trigger is only worth it for the log.
+    if VERBOSE.load(Ordering::Relaxed) {
+        for file in changed_files {
+            for trigger in &positive_triggers {
+                let patterns: Vec<String> = std::iter::once(*trigger)
+                    .chain(negative_triggers.iter().copied())
+                    .cloned()
+                    .collect();
+                if check_force_triggers(std::slice::from_ref(file), &patterns)? {
+                    debug!("{file} matches force trigger {trigger:?}");
+                }
+            }
+        }
+    }
 
-    let excluded: std::collections::HashSet<String> = env::var("EXCLUDED_MEMBERS")
+    let excluded: Vec<String> = config
+        .excluded_members
+        .into_iter()
+        .chain(list_or_env(cli.excluded.clone(), "EXCLUDED_MEMBERS"))
+        .collect();
+
+    let included: Vec<String> = config
+        .included_members
+        .into_iter()
+        .chain(
+            env::var("INCLUDED_MEMBERS")
+                .map(|v| v.split_whitespace().map(String::from).collect::<Vec<_>>())
+                .unwrap_or_default(),
+        )
+        .collect();
+
+    let always_affected: Vec<String> = env::var("ALWAYS_AFFECTED")
         .map(|v| v.split_whitespace().map(String::from).collect())
         .unwrap_or_default();
 
-    let mut cmd = MetadataCommand::new();
-    let graph = PackageGraph::from_command(&mut cmd)
-        .expect("Failed to load package graph. Is this a Cargo workspace?");
+    let mut options = AffectedOptions::new()
+        .force_triggers(force_triggers)
+        .default_force_triggers(default_force_triggers)
+        .excluded(excluded)
+        .included(included)
+        .always_affected(always_affected)
+        .include_dev(env_flag("INCLUDE_DEV_DEPENDENCIES", true))
+        .prune_excluded(env_flag("PRUNE_EXCLUDED", false))
+        .source_hashes(env_flag("EMIT_SOURCE_HASHES", false))
+        .dependencies_of_changed(env_flag("EMIT_DEPENDENCIES_OF_CHANGED", false))
+        .degrees(env_flag("EMIT_DEGREES", false))
+        .indexed(env_flag("EMIT_INDEXED", false))
+        .editions(env_flag("EMIT_EDITIONS", false))
+        .testable_members(env_flag("EMIT_TESTABLE_MEMBERS", false))
+        .split_build_test(env_flag("SPLIT_BUILD_TEST", false))
+        .lints_change_forces_all(env_flag("LINTS_CHANGE_FORCES_ALL", false))
+        .only_default_members(env_flag("ONLY_DEFAULT_MEMBERS", false))
+        .only_publishable(env_flag("ONLY_PUBLISHABLE", false))
+        .case_insensitive_paths(env_flag("CASE_INSENSITIVE_PATHS", false));
+
+    // An explicit list of edge kinds replaces INCLUDE_DEV_DEPENDENCIES.
+    if let Ok(kinds) = env::var("DEPENDENCY_KINDS")
+        && !kinds.trim().is_empty()
+    {
+        let kinds: Vec<DependencyKind> = kinds
+            .split([',', ' '])
+            .filter(|kind| !kind.is_empty())
+            .map(|kind| match kind {
+                "normal" => Ok(DependencyKind::Normal),
+                "build" => Ok(DependencyKind::Build),
+                "dev" => Ok(DependencyKind::Development),
+                other => Err(invalid_input(
+                    "DEPENDENCY_KINDS",
+                    format!(
+                        "unknown dependency kind {other:?}; expected \"normal\", \"build\" or \"dev\""
+                    ),
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+        options = options.dependency_kinds(kinds);
+    }
+
+    if let Ok(path) = env::var("EXCLUDED_MEMBERS_FILE")
+        && !path.trim().is_empty()
+    {
+        let read_error =
+            |e: std::io::Error| invalid_input("EXCLUDED_MEMBERS_FILE", format!("{path}: {e}"));
+        let file = std::fs::File::open(path.trim()).map_err(read_error)?;
+        options = options
+            .excluded_from_reader(std::io::BufReader::new(file))
+            .map_err(read_error)?;
+    }
+
+    // A .affectedignore at the workspace root adds exclusions, one or more
+    // per line, in the same format.
+    let ignore_path = location.dir.join(".affectedignore");
+    if ignore_path.is_file() {
+        let read_error = |e: std::io::Error| AffectedError::Config {
+            path: ignore_path.clone(),
+            message: e.to_string(),
+        };
+        let file = std::fs::File::open(&ignore_path).map_err(read_error)?;
+        options = options
+            .excluded_from_reader(std::io::BufReader::new(file))
+            .map_err(read_error)?;
+    }
+
+    if let Some(api_changed) =
+        env_json::<HashMap<String, bool>>("API_DIFF", "a JSON object of crate name to bool")?
+    {
+        options = options.api_changed(api_changed);
+    }
+
+    let mut codegen_inputs: Vec<(String, Vec<String>)> =
+        config.codegen_inputs.into_iter().collect();
+    if let Some(env_inputs) = env_json::<HashMap<String, Vec<String>>>(
+        "CODEGEN_INPUTS",
+        "a JSON object of glob to crate names",
+    )? {
+        codegen_inputs.extend(env_inputs);
+    }
+    options = options.codegen_inputs(codegen_inputs);
 
-    let result = compute_affected(&graph, &changed_files, &force_triggers, &excluded);
+    if let Some(scoped_triggers) = env_json::<HashMap<String, Vec<String>>>(
+        "SCOPED_TRIGGERS",
+        "a JSON object of glob to crate names",
+    )? {
+        options = options.scoped_triggers(scoped_triggers);
+    }
+
+    if let Some(workflow_crate_map) = env_json::<HashMap<String, Vec<String>>>(
+        "WORKFLOW_CRATE_MAP",
+        "a JSON object of workflow to crate names",
+    )? {
+        options = options.workflow_crate_map(workflow_crate_map);
+    }
+
+    if let Some(published_versions) = env_json::<HashMap<String, String>>(
+        "PUBLISHED_VERSIONS",
+        "a JSON object of crate name to version",
+    )? {
+        options = options.published_versions(published_versions);
+    }
+
+    if let Some(submodule_crate_map) = env_json::<HashMap<String, Vec<String>>>(
+        "SUBMODULE_CRATE_MAP",
+        "a JSON object of submodule path to crate names",
+    )? {
+        options = options.submodule_crate_map(submodule_crate_map);
+    }
 
-    emit_output(
-        result.force_all,
-        result.changed_crates,
-        result.affected_library_members,
-        result.affected_binary_members,
+    let rebuild_marker = env::var("REBUILD_MARKER").unwrap_or_default();
+    options = options.rebuild_marker(match rebuild_marker.trim() {
+        "" => ".rebuild",
+        marker => marker,
+    });
+
+    // A cached copy of the base lockfile wins over reading it from a ref.
+    // Either way, a lockfile that can't be read forces a full rebuild.
+    let base_lockfile = env::var("BASE_LOCKFILE").unwrap_or_default();
+    let lockfile_base_ref = env::var("LOCKFILE_BASE_REF").unwrap_or_default();
+    if !base_lockfile.trim().is_empty() {
+        let base = std::fs::read_to_string(base_lockfile.trim());
+        if let Err(e) = &base {
+            debug!("no base lockfile at {}: {e}", base_lockfile.trim());
+        }
+        options = options.base_lockfile(base.ok());
+    } else if !lockfile_base_ref.trim().is_empty() {
+        let base = git::file_at_rev(&location.dir, lockfile_base_ref.trim(), "Cargo.lock");
+        if let Err(e) = &base {
+            debug!("no base lockfile at {}: {e}", lockfile_base_ref.trim());
+        }
+        options = options.base_lockfile(base.ok());
+    }
+
+    // Manifests missing at the ref are new, and always count as changed.
+    if let Ok(rev) = env::var("MANIFEST_BASE_REF")
+        && !rev.trim().is_empty()
+    {
+        let base_manifests: Vec<(&String, String)> = changed_files
+            .iter()
+            .filter(|f| f.rsplit('/').next() == Some("Cargo.toml"))
+            .filter_map(|f| Some((f, git::file_at_rev(&location.dir, rev.trim(), f).ok()?)))
+            .collect();
+        options = options.base_manifests(base_manifests);
+    }
+
+    if let Some(select) = env_json::<Selector>(
+        "SELECT",
+        "a JSON object with optional \"kind\" and \"path\"",
+    )? {
+        options = options.select(select);
+    }
+
+    if let Some(max_depth) = env_parse("MAX_DEPTH", "a non-negative integer")? {
+        options = options.max_depth(max_depth);
+    }
+
+    if let Some(ratio) = env_parse("FORCE_ALL_RATIO", "a number between 0 and 1")? {
+        options = options.force_all_ratio(ratio);
+    }
+
+    match env::var("RATIO_DENOMINATOR").unwrap_or_default().trim() {
+        "" | "all" => {}
+        "non-excluded" => options = options.ratio_denominator(RatioDenominator::NonExcluded),
+        other => {
+            return Err(invalid_input(
+                "RATIO_DENOMINATOR",
+                format!("unknown denominator {other:?}; expected \"all\" or \"non-excluded\""),
+            ));
+        }
+    }
+
+    if let Ok(target) = env::var("TARGET_TRIPLE")
+        && !target.trim().is_empty()
+    {
+        options = options.target(target.trim());
+    }
+
+    let graph = location.package_graph()?;
+    debug!("workspace root: {}", graph.workspace().root());
+    if VERBOSE.load(Ordering::Relaxed) {
+        let owners = options.file_owners(&graph, changed_files);
+        for (file, owner) in changed_files.iter().zip(owners) {
+            match owner {
+                Some(id) => debug!("{file} belongs to {}", graph.metadata(&id).unwrap().name()),
+                None => debug!("{file} is outside every workspace member"),
+            }
+        }
+    }
+
+    if cli.warn_unmatched || env_flag("WARN_UNMATCHED", false) {
+        for file in options.unmatched_files(&graph, changed_files)? {
+            eprintln!(
+                "rust-affected: warning: {file} belongs to no workspace member and matches no force trigger"
+            );
+        }
+    }
+    for name in options.excluded_members(&graph) {
+        debug!("{name} is excluded");
+    }
+
+    for name in options.always_affected_conflicts(&graph) {
+        eprintln!(
+            "rust-affected: warning: {name} is in ALWAYS_AFFECTED but also excluded; excluding it"
+        );
+    }
+
+    let result = options.compute(&graph, changed_files)?;
+    Ok((graph, result))
+}
+
+/// Each workspace's directory and result, in the order given.
+type WorkspaceResults = Vec<(String, AffectedResult)>;
+
+/// The combined result for several `--workspace` directories: each changed
+/// file goes to the workspace whose directory contains it (the innermost one
+/// when they nest), with that directory stripped, and each workspace's result
+/// is grouped under its directory in `workspaces`, so members with the same
+/// name in different workspaces don't collide. `force_all` and `any_affected`
+/// are set when they are set for any workspace. Each workspace's result is
+/// also returned with its directory, for the step summary.
+fn workspaces_output(
+    location: &WorkspaceLocation,
+    cli: &CliArgs,
+    changed_files: &[String],
+    kind: Option<Kind>,
+) -> Result<(serde_json::Map<String, serde_json::Value>, WorkspaceResults), AffectedError> {
+    let dirs: Vec<String> = cli
+        .workspaces
+        .iter()
+        .map(|dir| normalize_changed_file(dir.trim()))
+        .collect();
+    let mut files: Vec<Vec<String>> = vec![Vec::new(); dirs.len()];
+    for file in changed_files {
+        let owner = dirs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, dir)| {
+                let relative = if dir.is_empty() {
+                    file.as_str()
+                } else {
+                    file.strip_prefix(dir.as_str())?.strip_prefix('/')?
+                };
+                Some((i, dir.len(), relative))
+            })
+            .max_by_key(|(_, len, _)| *len);
+        match owner {
+            Some((i, _, relative)) => files[i].push(relative.to_string()),
+            None => debug!("{file} is outside every workspace"),
+        }
+    }
+
+    let mut workspaces = serde_json::Map::new();
+    let mut results = Vec::new();
+    let mut force_all = false;
+    let mut any_affected = false;
+    for (dir, files) in dirs.iter().zip(&files) {
+        let location = WorkspaceLocation {
+            dir: PathBuf::from(if dir.is_empty() { "." } else { dir }),
+            manifest_path: None,
+            cache_dir: location.cache_dir.clone(),
+        };
+        let (_, result) =
+            affected(&location, cli, files).map_err(|e| AffectedError::Workspace {
+                dir: PathBuf::from(dir),
+                source: Box::new(e),
+            })?;
+        force_all |= result.force_all;
+        any_affected |= result.any_affected;
+        workspaces.insert(
+            dir.clone(),
+            serde_json::Value::Object(selected_outputs(&result, kind.unwrap_or(Kind::All))),
+        );
+        results.push((dir.clone(), result));
+    }
+    let mut output = serde_json::Map::new();
+    output.insert("schema_version".into(), SCHEMA_VERSION.into());
+    output.insert("force_all".into(), force_all.into());
+    output.insert("any_affected".into(), any_affected.into());
+    output.insert("workspaces".into(), workspaces.into());
+    Ok((output, results))
+}
+
+/// Write the combined output of several workspaces to one destination, chosen
+/// like in [`emit_output`].
+fn emit_workspaces(
+    output: &serde_json::Map<String, serde_json::Value>,
+    output_file: Option<&str>,
+    pretty: bool,
+) -> Result<(), AffectedError> {
+    if let Ok(path) = env::var("GITHUB_OUTPUT") {
+        let write = || -> std::io::Result<()> {
+            let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+            for (key, value) in output {
+                writeln!(file, "{key}={value}")?;
+            }
+            Ok(())
+        };
+        write().map_err(|source| AffectedError::Output {
+            path: PathBuf::from(&path),
+            source,
+        })
+    } else if let Some(path) = output_file {
+        write_atomically(Path::new(path), &format!("{}\n", to_json(output, pretty))).map_err(
+            |source| AffectedError::Output {
+                path: PathBuf::from(path),
+                source,
+            },
+        )
+    } else {
+        println!("{}", to_json(output, pretty));
+        Ok(())
+    }
+}
+
+/// The member each changed file belongs to, or `null`, and the files that
+/// belong to no member, for debugging path matching without resolving
+/// dependents. `owners` holds each file's owner, as from
+/// [`AffectedOptions::file_owners`].
+fn attribution(
+    graph: &PackageGraph,
+    changed_files: &[String],
+    owners: Vec<Option<PackageId>>,
+) -> serde_json::Value {
+    let mut files = serde_json::Map::new();
+    let mut unmatched = Vec::new();
+    for (file, owner) in changed_files.iter().zip(owners) {
+        let owner = owner.map(|id| graph.metadata(&id).unwrap().name().to_string());
+        if owner.is_none() {
+            unmatched.push(file.clone());
+        }
+        files.insert(file.clone(), owner.into());
+    }
+    serde_json::json!({ "files": files, "unmatched": unmatched })
+}
+
+/// Load the package graph without computing anything and report the member
+/// count, as a pre-flight check. Returns `false` if a member's manifest is
+/// missing; a workspace that `cargo metadata` can't parse is an error.
+fn check_workspace(location: &WorkspaceLocation) -> Result<bool, AffectedError> {
+    let graph = location.package_graph()?;
+    let workspace = graph.workspace();
+
+    let mut healthy = true;
+    for pkg in workspace.iter() {
+        if !pkg.manifest_path().exists() {
+            eprintln!(
+                "rust-affected: {}: manifest {} is missing",
+                pkg.name(),
+                pkg.manifest_path()
+            );
+            healthy = false;
+        }
+    }
+    println!(
+        "{}: {} workspace members",
+        workspace.root(),
+        workspace.member_count()
     );
+    Ok(healthy)
+}
+
+/// An [`AffectedError::InvalidInput`] for the setting `name`.
+fn invalid_input(name: &str, message: impl std::fmt::Display) -> AffectedError {
+    AffectedError::InvalidInput {
+        name: name.to_string(),
+        message: message.to_string(),
+    }
 }
 
-fn emit_output(force: bool, changed: Vec<String>, affected: Vec<String>, binaries: Vec<String>) {
-    let changed_json = serde_json::to_string(&changed).unwrap();
-    let affected_json = serde_json::to_string(&affected).unwrap();
-    let binaries_json = serde_json::to_string(&binaries).unwrap();
-    let force_str = force.to_string();
+/// Parse the environment variable `name`, or `None` when it is unset or blank.
+/// `expected` describes a valid value in the error.
+fn env_parse<T>(name: &str, expected: &str) -> Result<Option<T>, AffectedError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| invalid_input(name, format!("expected {expected}: {e}"))),
+        _ => Ok(None),
+    }
+}
+
+/// Parse the JSON in the environment variable `name`, or `None` when it is
+/// unset or blank. `expected` describes a valid value in the error.
+fn env_json<T: serde::de::DeserializeOwned>(
+    name: &str,
+    expected: &str,
+) -> Result<Option<T>, AffectedError> {
+    match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => serde_json::from_str(&value)
+            .map(Some)
+            .map_err(|e| invalid_input(name, format!("expected {expected}: {e}"))),
+        _ => Ok(None),
+    }
+}
+
+/// Read a boolean environment variable, falling back to `default` when unset or empty.
+fn env_flag(name: &str, default: bool) -> bool {
+    match env::var(name) {
+        Ok(v) if !v.trim().is_empty() => matches!(v.trim(), "true" | "1"),
+        _ => default,
+    }
+}
+
+/// Serialize to a single line of JSON, or indented when `pretty` is set.
+fn to_json<T: serde::Serialize>(value: &T, pretty: bool) -> String {
+    if pretty {
+        serde_json::to_string_pretty(value).unwrap()
+    } else {
+        serde_json::to_string(value).unwrap()
+    }
+}
+
+/// One JSON object per line: `{"force_all": ...}` first, then
+/// `{"name", "kind", "changed"}` for each affected library and binary that
+/// `selected` picks; `Kind::Changed` keeps only the directly changed ones.
+fn ndjson(result: &AffectedResult, selected: Kind) -> Vec<String> {
+    let crates = |members: &[String], kind: &'static str| {
+        members
+            .iter()
+            .filter_map(move |name| {
+                let changed = result.changed_crates.contains(name);
+                let keep = match selected {
+                    Kind::Library => kind == "library",
+                    Kind::Binary => kind == "binary",
+                    Kind::Changed => changed,
+                    Kind::All => true,
+                };
+                keep.then(|| {
+                    serde_json::json!({ "name": name, "kind": kind, "changed": changed })
+                        .to_string()
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut lines = vec![serde_json::json!({ "force_all": result.force_all }).to_string()];
+    lines.extend(crates(&result.affected_library_members, "library"));
+    lines.extend(crates(&result.affected_binary_members, "binary"));
+    lines
+}
+
+/// The crate names of the list(s) picked by `kind`, one per line, for piping
+/// into e.g. `xargs -I{} cargo build -p {}`. `Kind::All` gives the libraries
+/// and then the binaries.
+fn names(result: &AffectedResult, kind: Kind) -> Vec<String> {
+    match kind {
+        Kind::Library => result.affected_library_members.clone(),
+        Kind::Binary => result.affected_binary_members.clone(),
+        Kind::Changed => result.changed_crates.clone(),
+        Kind::All => result
+            .affected_library_members
+            .iter()
+            .chain(&result.affected_binary_members)
+            .cloned()
+            .collect(),
+    }
+}
+
+/// The result as a JSON object without the crate lists `kind` doesn't pick.
+fn selected_outputs(
+    result: &AffectedResult,
+    kind: Kind,
+) -> serde_json::Map<String, serde_json::Value> {
+    let serde_json::Value::Object(mut outputs) = to_json_value(result) else {
+        unreachable!("AffectedResult serializes to a JSON object");
+    };
+    outputs.retain(|key, _| !KIND_LISTS.contains(&key.as_str()) || kind.selects(key));
+    outputs
+}
+
+/// `KEY=value` lines for a GitLab `dotenv` report: every flag as `true`/`false`
+/// and every crate list comma-separated, under upper-case output names (e.g.
+/// `CHANGED_CRATES=app-alpha,app-beta`). Map-shaped outputs have no dotenv
+/// form and are left out, as are the crate lists `kind` doesn't pick.
+fn dotenv(result: &AffectedResult, kind: Kind) -> Vec<String> {
+    selected_outputs(result, kind)
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::Bool(flag) => flag.to_string(),
+                serde_json::Value::String(text) => text,
+                serde_json::Value::Array(items) => items
+                    .iter()
+                    .map(|item| item.as_str().map(String::from))
+                    .collect::<Option<Vec<_>>>()?
+                    .join(","),
+                _ => return None,
+            };
+            Some(format!("{}={value}", key.to_uppercase()))
+        })
+        .collect()
+}
+
+/// A `strategy.matrix` object with one `crate` entry per member, ready for
+/// `fromJson`. No members give `{}`, so the downstream job is skipped.
+fn matrix(members: &[String]) -> serde_json::Value {
+    if members.is_empty() {
+        serde_json::json!({})
+    } else {
+        serde_json::json!({ "crate": members })
+    }
+}
+
+/// Add this run to the history in `FLAP_STATE`, if set, and report the crates
+/// that flipped between affected and not affected more than `FLAP_THRESHOLD`
+/// times (default 2). A missing state file starts a new history.
+fn record_flaps(result: &mut AffectedResult) -> Result<(), AffectedError> {
+    let Ok(path) = env::var("FLAP_STATE") else {
+        return Ok(());
+    };
+    if path.trim().is_empty() {
+        return Ok(());
+    }
+    let threshold = env_parse("FLAP_THRESHOLD", "a non-negative integer")?.unwrap_or(2);
+
+    let mut state: FlapState = match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).map_err(|e| {
+            invalid_input(
+                "FLAP_STATE",
+                format!("{path} is not a valid state file: {e}"),
+            )
+        })?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => FlapState::default(),
+        Err(e) => return Err(invalid_input("FLAP_STATE", format!("{path}: {e}"))),
+    };
+    let affected: Vec<&String> = result
+        .affected_library_members
+        .iter()
+        .chain(&result.affected_binary_members)
+        .collect();
+    state.record(&affected);
+    write_atomically(
+        Path::new(&path),
+        &format!("{}\n", serde_json::to_string_pretty(&state).unwrap()),
+    )
+    .map_err(|e| invalid_input("FLAP_STATE", format!("{path}: {e}")))?;
+
+    result.flapping = state.flapping(threshold);
+    Ok(())
+}
+
+/// Write the outputs to exactly one destination: the `GITHUB_OUTPUT` keys when
+/// running in GitHub Actions, else the output file (OUTPUT_FILE or --output),
+/// else stdout. `lines`, when set, replaces the JSON object written to the file
+/// or stdout with one crate name per line; the GITHUB_OUTPUT keys and step
+/// summary are unaffected.
+fn emit_output(
+    result: &AffectedResult,
+    lines: Option<Vec<String>>,
+    kind: Option<Kind>,
+    output_file: Option<&str>,
+    pretty: bool,
+) -> Result<(), AffectedError> {
+    // The preview is for humans, so it goes to stderr and leaves stdout alone.
+    if env_flag("PREVIEW", false) {
+        eprint!("{}", preview(result));
+    }
+
+    let changed = &result.changed_crates;
+    let affected = &result.affected_library_members;
+    let binaries = &result.affected_binary_members;
+
+    // The JSON for the output file or stdout; the GITHUB_OUTPUT keys below stay
+    // compact because the runner reads them line by line.
+    let rendered = match lines {
+        Some(lines) => lines.into_iter().map(|line| line + "\n").collect(),
+        None => match env::var("BASELINE") {
+            // Only the delta from the stored state is printed.
+            Ok(baseline) if !baseline.trim().is_empty() => {
+                let baseline: serde_json::Value = serde_json::from_str(&baseline).map_err(|e| {
+                    invalid_input("BASELINE", format!("expected a JSON object: {e}"))
+                })?;
+                let current =
+                    serde_json::Value::Object(selected_outputs(result, kind.unwrap_or(Kind::All)));
+                format!("{}\n", to_json(&json_patch(&baseline, &current), pretty))
+            }
+            _ => match kind {
+                Some(kind) if kind != Kind::All => {
+                    format!("{}\n", to_json(&selected_outputs(result, kind), pretty))
+                }
+                _ => format!("{}\n", to_json(result, pretty)),
+            },
+        },
+    };
 
     // When GITHUB_OUTPUT is set (i.e. running inside a GitHub Actions runner)
     // write key=value pairs to the output file expected by the runner.
-    // Otherwise fall back to printing a JSON object to stdout for local use.
     if let Ok(path) = env::var("GITHUB_OUTPUT") {
-        let mut file = std::fs::OpenOptions::new()
-            .append(true)
-            .open(&path)
-            .expect("Failed to open GITHUB_OUTPUT");
-        writeln!(file, "changed_crates={changed_json}").unwrap();
-        writeln!(file, "affected_library_members={affected_json}").unwrap();
-        writeln!(file, "affected_binary_members={binaries_json}").unwrap();
-        writeln!(file, "force_all={force_str}").unwrap();
+        let write = || -> std::io::Result<()> {
+            let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+            let serde_json::Value::Object(outputs) = to_json_value(result) else {
+                unreachable!("AffectedResult serializes to a JSON object");
+            };
+            for (key, value) in outputs {
+                match value {
+                    serde_json::Value::String(text) => writeln!(file, "{key}={text}")?,
+                    value => writeln!(file, "{key}={value}")?,
+                }
+            }
+            // Comma-joined forms of the main lists, e.g. for `cargo build -p`,
+            // without a `fromJson` round trip.
+            for (key, members) in [
+                ("changed_crates", changed),
+                ("affected_library_members", affected),
+                ("affected_binary_members", binaries),
+            ] {
+                writeln!(file, "{key}_csv={}", members.join(","))?;
+            }
+            if env_flag("OUTPUT_MATRIX", false) {
+                writeln!(file, "matrix={}", matrix(affected))?;
+                writeln!(file, "matrix_binary={}", matrix(binaries))?;
+            }
+            Ok(())
+        };
+        write().map_err(|source| AffectedError::Output {
+            path: PathBuf::from(&path),
+            source,
+        })?;
+    } else if let Some(path) = output_file {
+        write_atomically(Path::new(path), &rendered).map_err(|source| AffectedError::Output {
+            path: PathBuf::from(path),
+            source,
+        })?;
     } else {
-        println!(
-            "{}",
-            serde_json::json!({
-                "changed_crates": changed,
-                "affected_library_members": affected,
-                "affected_binary_members": binaries,
-                "force_all": force,
-            })
-        );
+        print!("{rendered}");
     }
 
     // Write a job summary when running inside GitHub Actions.
     if let Ok(path) = env::var("GITHUB_STEP_SUMMARY") {
-        let mut file = std::fs::OpenOptions::new()
-            .append(true)
-            .open(&path)
-            .expect("Failed to open GITHUB_STEP_SUMMARY");
-
-        let fmt_inline = |items: &[String]| -> String {
-            if items.is_empty() {
-                String::new()
-            } else {
-                items
-                    .iter()
-                    .map(|s| format!("`{s}`"))
-                    .collect::<Vec<_>>()
-                    .join(" ")
+        write_step_summary(Path::new(&path), "rust-affected", result).map_err(|source| {
+            AffectedError::Output {
+                path: PathBuf::from(&path),
+                source,
             }
-        };
+        })?;
+    }
+    Ok(())
+}
 
-        let fmt_list = |items: &[String]| -> String {
-            if items.is_empty() {
-                "_none_".to_string()
-            } else {
-                items
-                    .iter()
-                    .map(|s| format!("- `{s}`"))
-                    .collect::<Vec<_>>()
-                    .join("\n")
-            }
-        };
+/// Append the job summary for `result`, headed by `title`, to the
+/// `GITHUB_STEP_SUMMARY` file.
+fn write_step_summary(path: &Path, title: &str, result: &AffectedResult) -> std::io::Result<()> {
+    let changed = &result.changed_crates;
+    let affected = &result.affected_library_members;
+    let binaries = &result.affected_binary_members;
+    let mut file = std::fs::OpenOptions::new().append(true).open(path)?;
 
-        if force {
-            writeln!(
-                file,
-                "> [!WARNING]\n> **Force all** — a global trigger file changed, entire workspace affected.\n"
-            )
-            .unwrap();
+    let fmt_inline = |items: &[String]| -> String {
+        if items.is_empty() {
+            String::new()
+        } else {
+            items
+                .iter()
+                .map(|s| format!("`{s}`"))
+                .collect::<Vec<_>>()
+                .join(" ")
         }
+    };
 
-        writeln!(file, "## rust-affected\n").unwrap();
-        writeln!(file, "| | Crates |").unwrap();
-        writeln!(file, "|---|---|").unwrap();
-        writeln!(file, "| **Changed** | {} |", fmt_inline(&changed)).unwrap();
-        writeln!(
-            file,
-            "| **Affected libraries** | {} |",
-            fmt_inline(&affected)
-        )
-        .unwrap();
-        writeln!(
-            file,
-            "| **Affected binaries** | {} |",
-            fmt_inline(&binaries)
-        )
-        .unwrap();
-        writeln!(file).unwrap();
-        writeln!(file, "### Changed crates\n{}", fmt_list(&changed)).unwrap();
-        writeln!(
-            file,
-            "\n### Affected library members\n{}",
-            fmt_list(&affected)
-        )
-        .unwrap();
+    let fmt_list = |items: &[String]| -> String {
+        if items.is_empty() {
+            "_none_".to_string()
+        } else {
+            items
+                .iter()
+                .map(|s| format!("- `{s}`"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    };
+
+    if result.force_all {
         writeln!(
             file,
-            "\n### Affected binary members\n{}",
-            fmt_list(&binaries)
+            "> [!WARNING]\n> **Force all** — a global trigger file changed, entire workspace affected.\n"
+        )?;
+    }
+
+    writeln!(file, "## {title}\n")?;
+    writeln!(file, "| | Crates |")?;
+    writeln!(file, "|---|---|")?;
+    writeln!(file, "| **Changed** | {} |", fmt_inline(changed))?;
+    writeln!(
+        file,
+        "| **Affected libraries** | {} |",
+        fmt_inline(affected)
+    )?;
+    writeln!(file, "| **Affected binaries** | {} |", fmt_inline(binaries))?;
+    writeln!(file)?;
+    writeln!(file, "### Changed crates\n{}", fmt_list(changed))?;
+    writeln!(
+        file,
+        "\n### Affected library members\n{}",
+        fmt_list(affected)
+    )?;
+    writeln!(
+        file,
+        "\n### Affected binary members\n{}",
+        fmt_list(binaries)
+    )?;
+    writeln!(file, "\n### Affected crates\n")?;
+    if affected.is_empty() && binaries.is_empty() {
+        writeln!(file, "_none_")?;
+    } else {
+        writeln!(file, "| Crate | Kind | Directly changed |")?;
+        writeln!(file, "|---|---|---|")?;
+        let rows = affected
+            .iter()
+            .map(|name| (name, "library"))
+            .chain(binaries.iter().map(|name| (name, "binary")));
+        for (name, kind) in rows {
+            let direct = if changed.contains(name) { "yes" } else { "no" };
+            writeln!(file, "| `{name}` | {kind} | {direct} |")?;
+        }
+    }
+    Ok(())
+}
+
+/// Describe in plain words what CI will run for a result: `cargo test` for each
+/// affected library and `cargo build` for each affected binary.
+fn preview(result: &AffectedResult) -> String {
+    let commands: Vec<String> = result
+        .affected_library_members
+        .iter()
+        .map(|name| format!("cargo test -p {name}"))
+        .chain(
+            result
+                .affected_binary_members
+                .iter()
+                .map(|name| format!("cargo build -p {name}")),
         )
-        .unwrap();
+        .collect();
+
+    let reason = if let Some(trigger) = &result.force_trigger_reason {
+        format!("of the force trigger {trigger}")
+    } else if let Some(cause) = &result.force_all_cause {
+        cause.clone()
+    } else if result.force_all {
+        "the whole workspace is affected".to_string()
+    } else if result.changed_crates.is_empty() {
+        return "No crates changed, so CI has nothing to run.\n".to_string();
+    } else {
+        format!("you changed {}", result.changed_crates.join(", "))
+    };
+
+    if commands.is_empty() {
+        return format!(
+            "Because {reason}, CI would run nothing: every affected crate is excluded.\n"
+        );
+    }
+    let mut text = format!("Because {reason}, CI will run:\n");
+    for command in commands {
+        text.push_str(&format!("  {command}\n"));
+    }
+    text
+}
+
+/// Replace the file at `path` with `contents` so that readers only ever see the
+/// old or the new file, never a partial write: the contents go to a temporary
+/// file in the same directory which is then renamed over `path`.
+fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::other("path has no file name"))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let written = std::fs::File::create(&tmp_path).and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|()| std::fs::rename(&tmp_path, path)) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
     }
+    Ok(())
 }
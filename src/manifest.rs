@@ -0,0 +1,108 @@
+use guppy::DependencyKind;
+use std::collections::BTreeSet;
+
+/// Keys of a member manifest that don't affect how the crate builds.
+const IGNORABLE_PACKAGE_KEYS: &[&str] = &["metadata"];
+
+/// Keys of a root manifest's `[workspace]` that don't affect how members build.
+const IGNORABLE_WORKSPACE_KEYS: &[&str] = &["metadata"];
+
+/// Dependency tables of a member manifest and the edge kind each declares.
+const DEPENDENCY_TABLES: &[(&str, DependencyKind)] = &[
+    ("dependencies", DependencyKind::Normal),
+    ("build-dependencies", DependencyKind::Build),
+    ("dev-dependencies", DependencyKind::Development),
+];
+
+fn significant(manifest: &str) -> Option<toml::Table> {
+    let mut table: toml::Table = manifest.parse().ok()?;
+    if let Some(package) = table.get_mut("package").and_then(|p| p.as_table_mut()) {
+        for key in IGNORABLE_PACKAGE_KEYS {
+            package.remove(*key);
+        }
+    }
+    if let Some(workspace) = table.get_mut("workspace").and_then(|w| w.as_table_mut()) {
+        for key in IGNORABLE_WORKSPACE_KEYS {
+            workspace.remove(*key);
+        }
+    }
+    Some(table)
+}
+
+/// The `[package] name` of a `Cargo.toml`, if it parses and has one.
+pub(crate) fn package_name(manifest: &str) -> Option<String> {
+    let table: toml::Table = manifest.parse().ok()?;
+    let name = table.get("package")?.get("name")?.as_str()?;
+    Some(name.to_string())
+}
+
+/// Whether two versions of a `Cargo.toml` differ only in formatting, comments
+/// or `[package.metadata]`. Manifests that don't parse never count as equal.
+pub(crate) fn only_ignorable_changes(base: &str, current: &str) -> bool {
+    match (significant(base), significant(current)) {
+        (Some(base), Some(current)) => base == current,
+        _ => false,
+    }
+}
+
+/// Keys of `[workspace.dependencies]` that were added, removed or changed
+/// between two versions of a root manifest, or `None` if anything else that
+/// matters changed too (or either manifest doesn't parse), since that may
+/// affect every member.
+pub(crate) fn changed_workspace_dependencies(
+    base: &str,
+    current: &str,
+) -> Option<BTreeSet<String>> {
+    let mut base = significant(base)?;
+    let mut current = significant(current)?;
+    let take_dependencies = |table: &mut toml::Table| {
+        table
+            .get_mut("workspace")
+            .and_then(|w| w.as_table_mut())
+            .and_then(|w| w.remove("dependencies"))
+            .and_then(|deps| deps.as_table().cloned())
+            .unwrap_or_default()
+    };
+    let base_deps = take_dependencies(&mut base);
+    let current_deps = take_dependencies(&mut current);
+    if base != current {
+        return None;
+    }
+    Some(
+        base_deps
+            .keys()
+            .chain(current_deps.keys())
+            .filter(|key| base_deps.get(*key) != current_deps.get(*key))
+            .cloned()
+            .collect(),
+    )
+}
+
+/// Whether a member manifest inherits one of `keys` from
+/// `[workspace.dependencies]` (`key = { workspace = true }`) in a dependency
+/// table of one of the given `kinds`, including platform-specific tables.
+pub(crate) fn inherits_any(
+    manifest: &str,
+    keys: &BTreeSet<String>,
+    kinds: &[DependencyKind],
+) -> bool {
+    let Ok(table) = manifest.parse::<toml::Table>() else {
+        return false;
+    };
+    let targets = table
+        .get("target")
+        .and_then(|t| t.as_table())
+        .into_iter()
+        .flat_map(|targets| targets.values())
+        .filter_map(|target| target.as_table());
+    std::iter::once(&table).chain(targets).any(|section| {
+        DEPENDENCY_TABLES
+            .iter()
+            .filter(|(_, kind)| kinds.contains(kind))
+            .filter_map(|(name, _)| section.get(*name).and_then(|deps| deps.as_table()))
+            .flat_map(|deps| deps.iter())
+            .any(|(key, dep)| {
+                keys.contains(key) && dep.get("workspace").and_then(|w| w.as_bool()) == Some(true)
+            })
+    })
+}
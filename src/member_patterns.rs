@@ -0,0 +1,96 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Entries in the [`crate::is_excluded`] syntax, compiled once so that
+/// matching a package costs a few set lookups and one glob set match per
+/// directory level instead of a scan of every entry.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MemberPatterns {
+    /// Entries without a `/`, compared with the crate name.
+    names: HashSet<String>,
+    /// Entries with a `/`, without trailing separators, compared with the
+    /// package's directory and each of its ancestors.
+    dirs: HashSet<String>,
+    /// The entries with glob metacharacters, from which the glob sets are
+    /// compiled.
+    globs: Vec<String>,
+    name_globs: GlobSet,
+    dir_globs: GlobSet,
+}
+
+impl MemberPatterns {
+    pub(crate) fn new<I, S>(entries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut patterns = Self::default();
+        for entry in entries {
+            patterns.insert(entry.as_ref());
+        }
+        patterns.compile();
+        patterns
+    }
+
+    /// Add one entry. A glob entry only takes effect after the next
+    /// [`MemberPatterns::compile`], so that adding many entries compiles once.
+    pub(crate) fn insert(&mut self, entry: &str) {
+        let literals = if entry.contains('/') {
+            &mut self.dirs
+        } else {
+            &mut self.names
+        };
+        let literal = entry.trim_end_matches('/');
+        if !literals.contains(literal) {
+            literals.insert(literal.to_string());
+        }
+        if entry.contains(['*', '?', '[', '{']) && !self.globs.iter().any(|glob| glob == entry) {
+            self.globs.push(entry.to_string());
+        }
+    }
+
+    /// Compile the glob entries added so far. Entries that are not valid
+    /// globs are only matched literally.
+    pub(crate) fn compile(&mut self) {
+        let mut names = GlobSetBuilder::new();
+        let mut dirs = GlobSetBuilder::new();
+        for entry in &self.globs {
+            let builder = if entry.contains('/') {
+                &mut dirs
+            } else {
+                &mut names
+            };
+            if let Ok(glob) = GlobBuilder::new(entry.trim_end_matches('/'))
+                .literal_separator(true)
+                .build()
+            {
+                builder.add(glob);
+            }
+        }
+        self.name_globs = names.build().unwrap_or_else(|_| GlobSet::empty());
+        self.dir_globs = dirs.build().unwrap_or_else(|_| GlobSet::empty());
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.dirs.is_empty()
+    }
+
+    /// Whether an entry matches the crate `name` or its directory `dir`,
+    /// relative to the workspace root.
+    pub(crate) fn matches(&self, name: &str, dir: &Path) -> bool {
+        if self.names.contains(name) || self.name_globs.is_match(name) {
+            return true;
+        }
+        // Compare in forward-slash form without trailing separators, so
+        // `tools`, `tools/` and a relative dir reported as `tools/` all agree.
+        let dir = dir.to_string_lossy().replace('\\', "/");
+        let dir = dir.trim_end_matches('/');
+        // A path entry matches the directory or any of its ancestors.
+        let mut ancestors = dir
+            .match_indices('/')
+            .map(|(i, _)| &dir[..i])
+            .chain(std::iter::once(dir));
+        ancestors.any(|dir| self.dirs.contains(dir) || self.dir_globs.is_match(dir))
+    }
+}
@@ -0,0 +1,127 @@
+use crate::AffectedError;
+use guppy::graph::PackageGraph;
+use guppy::{CargoMetadata, MetadataCommand};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+/// One cached `cargo metadata` run, with the manifests it was read from.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    /// Modification time of every manifest, in nanoseconds since the epoch.
+    manifests: BTreeMap<PathBuf, u64>,
+    /// The `cargo metadata` output.
+    metadata: serde_json::Value,
+}
+
+fn mtime(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    let nanos = modified.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    u64::try_from(nanos).ok()
+}
+
+/// The canonical root of the workspace containing `workspace_dir`, which may
+/// be a member's directory, as reported by `cargo locate-project`. Falls back
+/// to `workspace_dir` itself, where `cargo metadata` will report the error.
+fn workspace_root(workspace_dir: &Path) -> PathBuf {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let root = Command::new(cargo)
+        .args(["locate-project", "--workspace", "--message-format", "plain"])
+        .current_dir(workspace_dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| {
+            let manifest = PathBuf::from(String::from_utf8(output.stdout).ok()?.trim());
+            Some(manifest.parent()?.to_path_buf())
+        })
+        .unwrap_or_else(|| workspace_dir.to_path_buf());
+    root.canonicalize().unwrap_or(root)
+}
+
+/// The cache file for a workspace, named after the hash of its canonical
+/// root and its `Cargo.lock`, so that workspaces sharing a cache directory
+/// never share an entry.
+fn cache_path(root: &Path, cache_dir: &Path) -> PathBuf {
+    let lock = fs::read(root.join("Cargo.lock")).unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(root.as_os_str().as_encoded_bytes());
+    hasher.update([0]);
+    hasher.update(&lock);
+    cache_dir.join(format!("metadata-{:x}.json", hasher.finalize()))
+}
+
+/// The graph from a cache entry, if every manifest is unchanged.
+fn load(path: &Path) -> Option<PackageGraph> {
+    let entry: CacheEntry = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+    let unchanged = entry
+        .manifests
+        .iter()
+        .all(|(manifest, recorded)| mtime(manifest) == Some(*recorded));
+    if !unchanged {
+        return None;
+    }
+    CargoMetadata::parse_json(entry.metadata.to_string())
+        .ok()?
+        .build_graph()
+        .ok()
+}
+
+/// Write a cache entry through a temporary file, so that a concurrent reader
+/// never sees it half-written. Failures only cost the next run a cache miss.
+fn store(path: &Path, graph: &PackageGraph, metadata: serde_json::Value) {
+    let workspace = graph.workspace();
+    let manifests = workspace
+        .iter()
+        .map(|pkg| pkg.manifest_path().as_std_path().to_path_buf())
+        .chain([workspace.root().as_std_path().join("Cargo.toml")])
+        .filter_map(|manifest| Some((manifest.clone(), mtime(&manifest)?)))
+        .collect();
+    let entry = CacheEntry {
+        manifests,
+        metadata,
+    };
+
+    let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    let written = fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))
+        .and_then(|()| fs::write(&tmp_path, serde_json::to_vec(&entry)?))
+        .and_then(|()| fs::rename(&tmp_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+}
+
+/// Load the package graph for the workspace in `workspace_dir`, reusing the
+/// `cargo metadata` output cached in `cache_dir` by an earlier run.
+///
+/// Entries are keyed by the workspace root and its `Cargo.lock`, and are only
+/// used while the modification times of the root and member manifests are
+/// unchanged; otherwise `command` runs and its output replaces the entry. An
+/// unreadable or corrupt cache is treated as a miss.
+pub fn cached_package_graph(
+    command: &MetadataCommand,
+    workspace_dir: &Path,
+    cache_dir: &Path,
+) -> Result<PackageGraph, AffectedError> {
+    let root = workspace_root(workspace_dir);
+    let path = cache_path(&root, cache_dir);
+    if let Some(graph) = load(&path) {
+        return Ok(graph);
+    }
+
+    let metadata = command.exec().map_err(AffectedError::Metadata)?;
+    let mut json = Vec::new();
+    metadata
+        .serialize(&mut json)
+        .map_err(AffectedError::Metadata)?;
+    let graph = metadata.build_graph().map_err(AffectedError::Metadata)?;
+    if let Ok(json) = serde_json::from_slice(&json) {
+        // `cargo metadata` may have just created or updated the lock file.
+        store(&cache_path(&root, cache_dir), &graph, json);
+    }
+    Ok(graph)
+}
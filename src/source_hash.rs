@@ -0,0 +1,48 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directory names skipped while walking a crate: build output and VCS metadata.
+const SKIPPED_DIRS: &[&str] = &["target", ".git"];
+
+/// Collect every file below `dir`, as paths relative to `root`.
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if !SKIPPED_DIRS.iter().any(|skip| entry.file_name() == *skip) {
+                collect_files(root, &path, files)?;
+            }
+        } else if file_type.is_file() {
+            files.push(path.strip_prefix(root).unwrap_or(&path).to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Hash the contents of a crate's source tree as a hex-encoded SHA-256 digest.
+///
+/// Every file below `dir` contributes its relative path and its contents, in
+/// sorted path order, so the hash only depends on what is on disk — not on
+/// mtimes or directory iteration order. `target/` and `.git/` directories are
+/// skipped.
+pub fn source_hash(dir: &Path) -> io::Result<String> {
+    let mut files = Vec::new();
+    collect_files(dir, dir, &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for file in files {
+        let contents = fs::read(dir.join(&file))?;
+        // Length-prefix both fields so that no two trees hash the same input.
+        let name = file.to_string_lossy();
+        hasher.update((name.len() as u64).to_le_bytes());
+        hasher.update(name.as_bytes());
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -1,5 +1,5 @@
 use serde_json::Value;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 fn binary_path() -> PathBuf {
@@ -17,19 +17,92 @@ fn fixture_dir() -> PathBuf {
 }
 
 fn run_binary(envs: &[(&str, &str)]) -> (String, bool) {
+    let (stdout, _, ok) = run_binary_with_stderr(envs);
+    (stdout, ok)
+}
+
+fn run_binary_with_stderr(envs: &[(&str, &str)]) -> (String, String, bool) {
+    run_binary_in(&fixture_dir(), &[], envs)
+}
+
+fn run_binary_with_args(args: &[&str], envs: &[(&str, &str)]) -> (String, String, bool) {
+    run_binary_in(&fixture_dir(), args, envs)
+}
+
+fn run_binary_in(dir: &Path, args: &[&str], envs: &[(&str, &str)]) -> (String, String, bool) {
+    let output = binary_output(dir, args, envs);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    (stdout, stderr, output.status.success())
+}
+
+fn binary_output(dir: &Path, args: &[&str], envs: &[(&str, &str)]) -> std::process::Output {
     let mut cmd = Command::new(binary_path());
-    cmd.current_dir(fixture_dir());
+    cmd.current_dir(dir).args(args);
     // Clear env vars that could interfere
     cmd.env_remove("GITHUB_OUTPUT");
+    cmd.env_remove("GITHUB_STEP_SUMMARY");
     cmd.env_remove("CHANGED_FILES");
+    cmd.env_remove("CHANGED_FILES_PORCELAIN");
+    cmd.env_remove("PORCELAIN_RENAME_SOURCES");
     cmd.env_remove("FORCE_TRIGGERS");
+    cmd.env_remove("DEFAULT_FORCE_TRIGGERS");
     cmd.env_remove("EXCLUDED_MEMBERS");
+    cmd.env_remove("INCLUDED_MEMBERS");
+    cmd.env_remove("EXCLUDED_MEMBERS_FILE");
+    cmd.env_remove("TARGET_TRIPLE");
+    cmd.env_remove("INCLUDE_DEV_DEPENDENCIES");
+    cmd.env_remove("SPLIT_BUILD_TEST");
+    cmd.env_remove("DIFF_REFLOG");
+    cmd.env_remove("GIT_DIFF_RANGE");
+    cmd.env_remove("PRUNE_EXCLUDED");
+    cmd.env_remove("OUTPUT_FORMAT");
+    cmd.env_remove("OUTPUT_KIND");
+    cmd.env_remove("API_DIFF");
+    cmd.env_remove("EMIT_SOURCE_HASHES");
+    cmd.env_remove("CODEGEN_INPUTS");
+    cmd.env_remove("WORKSPACE_MANIFEST_PATH");
+    cmd.env_remove("MAX_DEPTH");
+    cmd.env_remove("FORCE_ALL_RATIO");
+    cmd.env_remove("RATIO_DENOMINATOR");
+    cmd.env_remove("OUTPUT_FILE");
+    cmd.env_remove("EMIT_DEPENDENCIES_OF_CHANGED");
+    cmd.env_remove("EMIT_DEGREES");
+    cmd.env_remove("EMIT_INDEXED");
+    cmd.env_remove("EMIT_EDITIONS");
+    cmd.env_remove("EMIT_TESTABLE_MEMBERS");
+    cmd.env_remove("LINTS_CHANGE_FORCES_ALL");
+    cmd.env_remove("FLAP_STATE");
+    cmd.env_remove("FLAP_THRESHOLD");
+    cmd.env_remove("OUTPUT_MATRIX");
+    cmd.env_remove("BASELINE");
+    cmd.env_remove("REBUILD_MARKER");
+    cmd.env_remove("BASE_LOCKFILE");
+    cmd.env_remove("LOCKFILE_BASE_REF");
+    cmd.env_remove("ONLY_DEFAULT_MEMBERS");
+    cmd.env_remove("ONLY_PUBLISHABLE");
+    cmd.env_remove("CASE_INSENSITIVE_PATHS");
+    cmd.env_remove("MANIFEST_BASE_REF");
+    cmd.env_remove("DEPENDENCY_KINDS");
+    cmd.env_remove("SCOPED_TRIGGERS");
+    cmd.env_remove("CHECK_WORKSPACE");
+    cmd.env_remove("DRY_RUN");
+    cmd.env_remove("WARN_UNMATCHED");
+    cmd.env_remove("PRETTY");
+    cmd.env_remove("EXIT_CODE_ON_EMPTY");
+    cmd.env_remove("RUST_AFFECTED_LOG");
+    cmd.env_remove("WORKSPACE_DIR");
+    cmd.env_remove("CACHE_DIR");
+    cmd.env_remove("WORKFLOW_CRATE_MAP");
+    cmd.env_remove("SUBMODULE_CRATE_MAP");
+    cmd.env_remove("PUBLISHED_VERSIONS");
+    cmd.env_remove("PREVIEW");
+    cmd.env_remove("ALWAYS_AFFECTED");
+    cmd.env_remove("SELECT");
     for (k, v) in envs {
         cmd.env(k, v);
     }
-    let output = cmd.output().expect("Failed to run binary");
-    let stdout = String::from_utf8(output.stdout).unwrap();
-    (stdout, output.status.success())
+    cmd.output().expect("Failed to run binary")
 }
 
 fn parse_json(stdout: &str) -> Value {
@@ -95,204 +168,2810 @@ fn env_changed_files_extra_whitespace_ignored() {
     assert_eq!(changed, vec!["app-alpha", "lib-core"]);
 }
 
-// ── FORCE_TRIGGERS parsing ──────────────────────────────────────────
+#[test]
+fn env_changed_files_with_backslashes() {
+    let (stdout, ok) = run_binary(&[("CHANGED_FILES", r"lib-core\src\lib.rs")]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["changed_crates"][0], "lib-core");
+}
 
 #[test]
-fn env_force_triggers_space_separated() {
+fn env_changed_files_porcelain_strips_status_codes() {
     let (stdout, ok) = run_binary(&[
-        ("CHANGED_FILES", "infra/deploy.yml"),
-        ("FORCE_TRIGGERS", "Cargo.lock infra/"),
+        (
+            "CHANGED_FILES",
+            "M lib-core/src/lib.rs\nR  app-alpha/src/old.rs -> app-beta/src/main.rs\n",
+        ),
+        ("CHANGED_FILES_PORCELAIN", "true"),
     ]);
     assert!(ok);
     let json = parse_json(&stdout);
-    assert_eq!(json["force_all"], true);
+    let changed: Vec<String> = serde_json::from_value(json["changed_crates"].clone()).unwrap();
+    assert_eq!(changed, vec!["app-beta", "lib-core"]);
 }
 
 #[test]
-fn env_force_triggers_newline_separated() {
+fn env_porcelain_rename_sources_keeps_both_paths() {
     let (stdout, ok) = run_binary(&[
-        ("CHANGED_FILES", "infra/deploy.yml"),
-        ("FORCE_TRIGGERS", "Cargo.lock\ninfra/"),
+        (
+            "CHANGED_FILES",
+            "R  app-alpha/src/old.rs -> app-beta/src/main.rs",
+        ),
+        ("CHANGED_FILES_PORCELAIN", "true"),
+        ("PORCELAIN_RENAME_SOURCES", "true"),
     ]);
     assert!(ok);
     let json = parse_json(&stdout);
-    assert_eq!(json["force_all"], true);
+    let changed: Vec<String> = serde_json::from_value(json["changed_crates"].clone()).unwrap();
+    assert_eq!(changed, vec!["app-alpha", "app-beta"]);
 }
 
+// ── Command-line flags ──────────────────────────────────────────────
+
 #[test]
-fn env_force_triggers_unset_means_no_force() {
-    let (stdout, ok) = run_binary(&[("CHANGED_FILES", "lib-core/src/lib.rs")]);
+fn cli_flags_replace_env_vars() {
+    let (stdout, _, ok) = run_binary_with_args(
+        &[
+            "--changed",
+            "lib-utils/src/lib.rs",
+            "--force-trigger",
+            "infra/",
+            "--exclude",
+            "tools/",
+        ],
+        &[("CHANGED_FILES", "lib-standalone/src/lib.rs")],
+    );
     assert!(ok);
     let json = parse_json(&stdout);
     assert_eq!(json["force_all"], false);
+    assert_eq!(json["changed_crates"], serde_json::json!(["lib-utils"]));
+    assert_eq!(
+        json["affected_binary_members"],
+        serde_json::json!(["app-alpha", "app-beta"])
+    );
 }
 
 #[test]
-fn env_force_triggers_trailing_slash_matches_directory() {
-    // action.yml: "A trailing slash (e.g. "infra/") matches the directory
-    // and everything inside it."
-    let (stdout, ok) = run_binary(&[
-        ("CHANGED_FILES", "infra/nested/deep/file.yml"),
-        ("FORCE_TRIGGERS", "infra/"),
-    ]);
+fn cli_repeated_flags_accumulate() {
+    let (stdout, _, ok) = run_binary_with_args(
+        &[
+            "--changed",
+            "lib-core/src/lib.rs",
+            "--changed=lib-standalone/src/lib.rs",
+            "--force-trigger",
+            "infra/",
+            "--force-trigger",
+            "Cargo.lock",
+        ],
+        &[],
+    );
     assert!(ok);
     let json = parse_json(&stdout);
-    assert_eq!(json["force_all"], true);
+    assert_eq!(
+        json["changed_crates"],
+        serde_json::json!(["lib-core", "lib-standalone"])
+    );
+    assert_eq!(json["force_all"], false);
+
+    let (stdout, _, ok) = run_binary_with_args(
+        &[
+            "--changed",
+            "Cargo.lock",
+            "--force-trigger",
+            "infra/",
+            "--force-trigger",
+            "Cargo.lock",
+        ],
+        &[],
+    );
+    assert!(ok);
+    assert_eq!(parse_json(&stdout)["force_all"], true);
 }
 
 #[test]
-fn env_force_triggers_bare_name_matches_exact_path() {
-    // action.yml: 'A bare name (e.g. "Cargo.lock") matches that exact path only.'
-    let (stdout, ok) = run_binary(&[
-        ("CHANGED_FILES", "Cargo.lock"),
-        ("FORCE_TRIGGERS", "Cargo.lock"),
-    ]);
+fn cli_env_vars_used_when_flag_absent() {
+    let (stdout, _, ok) = run_binary_with_args(
+        &["--changed", "Cargo.lock"],
+        &[("FORCE_TRIGGERS", "Cargo.lock")],
+    );
     assert!(ok);
-    let json = parse_json(&stdout);
-    assert_eq!(json["force_all"], true);
+    assert_eq!(parse_json(&stdout)["force_all"], true);
 }
 
 #[test]
-fn env_force_triggers_bare_name_no_match_on_different_file() {
-    let (stdout, ok) = run_binary(&[
-        ("CHANGED_FILES", "lib-core/src/lib.rs"),
-        ("FORCE_TRIGGERS", "Cargo.lock"),
-    ]);
+fn cli_unknown_flag_fails_with_usage() {
+    let (stdout, stderr, ok) = run_binary_with_args(&["--bogus"], &[]);
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(stderr.starts_with("rust-affected: unknown argument \"--bogus\""));
+    assert!(stderr.contains("Usage: rust-affected"));
+}
+
+#[test]
+fn cli_flag_without_value_fails() {
+    let (_, stderr, ok) = run_binary_with_args(&["--changed"], &[]);
+    assert!(!ok);
+    assert!(stderr.starts_with("rust-affected: --changed requires a value"));
+}
+
+#[test]
+fn cli_help_prints_usage() {
+    let (stdout, _, ok) = run_binary_with_args(&["--help"], &[]);
     assert!(ok);
-    let json = parse_json(&stdout);
-    assert_eq!(json["force_all"], false);
+    assert!(stdout.starts_with("Usage: rust-affected"));
 }
 
 #[test]
-fn env_force_triggers_glob_pattern() {
-    // action.yml: 'Full glob patterns are also supported (e.g. "**/*.sql", ".github/**").'
+fn env_git_diff_range_with_unknown_ref_fails_cleanly() {
+    let (stdout, stderr, ok) =
+        run_binary_with_stderr(&[("GIT_DIFF_RANGE", "no-such-ref-for-rust-affected..HEAD")]);
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(stderr.starts_with("rust-affected: git diff"), "{stderr}");
+}
+
+#[test]
+fn env_diff_reflog_git_failure_fails_cleanly() {
+    let missing = outside_dir().join("rust-affected-no-such-dir");
+    let (stdout, stderr, ok) = run_binary_with_stderr(&[
+        ("DIFF_REFLOG", "true"),
+        ("WORKSPACE_DIR", missing.to_str().unwrap()),
+    ]);
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(
+        stderr.starts_with("rust-affected: failed to read changed files from the git reflog"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"));
+}
+
+#[test]
+fn env_changed_files_win_over_git_diff_range() {
     let (stdout, ok) = run_binary(&[
-        ("CHANGED_FILES", ".github/workflows/ci.yml"),
-        ("FORCE_TRIGGERS", ".github/**"),
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("GIT_DIFF_RANGE", "no-such-ref-for-rust-affected..HEAD"),
     ]);
     assert!(ok);
+    assert_eq!(parse_json(&stdout)["changed_crates"][0], "lib-core");
+}
+
+// ── Workspace location ──────────────────────────────────────────────
+
+/// A directory outside the fixture workspace to run the binary from.
+fn outside_dir() -> PathBuf {
+    std::env::temp_dir()
+}
+
+#[test]
+fn cli_workspace_flag_points_at_another_directory() {
+    let workspace = fixture_dir();
+    let (stdout, stderr, ok) = run_binary_in(
+        &outside_dir(),
+        &["--workspace", workspace.to_str().unwrap()],
+        &[("CHANGED_FILES", "lib-core/src/lib.rs")],
+    );
+    assert!(ok, "{stderr}");
     let json = parse_json(&stdout);
-    assert_eq!(json["force_all"], true);
+    assert_eq!(json["changed_crates"], serde_json::json!(["lib-core"]));
+    assert!(
+        json["affected_binary_members"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("app-alpha"))
+    );
 }
 
-// ── EXCLUDED_MEMBERS parsing ────────────────────────────────────────
+#[test]
+fn env_workspace_dir_points_at_another_directory() {
+    let workspace = fixture_dir();
+    let (stdout, stderr, ok) = run_binary_in(
+        &outside_dir(),
+        &[],
+        &[
+            ("CHANGED_FILES", "tools/tool-alpha/src/main.rs"),
+            ("WORKSPACE_DIR", workspace.to_str().unwrap()),
+        ],
+    );
+    assert!(ok, "{stderr}");
+    // Crate directories stay relative to the chosen workspace root.
+    assert_eq!(
+        parse_json(&stdout)["changed_crates"],
+        serde_json::json!(["tool-alpha"])
+    );
+}
 
 #[test]
-fn env_excluded_members_space_separated() {
-    let (stdout, ok) = run_binary(&[
-        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
-        ("EXCLUDED_MEMBERS", "lib-core app-alpha"),
-    ]);
-    assert!(ok);
+fn cli_manifest_path_points_at_another_workspace() {
+    let manifest = fixture_dir().join("Cargo.toml");
+    let (stdout, stderr, ok) = run_binary_in(
+        &outside_dir(),
+        &[&format!("--manifest-path={}", manifest.display())],
+        &[("CHANGED_FILES", "lib-utils/src/lib.rs")],
+    );
+    assert!(ok, "{stderr}");
+    assert_eq!(
+        parse_json(&stdout)["changed_crates"],
+        serde_json::json!(["lib-utils"])
+    );
+}
+
+// ── Multiple workspaces ─────────────────────────────────────────────
+
+/// A directory holding two workspaces, `backend` and `tools`, that both have
+/// a member named `common`.
+fn multi_workspace_dir() -> PathBuf {
+    [
+        env!("CARGO_MANIFEST_DIR"),
+        "tests",
+        "fixtures",
+        "multi-workspace",
+    ]
+    .iter()
+    .collect()
+}
+
+#[test]
+fn cli_several_workspaces_group_results_by_directory() {
+    let (stdout, stderr, ok) = run_binary_in(
+        &multi_workspace_dir(),
+        &["--workspace", "backend", "--workspace", "tools/"],
+        &[("CHANGED_FILES", "backend/common/src/lib.rs README.md")],
+    );
+    assert!(ok, "{stderr}");
     let json = parse_json(&stdout);
-    let affected: Vec<String> =
-        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
-    assert!(!affected.contains(&"lib-core".to_string()));
-    assert!(!affected.contains(&"app-alpha".to_string()));
-    assert!(affected.contains(&"lib-utils".to_string()));
-    // app-beta is a binary crate — it must not appear in affected_library_members
-    assert!(!affected.contains(&"app-beta".to_string()));
+    assert_eq!(json["force_all"], false);
+    assert_eq!(json["any_affected"], true);
+
+    let backend = &json["workspaces"]["backend"];
+    assert_eq!(backend["changed_crates"], serde_json::json!(["common"]));
+    assert_eq!(
+        backend["affected_binary_members"],
+        serde_json::json!(["server"])
+    );
+    // The same crate name in the other workspace is untouched.
+    let tools = &json["workspaces"]["tools"];
+    assert_eq!(tools["changed_crates"], serde_json::json!([]));
+    assert_eq!(tools["affected_binary_members"], serde_json::json!([]));
 }
 
 #[test]
-fn env_excluded_members_newline_separated() {
-    let (stdout, ok) = run_binary(&[
-        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
-        ("EXCLUDED_MEMBERS", "lib-core\napp-alpha"),
-    ]);
-    assert!(ok);
+fn cli_several_workspaces_attribute_files_to_each() {
+    let (stdout, stderr, ok) = run_binary_in(
+        &multi_workspace_dir(),
+        &["--workspace=backend", "--workspace=tools", "--kind=binary"],
+        &[(
+            "CHANGED_FILES",
+            "backend/server/src/main.rs tools/common/src/lib.rs",
+        )],
+    );
+    assert!(ok, "{stderr}");
     let json = parse_json(&stdout);
-    let affected: Vec<String> =
-        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
-    assert!(!affected.contains(&"lib-core".to_string()));
-    assert!(!affected.contains(&"app-alpha".to_string()));
+    assert_eq!(
+        json["workspaces"],
+        serde_json::json!({
+            "backend": {
+                "schema_version": 1,
+                "force_all": false,
+                "any_affected": true,
+                "removed_crates": [],
+                "affected_binary_members": ["server"],
+                "affected_bin_targets": [{"crate": "server", "bin": "server"}],
+                "affected_build_order": [],
+                "transitively_affected_members": [],
+                "affected_proc_macro_members": [],
+                "affected_cdylib_members": [],
+                "affected_paths": {"server": "server"},
+            },
+            "tools": {
+                "schema_version": 1,
+                "force_all": false,
+                "any_affected": true,
+                "removed_crates": [],
+                "affected_binary_members": ["cli"],
+                "affected_bin_targets": [{"crate": "cli", "bin": "cli"}],
+                "affected_build_order": ["common"],
+                "transitively_affected_members": [],
+                "affected_proc_macro_members": [],
+                "affected_cdylib_members": [],
+                "affected_paths": {"cli": "cli", "common": "common"},
+            },
+        })
+    );
 }
 
 #[test]
-fn env_excluded_members_unset_means_no_exclusions() {
-    let (stdout, ok) = run_binary(&[("CHANGED_FILES", "lib-utils/src/lib.rs")]);
-    assert!(ok);
+fn cli_several_workspaces_nothing_affected() {
+    let (stdout, stderr, ok) = run_binary_in(
+        &multi_workspace_dir(),
+        &["--workspace", "backend", "--workspace", "tools"],
+        &[("CHANGED_FILES", "docs/index.md")],
+    );
+    assert!(ok, "{stderr}");
     let json = parse_json(&stdout);
-    let affected: Vec<String> =
-        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
-    assert!(affected.contains(&"lib-core".to_string()));
-    // app-alpha is a binary crate — it must not appear in affected_library_members
-    assert!(!affected.contains(&"app-alpha".to_string()));
+    assert_eq!(json["any_affected"], false);
+    assert_eq!(
+        json["workspaces"]["tools"]["changed_crates"],
+        serde_json::json!([])
+    );
 }
 
-// ── GITHUB_OUTPUT file-based output ─────────────────────────────────
+#[test]
+fn cli_several_workspaces_name_the_failing_workspace() {
+    let (stdout, stderr, ok) = run_binary_in(
+        &multi_workspace_dir(),
+        &["--workspace", "backend", "--workspace", "missing"],
+        &[(
+            "CHANGED_FILES",
+            "backend/common/src/lib.rs missing/src/lib.rs",
+        )],
+    );
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(
+        stderr.starts_with("rust-affected: workspace missing: "),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
 
 #[test]
-fn env_github_output_writes_to_file() {
-    let tmp = std::env::temp_dir().join(format!("test-github-output-{}", std::process::id()));
-    // Create the file
-    std::fs::write(&tmp, "").unwrap();
+fn cli_several_workspaces_report_unwritable_github_output() {
+    let dir = std::env::temp_dir().join(format!("test-workspaces-output-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
 
-    let (stdout, ok) = run_binary(&[
-        ("CHANGED_FILES", "app-alpha/src/main.rs"),
-        ("GITHUB_OUTPUT", tmp.to_str().unwrap()),
-    ]);
+    // A directory can't be opened for appending.
+    let (_, stderr, ok) = run_binary_in(
+        &multi_workspace_dir(),
+        &["--workspace", "backend", "--workspace", "tools"],
+        &[
+            ("CHANGED_FILES", "backend/common/src/lib.rs"),
+            ("GITHUB_OUTPUT", dir.to_str().unwrap()),
+        ],
+    );
+    assert!(!ok);
+    assert!(
+        stderr.contains("rust-affected: failed to write"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"), "{stderr}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cli_several_workspaces_require_json_output() {
+    let (_, _, ok) = run_binary_in(
+        &multi_workspace_dir(),
+        &["--workspace", "backend", "--workspace", "tools"],
+        &[
+            ("CHANGED_FILES", "backend/common/src/lib.rs"),
+            ("OUTPUT_FORMAT", "lines"),
+        ],
+    );
+    assert!(!ok);
+}
+
+#[test]
+fn cli_several_workspaces_reject_dry_run() {
+    let (stdout, stderr, ok) = run_binary_in(
+        &multi_workspace_dir(),
+        &[
+            "--workspace",
+            "backend",
+            "--workspace",
+            "tools",
+            "--dry-run",
+        ],
+        &[("CHANGED_FILES", "backend/common/src/lib.rs")],
+    );
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(stderr.contains("DRY_RUN"), "{stderr}");
+}
+
+#[test]
+fn cli_several_workspaces_write_a_summary_per_workspace() {
+    let path = std::env::temp_dir().join(format!("test-workspaces-summary-{}", std::process::id()));
+    std::fs::write(&path, "").unwrap();
+
+    let (_, stderr, ok) = run_binary_in(
+        &multi_workspace_dir(),
+        &["--workspace", "backend", "--workspace", "tools"],
+        &[
+            ("CHANGED_FILES", "backend/common/src/lib.rs"),
+            ("GITHUB_STEP_SUMMARY", path.to_str().unwrap()),
+        ],
+    );
+    assert!(ok, "{stderr}");
+    let summary = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(summary.contains("## rust-affected: backend\n"), "{summary}");
+    assert!(summary.contains("## rust-affected: tools\n"), "{summary}");
+}
+
+// ── Metadata cache ──────────────────────────────────────────────────
+
+#[test]
+fn env_cache_dir_reuses_metadata_across_runs() {
+    let cache_dir = std::env::temp_dir().join(format!("test-cache-dir-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let envs = [
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("CACHE_DIR", cache_dir.to_str().unwrap()),
+    ];
+
+    let (uncached, ok) = run_binary(&[("CHANGED_FILES", "lib-core/src/lib.rs")]);
     assert!(ok);
-    // stdout should be empty when GITHUB_OUTPUT is set
+    let (first, ok) = run_binary(&envs);
+    assert!(ok);
+    assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 1);
+    let (second, ok) = run_binary(&envs);
+    assert!(ok);
+
+    assert_eq!(first, uncached);
+    assert_eq!(second, uncached);
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+#[test]
+fn workspaces_sharing_a_cache_dir_keep_their_own_graphs() {
+    let cache_dir = std::env::temp_dir().join(format!("test-cache-shared-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let envs = [("CACHE_DIR", cache_dir.to_str().unwrap())];
+
+    // Run from member directories, which hold no Cargo.lock of their own.
+    let (stdout, stderr, ok) = run_binary_in(
+        &fixture_dir().join("lib-core"),
+        &["--check-workspace"],
+        &envs,
+    );
+    assert!(ok, "{stderr}");
     assert!(
-        stdout.trim().is_empty(),
-        "stdout should be empty when GITHUB_OUTPUT is set, got: {stdout}"
+        stdout.trim().ends_with(": 11 workspace members"),
+        "{stdout}"
     );
 
-    let contents = std::fs::read_to_string(&tmp).unwrap();
-    assert!(contents.contains("changed_crates="));
-    assert!(contents.contains("affected_library_members="));
-    assert!(contents.contains("affected_binary_members="));
-    assert!(contents.contains("force_all="));
-    // Verify the values are valid JSON arrays
-    for line in contents.lines() {
-        if let Some(value) = line.strip_prefix("changed_crates=") {
-            let parsed: Vec<String> = serde_json::from_str(value).unwrap();
-            assert_eq!(parsed, vec!["app-alpha"]);
-        }
-    }
+    let (stdout, stderr, ok) = run_binary_in(
+        &multi_workspace_dir().join("backend").join("common"),
+        &["--check-workspace"],
+        &envs,
+    );
+    assert!(ok, "{stderr}");
+    assert!(stdout.trim().ends_with(": 2 workspace members"), "{stdout}");
+    assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 2);
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
 
-    std::fs::remove_file(&tmp).ok();
+#[test]
+fn cli_cache_flag_sets_the_cache_dir() {
+    let cache_dir = std::env::temp_dir().join(format!("test-cache-flag-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache_dir);
+
+    let (_, _, ok) = run_binary_with_args(
+        &["--cache", cache_dir.to_str().unwrap()],
+        &[("CHANGED_FILES", "lib-core/src/lib.rs")],
+    );
+    assert!(ok);
+    assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&cache_dir).unwrap();
 }
 
+// ── Dry run ─────────────────────────────────────────────────────────
+
 #[test]
-fn env_github_output_appends_to_existing_content() {
-    let tmp =
-        std::env::temp_dir().join(format!("test-github-output-append-{}", std::process::id()));
-    std::fs::write(&tmp, "existing_key=existing_value\n").unwrap();
+fn cli_dry_run_maps_files_to_crates() {
+    let (stdout, stderr, ok) = run_binary_with_args(
+        &["--dry-run"],
+        &[(
+            "CHANGED_FILES",
+            "lib-core/src/lib.rs tools/tool-alpha/src/main.rs README.md",
+        )],
+    );
+    assert!(ok, "{stderr}");
+    assert_eq!(
+        parse_json(&stdout),
+        serde_json::json!({
+            "files": {
+                "lib-core/src/lib.rs": "lib-core",
+                "tools/tool-alpha/src/main.rs": "tool-alpha",
+                "README.md": null,
+            },
+            "unmatched": ["README.md"],
+        })
+    );
+}
 
-    let (_stdout, ok) = run_binary(&[
-        ("CHANGED_FILES", "app-alpha/src/main.rs"),
-        ("GITHUB_OUTPUT", tmp.to_str().unwrap()),
+#[test]
+fn env_dry_run_skips_the_affected_computation() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("DRY_RUN", "true"),
     ]);
     assert!(ok);
+    let json = parse_json(&stdout);
+    assert!(json.get("affected_library_members").is_none());
+    assert_eq!(json["unmatched"], serde_json::json!([]));
+}
 
-    let contents = std::fs::read_to_string(&tmp).unwrap();
-    // Existing content is preserved
-    assert!(contents.starts_with("existing_key=existing_value\n"));
-    // New content appended
-    assert!(contents.contains("changed_crates="));
+// ── Unmatched file warnings ─────────────────────────────────────────
 
-    std::fs::remove_file(&tmp).ok();
+#[test]
+fn cli_warn_unmatched_lists_files_matching_nothing() {
+    let (stdout, stderr, ok) = run_binary_with_args(
+        &["--warn-unmatched"],
+        &[
+            (
+                "CHANGED_FILES",
+                "Lib-Core/src/lib.rs lib-utils/src/lib.rs infra/deploy.yml",
+            ),
+            ("FORCE_TRIGGERS", "infra/"),
+        ],
+    );
+    assert!(ok);
+    assert!(
+        stderr.contains("warning: Lib-Core/src/lib.rs belongs to no workspace member"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("lib-utils/src/lib.rs"), "{stderr}");
+    assert!(!stderr.contains("infra/deploy.yml"), "{stderr}");
+    assert_eq!(parse_json(&stdout)["force_all"], true);
 }
 
-// ── JSON stdout output (no GITHUB_OUTPUT) ───────────────────────────
+#[test]
+fn unmatched_files_are_not_reported_by_default() {
+    let (_, stderr, ok) = run_binary_with_args(&[], &[("CHANGED_FILES", "Lib-Core/src/lib.rs")]);
+    assert!(ok);
+    assert!(!stderr.contains("warning"), "{stderr}");
+
+    let (_, stderr, ok) = run_binary_with_args(
+        &[],
+        &[
+            ("CHANGED_FILES", "Lib-Core/src/lib.rs"),
+            ("WARN_UNMATCHED", "true"),
+        ],
+    );
+    assert!(ok);
+    assert!(stderr.contains("Lib-Core/src/lib.rs"), "{stderr}");
+}
 
 #[test]
-fn stdout_json_contains_all_fields() {
-    let (stdout, ok) = run_binary(&[("CHANGED_FILES", "lib-core/src/lib.rs")]);
+fn warn_unmatched_follows_case_insensitive_paths_and_mappings() {
+    let (_, stderr, ok) = run_binary_with_args(
+        &["--warn-unmatched"],
+        &[
+            ("CHANGED_FILES", "Lib-Core/src/lib.rs schemas/user.json"),
+            ("CASE_INSENSITIVE_PATHS", "true"),
+            ("CODEGEN_INPUTS", r#"{"schemas/*.json": ["lib-core"]}"#),
+        ],
+    );
     assert!(ok);
-    let json = parse_json(&stdout);
-    // All four keys present
-    assert!(json.get("changed_crates").is_some());
-    assert!(json.get("affected_library_members").is_some());
-    assert!(json.get("affected_binary_members").is_some());
-    assert!(json.get("force_all").is_some());
-    // Types are correct
-    assert!(json["changed_crates"].is_array());
-    assert!(json["affected_library_members"].is_array());
-    assert!(json["affected_binary_members"].is_array());
-    assert!(json["force_all"].is_boolean());
+    assert!(!stderr.contains("warning"), "{stderr}");
+}
+
+// ── Workspace check ─────────────────────────────────────────────────
+
+#[test]
+fn cli_check_workspace_reports_member_count() {
+    let (stdout, stderr, ok) = run_binary_with_args(&["--check-workspace"], &[]);
+    assert!(ok, "{stderr}");
+    assert!(
+        stdout.trim().ends_with(": 11 workspace members"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn env_check_workspace_needs_no_changed_files() {
+    let (stdout, _, ok) = run_binary_in(
+        &[env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "editions"]
+            .iter()
+            .collect::<PathBuf>(),
+        &[],
+        &[("CHECK_WORKSPACE", "1")],
+    );
+    assert!(ok);
+    assert!(stdout.trim().ends_with(": 2 workspace members"), "{stdout}");
+}
+
+#[test]
+fn cli_check_workspace_outside_a_workspace_fails() {
+    let dir = std::env::temp_dir().join(format!("test-check-workspace-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (stdout, stderr, ok) = run_binary_in(&dir, &["--check-workspace"], &[]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(
+        stderr.starts_with("rust-affected: not a Cargo workspace"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn cli_check_workspace_takes_no_value() {
+    let (_, stderr, ok) = run_binary_with_args(&["--check-workspace=yes"], &[]);
+    assert!(!ok);
+    assert!(stderr.starts_with("rust-affected: --check-workspace takes no value"));
+}
+
+// ── Debug logging ───────────────────────────────────────────────────
+
+#[test]
+fn cli_verbose_logs_attribution_triggers_and_exclusions() {
+    let (stdout, stderr, ok) = run_binary_with_args(
+        &["-v"],
+        &[
+            ("CHANGED_FILES", "lib-core/src/lib.rs docs/notes.md"),
+            ("FORCE_TRIGGERS", "docs/"),
+            ("EXCLUDED_MEMBERS", "tools/"),
+        ],
+    );
+    assert!(ok);
+    assert!(stderr.contains("rust-affected: debug: workspace root: "));
+    assert!(stderr.contains("lib-core/src/lib.rs belongs to lib-core"));
+    assert!(stderr.contains("docs/notes.md is outside every workspace member"));
+    assert!(stderr.contains("docs/notes.md matches force trigger \"docs/\""));
+    assert!(stderr.contains("tool-alpha is excluded"));
+    // The output itself is untouched.
+    assert_eq!(parse_json(&stdout)["force_all"], true);
+}
+
+#[test]
+fn env_rust_affected_log_enables_logging() {
+    let (_, stderr, ok) = run_binary_with_stderr(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("RUST_AFFECTED_LOG", "debug"),
+    ]);
+    assert!(ok);
+    assert!(stderr.contains("lib-core/src/lib.rs belongs to lib-core"));
+}
+
+#[test]
+fn logging_is_silent_by_default() {
+    let (_, stderr, ok) = run_binary_with_stderr(&[("CHANGED_FILES", "lib-core/src/lib.rs")]);
+    assert!(ok);
+    assert!(stderr.is_empty(), "{stderr}");
+}
+
+// ── Exit code on empty results ──────────────────────────────────────
+
+/// Exit code of the binary run in the workspace fixture.
+fn exit_code_of(args: &[&str], envs: &[(&str, &str)]) -> Option<i32> {
+    binary_output(&fixture_dir(), args, envs).status.code()
+}
+
+#[test]
+fn exit_code_is_zero_by_default_when_nothing_affected() {
+    assert_eq!(
+        exit_code_of(&[], &[("CHANGED_FILES", "README.md")]),
+        Some(0)
+    );
+}
+
+#[test]
+fn cli_exit_code_flag_distinguishes_empty_results() {
+    assert_eq!(
+        exit_code_of(&["--exit-code"], &[("CHANGED_FILES", "README.md")]),
+        Some(78)
+    );
+    assert_eq!(exit_code_of(&["--exit-code"], &[]), Some(78));
+    assert_eq!(
+        exit_code_of(
+            &["--exit-code"],
+            &[("CHANGED_FILES", "lib-core/src/lib.rs")]
+        ),
+        Some(0)
+    );
+}
+
+#[test]
+fn exit_code_on_empty_differs_from_usage_error() {
+    let usage = exit_code_of(&["--bogus"], &[]);
+    let empty = exit_code_of(&["--exit-code"], &[("CHANGED_FILES", "README.md")]);
+    assert_eq!(usage, Some(2));
+    assert_ne!(usage, empty);
+}
+
+#[test]
+fn env_exit_code_on_empty_sets_the_code() {
+    assert_eq!(
+        exit_code_of(
+            &[],
+            &[("CHANGED_FILES", "README.md"), ("EXIT_CODE_ON_EMPTY", "3")]
+        ),
+        Some(3)
+    );
+}
+
+#[test]
+fn exit_code_counts_force_all_as_affected() {
+    assert_eq!(
+        exit_code_of(
+            &["--exit-code"],
+            &[
+                ("CHANGED_FILES", "README.md"),
+                ("FORCE_TRIGGERS", "README.md")
+            ]
+        ),
+        Some(0)
+    );
+}
+
+// ── FORCE_TRIGGERS parsing ──────────────────────────────────────────
+
+#[test]
+fn env_force_triggers_space_separated() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "infra/deploy.yml"),
+        ("FORCE_TRIGGERS", "Cargo.lock infra/"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], true);
+}
+
+#[test]
+fn env_force_triggers_newline_separated() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "infra/deploy.yml"),
+        ("FORCE_TRIGGERS", "Cargo.lock\ninfra/"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], true);
+}
+
+#[test]
+fn env_force_triggers_blank_lines_ignored() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("FORCE_TRIGGERS", "\n\ninfra/\n  \n"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], false);
+}
+
+#[test]
+fn env_force_triggers_limited_to_binaries() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "infra/deploy.yml"),
+        ("FORCE_TRIGGERS", "Cargo.lock infra/=>binaries"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], false);
+    assert_eq!(json["forced_kind"], "binary");
+    assert_eq!(json["affected_library_members"], serde_json::json!([]));
+    assert_eq!(
+        json["affected_binary_members"],
+        serde_json::json!(["app-alpha", "app-beta", "tool-alpha"])
+    );
+}
+
+#[test]
+fn env_force_triggers_unknown_kind_fails() {
+    let (_, stderr, ok) = run_binary_with_stderr(&[
+        ("CHANGED_FILES", "infra/deploy.yml"),
+        ("FORCE_TRIGGERS", "infra/=>services"),
+    ]);
+    assert!(!ok);
+    assert!(
+        stderr.contains("invalid member kind in force trigger"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn env_force_triggers_unset_means_no_force() {
+    let (stdout, ok) = run_binary(&[("CHANGED_FILES", "lib-core/src/lib.rs")]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], false);
+}
+
+#[test]
+fn env_force_triggers_trailing_slash_matches_directory() {
+    // action.yml: "A trailing slash (e.g. "infra/") matches the directory
+    // and everything inside it."
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "infra/nested/deep/file.yml"),
+        ("FORCE_TRIGGERS", "infra/"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], true);
+}
+
+#[test]
+fn env_force_triggers_bare_name_matches_exact_path() {
+    // action.yml: 'A bare name (e.g. "Cargo.lock") matches that exact path only.'
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "Cargo.lock"),
+        ("FORCE_TRIGGERS", "Cargo.lock"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], true);
+}
+
+#[test]
+fn env_force_triggers_bare_name_no_match_on_different_file() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("FORCE_TRIGGERS", "Cargo.lock"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], false);
+}
+
+#[test]
+fn env_force_triggers_glob_pattern() {
+    // action.yml: 'Full glob patterns are also supported (e.g. "**/*.sql", ".github/**").'
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", ".github/workflows/ci.yml"),
+        ("FORCE_TRIGGERS", ".github/**"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], true);
+}
+
+#[test]
+fn env_force_triggers_invalid_glob_fails_cleanly() {
+    let (stdout, stderr, ok) = run_binary_with_stderr(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("FORCE_TRIGGERS", "src/[a-"),
+    ]);
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(stderr.starts_with("rust-affected: invalid glob pattern \"src/[a-\""));
+    assert!(!stderr.contains("panicked"));
+}
+
+// ── DEFAULT_FORCE_TRIGGERS parsing ──────────────────────────────────
+
+#[test]
+fn default_force_triggers_apply_without_force_triggers() {
+    for file in [
+        "Cargo.lock",
+        "Cargo.toml",
+        "rust-toolchain.toml",
+        ".cargo/config.toml",
+    ] {
+        let (stdout, ok) = run_binary(&[("CHANGED_FILES", file)]);
+        assert!(ok);
+        let json = parse_json(&stdout);
+        assert_eq!(json["force_all"], true, "{file}");
+        assert_eq!(
+            json["force_trigger_reason"],
+            format!("{file} (matched {file})")
+        );
+    }
+}
+
+#[test]
+fn env_default_force_triggers_false_opts_out() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "rust-toolchain.toml"),
+        ("DEFAULT_FORCE_TRIGGERS", "false"),
+    ]);
+    assert!(ok);
+    assert_eq!(parse_json(&stdout)["force_all"], false);
+}
+
+#[test]
+fn env_default_force_triggers_false_opts_out_of_root_manifest_and_lockfile() {
+    for file in ["Cargo.lock", "Cargo.toml"] {
+        let (stdout, ok) = run_binary(&[("CHANGED_FILES", file)]);
+        assert!(ok);
+        assert_eq!(parse_json(&stdout)["force_all"], true, "{file}");
+
+        let (stdout, ok) =
+            run_binary(&[("CHANGED_FILES", file), ("DEFAULT_FORCE_TRIGGERS", "false")]);
+        assert!(ok);
+        assert_eq!(parse_json(&stdout)["force_all"], false, "{file}");
+    }
+}
+
+#[test]
+fn negative_force_trigger_vetoes_a_default() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "Cargo.lock"),
+        ("FORCE_TRIGGERS", "!Cargo.lock"),
+    ]);
+    assert!(ok);
+    assert_eq!(parse_json(&stdout)["force_all"], false);
+}
+
+// ── EXCLUDED_MEMBERS parsing ────────────────────────────────────────
+
+#[test]
+fn env_excluded_members_space_separated() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "lib-core app-alpha"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert!(!affected.contains(&"lib-core".to_string()));
+    assert!(!affected.contains(&"app-alpha".to_string()));
+    assert!(affected.contains(&"lib-utils".to_string()));
+    // app-beta is a binary crate — it must not appear in affected_library_members
+    assert!(!affected.contains(&"app-beta".to_string()));
+}
+
+#[test]
+fn env_excluded_members_newline_separated() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "lib-core\napp-alpha"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert!(!affected.contains(&"lib-core".to_string()));
+    assert!(!affected.contains(&"app-alpha".to_string()));
+}
+
+#[test]
+fn env_excluded_members_unset_means_no_exclusions() {
+    let (stdout, ok) = run_binary(&[("CHANGED_FILES", "lib-utils/src/lib.rs")]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert!(affected.contains(&"lib-core".to_string()));
+    // app-alpha is a binary crate — it must not appear in affected_library_members
+    assert!(!affected.contains(&"app-alpha".to_string()));
+}
+
+#[test]
+fn env_excluded_members_glob() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "app-*"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(
+        json["affected_library_members"],
+        serde_json::json!(["lib-core", "lib-core-ext"])
+    );
+    assert_eq!(json["affected_binary_members"], serde_json::json!([]));
+}
+
+#[test]
+fn env_excluded_members_file_with_many_entries() {
+    let path = std::env::temp_dir().join(format!("test-excluded-members-{}", std::process::id()));
+    let mut contents: String = (0..50_000)
+        .map(|i| format!("generated-crate-{i}\n"))
+        .collect();
+    contents.push_str("tools/\n");
+    std::fs::write(&path, contents).unwrap();
+
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "app-beta"),
+        ("EXCLUDED_MEMBERS_FILE", path.to_str().unwrap()),
+    ]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(
+        json["affected_binary_members"],
+        serde_json::json!(["app-alpha"])
+    );
+}
+
+#[test]
+fn env_excluded_members_file_missing_fails() {
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        (
+            "EXCLUDED_MEMBERS_FILE",
+            "/nonexistent/rust-affected-exclusions",
+        ),
+    ]);
+    assert!(!ok);
+}
+
+// ── INCLUDED_MEMBERS parsing ────────────────────────────────────────
+
+#[test]
+fn env_included_members_restrict_output() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("INCLUDED_MEMBERS", "app-alpha\ntools/"),
+        ("EXCLUDED_MEMBERS", "tool-alpha"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["changed_crates"], serde_json::json!([]));
+    assert_eq!(json["affected_library_members"], serde_json::json!([]));
+    assert_eq!(
+        json["affected_binary_members"],
+        serde_json::json!(["app-alpha"])
+    );
+}
+
+// ── ALWAYS_AFFECTED parsing ─────────────────────────────────────────
+
+#[test]
+fn env_always_affected_adds_member() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "app-alpha/src/main.rs"),
+        ("ALWAYS_AFFECTED", "lib-standalone"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert_eq!(affected, vec!["lib-standalone"]);
+}
+
+#[test]
+fn env_excluded_wins_over_always_affected_with_warning() {
+    let (stdout, stderr, ok) = run_binary_with_stderr(&[
+        ("CHANGED_FILES", "app-alpha/src/main.rs"),
+        ("ALWAYS_AFFECTED", "lib-standalone"),
+        ("EXCLUDED_MEMBERS", "lib-standalone"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert!(affected.is_empty());
+    assert!(stderr.contains("warning: lib-standalone is in ALWAYS_AFFECTED but also excluded"));
+}
+
+#[test]
+fn always_affected_conflict_with_affectedignore_names_no_input() {
+    let dir: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "tests",
+        "fixtures",
+        "affectedignore",
+    ]
+    .iter()
+    .collect();
+    // The exclusion of app comes from .affectedignore, not EXCLUDED_MEMBERS.
+    let (_, stderr, ok) = run_binary_in(
+        &dir,
+        &[],
+        &[
+            ("CHANGED_FILES", "base/src/lib.rs"),
+            ("ALWAYS_AFFECTED", "app"),
+        ],
+    );
+    assert!(ok);
+    assert!(stderr.contains("warning: app is in ALWAYS_AFFECTED but also excluded"));
+    assert!(!stderr.contains("EXCLUDED_MEMBERS"));
+}
+
+// ── LINTS_CHANGE_FORCES_ALL parsing ─────────────────────────────────
+
+#[test]
+fn env_lints_change_forces_all() {
+    // The default triggers would force a full rebuild on their own.
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "Cargo.toml"),
+        ("DEFAULT_FORCE_TRIGGERS", "false"),
+        ("LINTS_CHANGE_FORCES_ALL", "true"),
+    ]);
+    assert!(ok);
+    assert_eq!(parse_json(&stdout)["force_all"], true);
+
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "Cargo.toml"),
+        ("DEFAULT_FORCE_TRIGGERS", "false"),
+    ]);
+    assert!(ok);
+    assert_eq!(parse_json(&stdout)["force_all"], false);
+}
+
+// ── TARGET_TRIPLE parsing ───────────────────────────────────────────
+
+#[test]
+fn env_target_triple_skips_other_platform_edges() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-win-sys/src/lib.rs"),
+        ("TARGET_TRIPLE", "x86_64-unknown-linux-gnu"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert_eq!(affected, vec!["lib-win-sys"]);
+}
+
+#[test]
+fn env_target_triple_invalid_fails_cleanly() {
+    let (stdout, stderr, ok) = run_binary_with_stderr(&[
+        ("CHANGED_FILES", "lib-win-sys/src/lib.rs"),
+        ("TARGET_TRIPLE", "bogus"),
+    ]);
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(stderr.starts_with("rust-affected: invalid target triple \"bogus\""));
+    assert!(!stderr.contains("panicked"));
+}
+
+#[test]
+fn env_target_triple_unset_follows_all_edges() {
+    let (stdout, ok) = run_binary(&[("CHANGED_FILES", "lib-win-sys/src/lib.rs")]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert_eq!(affected, vec!["lib-portable", "lib-win-sys"]);
+}
+
+// ── INCLUDE_DEV_DEPENDENCIES parsing ────────────────────────────────
+
+#[test]
+fn env_include_dev_dependencies_false_skips_dev_dependents() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-test-utils/src/lib.rs"),
+        ("INCLUDE_DEV_DEPENDENCIES", "false"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert_eq!(affected, vec!["lib-test-utils"]);
+}
+
+#[test]
+fn env_include_dev_dependencies_unset_follows_dev_edges() {
+    let (stdout, ok) = run_binary(&[("CHANGED_FILES", "lib-test-utils/src/lib.rs")]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert_eq!(affected, vec!["lib-test-utils", "lib-with-tests"]);
+}
+
+// ── SPLIT_BUILD_TEST parsing ────────────────────────────────────────
+
+#[test]
+fn env_split_build_test() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-test-utils/src/lib.rs"),
+        ("SPLIT_BUILD_TEST", "true"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(
+        json["affected_build"],
+        serde_json::json!(["lib-test-utils"])
+    );
+    assert_eq!(
+        json["affected_test"],
+        serde_json::json!(["lib-test-utils", "lib-with-tests"])
+    );
+}
+
+// ── PRUNE_EXCLUDED parsing ──────────────────────────────────────────
+
+#[test]
+fn env_prune_excluded_cuts_traversal() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "lib-core"),
+        ("PRUNE_EXCLUDED", "true"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert_eq!(affected, vec!["lib-utils"]);
+    let binaries: Vec<String> =
+        serde_json::from_value(json["affected_binary_members"].clone()).unwrap();
+    assert_eq!(binaries, vec!["tool-alpha"]);
+}
+
+// ── API_DIFF parsing ────────────────────────────────────────────────
+
+#[test]
+fn env_api_diff_unchanged_api_does_not_propagate() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("API_DIFF", r#"{"lib-utils": false}"#),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert_eq!(affected, vec!["lib-utils"]);
+    let binaries: Vec<String> =
+        serde_json::from_value(json["affected_binary_members"].clone()).unwrap();
+    assert!(binaries.is_empty());
+}
+
+#[test]
+fn env_api_diff_invalid_json_fails() {
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("API_DIFF", "lib-utils=false"),
+    ]);
+    assert!(!ok);
+}
+
+// ── FORCE_ALL_RATIO / RATIO_DENOMINATOR parsing ─────────────────────
+
+#[test]
+fn env_force_all_ratio_escalates() {
+    // lib-utils affects 6 of the 11 members.
+    let envs = [
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("FORCE_ALL_RATIO", "0.5"),
+    ];
+    let (stdout, ok) = run_binary(&envs);
+    assert!(ok);
+    assert_eq!(parse_json(&stdout)["force_all"], true);
+
+    let (stdout, ok) = run_binary(&[envs[0], ("FORCE_ALL_RATIO", "0.6")]);
+    assert!(ok);
+    assert_eq!(parse_json(&stdout)["force_all"], false);
+}
+
+#[test]
+fn env_ratio_denominator_non_excluded() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        (
+            "EXCLUDED_MEMBERS",
+            "lib-standalone lib-win-sys lib-portable",
+        ),
+        ("FORCE_ALL_RATIO", "0.75"),
+        ("RATIO_DENOMINATOR", "non-excluded"),
+    ]);
+    assert!(ok);
+    assert_eq!(parse_json(&stdout)["force_all"], true);
+}
+
+#[test]
+fn env_ratio_denominator_unknown_fails() {
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("RATIO_DENOMINATOR", "some"),
+    ]);
+    assert!(!ok);
+}
+
+// ── REBUILD_MARKER parsing ──────────────────────────────────────────
+
+fn rebuild_marker_dir() -> PathBuf {
+    [
+        env!("CARGO_MANIFEST_DIR"),
+        "tests",
+        "fixtures",
+        "rebuild-marker",
+    ]
+    .iter()
+    .collect()
+}
+
+#[test]
+fn env_rebuild_marker_defaults_to_dot_rebuild() {
+    let (stdout, _, ok) = run_binary_in(
+        &rebuild_marker_dir(),
+        &[],
+        &[("CHANGED_FILES", "other/src/lib.rs")],
+    );
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(
+        json["changed_crates"],
+        serde_json::json!(["marked", "other"])
+    );
+}
+
+#[test]
+fn env_rebuild_marker_custom_name() {
+    let (stdout, _, ok) = run_binary_in(
+        &rebuild_marker_dir(),
+        &[],
+        &[
+            ("CHANGED_FILES", "other/src/lib.rs"),
+            ("REBUILD_MARKER", ".force-build"),
+        ],
+    );
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["changed_crates"], serde_json::json!(["other"]));
+}
+
+// ── BASE_LOCKFILE / LOCKFILE_BASE_REF parsing ───────────────────────
+
+#[test]
+fn env_base_lockfile_refines_lockfile_change() {
+    // Make sure `cargo metadata` has written the fixture's lockfile.
+    run_binary(&[("CHANGED_FILES", "lib-utils/src/lib.rs")]);
+    let current = std::fs::read_to_string(fixture_dir().join("Cargo.lock")).unwrap();
+    let base = std::env::temp_dir().join(format!("test-base-lockfile-{}", std::process::id()));
+    std::fs::write(
+        &base,
+        current.replace(
+            "name = \"lib-standalone\"\nversion = \"0.1.0\"",
+            "name = \"lib-standalone\"\nversion = \"0.0.9\"",
+        ),
+    )
+    .unwrap();
+
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "Cargo.lock"),
+        ("FORCE_TRIGGERS", "Cargo.lock"),
+        ("BASE_LOCKFILE", base.to_str().unwrap()),
+    ]);
+    std::fs::remove_file(&base).ok();
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], false);
+    assert_eq!(
+        json["affected_library_members"],
+        serde_json::json!(["lib-standalone"])
+    );
+    assert_eq!(
+        json["affected_binary_members"],
+        serde_json::json!(["app-beta"])
+    );
+}
+
+#[test]
+fn env_unreadable_base_lockfile_forces_all() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "Cargo.lock"),
+        ("BASE_LOCKFILE", "/nonexistent/Cargo.lock"),
+    ]);
+    assert!(ok);
+    assert_eq!(parse_json(&stdout)["force_all"], true);
+
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "Cargo.lock"),
+        ("LOCKFILE_BASE_REF", "no-such-ref"),
+    ]);
+    assert!(ok);
+    assert_eq!(parse_json(&stdout)["force_all"], true);
+}
+
+// ── ONLY_DEFAULT_MEMBERS parsing ────────────────────────────────────
+
+#[test]
+fn env_only_default_members_filters_output() {
+    let dir: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "tests",
+        "fixtures",
+        "default-members",
+    ]
+    .iter()
+    .collect();
+    let (stdout, _, ok) = run_binary_in(
+        &dir,
+        &[],
+        &[
+            ("CHANGED_FILES", "base/src/lib.rs"),
+            ("ONLY_DEFAULT_MEMBERS", "true"),
+        ],
+    );
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["affected_binary_members"], serde_json::json!(["app"]));
+}
+
+// ── ONLY_PUBLISHABLE parsing ────────────────────────────────────────
+
+#[test]
+fn env_only_publishable_drops_private_members() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("ONLY_PUBLISHABLE", "true"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    // tool-alpha has `publish = false`.
+    assert_eq!(
+        json["affected_binary_members"],
+        serde_json::json!(["app-alpha", "app-beta"])
+    );
+}
+
+// ── CASE_INSENSITIVE_PATHS parsing ──────────────────────────────────
+
+#[test]
+fn env_case_insensitive_paths_matches_mixed_case() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "Lib-Core/src/lib.rs"),
+        ("CASE_INSENSITIVE_PATHS", "true"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["changed_crates"], serde_json::json!(["lib-core"]));
+}
+
+// ── MANIFEST_BASE_REF parsing ───────────────────────────────────────
+
+#[test]
+fn env_manifest_base_ref_without_base_keeps_manifest_change() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-standalone/Cargo.toml"),
+        ("MANIFEST_BASE_REF", "no-such-ref"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(
+        json["changed_crates"],
+        serde_json::json!(["lib-standalone"])
+    );
+}
+
+// ── DEPENDENCY_KINDS parsing ────────────────────────────────────────
+
+#[test]
+fn env_dependency_kinds_without_dev_skips_dev_dependents() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-test-utils/src/lib.rs"),
+        ("DEPENDENCY_KINDS", "normal,build"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(
+        json["affected_library_members"],
+        serde_json::json!(["lib-test-utils"])
+    );
+}
+
+#[test]
+fn env_dependency_kinds_unknown_fails() {
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("DEPENDENCY_KINDS", "normal optional"),
+    ]);
+    assert!(!ok);
+}
+
+// ── Force trigger reason ────────────────────────────────────────────
+
+#[test]
+fn force_trigger_reason_in_json_and_github_output() {
+    let tmp = std::env::temp_dir().join(format!("test-trigger-reason-{}", std::process::id()));
+    std::fs::write(&tmp, "").unwrap();
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "infra/deploy.yml"),
+        ("FORCE_TRIGGERS", "infra/"),
+    ]);
+    assert!(ok);
+    assert_eq!(
+        parse_json(&stdout)["force_trigger_reason"],
+        "infra/ (matched infra/deploy.yml)"
+    );
+
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "infra/deploy.yml"),
+        ("FORCE_TRIGGERS", "infra/"),
+        ("GITHUB_OUTPUT", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+    let output = std::fs::read_to_string(&tmp).unwrap();
+    std::fs::remove_file(&tmp).ok();
+    assert!(output.contains("force_trigger_reason=infra/ (matched infra/deploy.yml)\n"));
+}
+
+// ── Config file ─────────────────────────────────────────────────────
+
+fn config_dir() -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "config"]
+        .iter()
+        .collect()
+}
+
+#[test]
+fn config_file_exclusions_apply() {
+    // affected.toml excludes tools/.
+    let (stdout, _, ok) =
+        run_binary_in(&config_dir(), &[], &[("CHANGED_FILES", "base/src/lib.rs")]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(
+        json["affected_library_members"],
+        serde_json::json!(["base"])
+    );
+}
+
+#[test]
+fn config_file_merges_with_env() {
+    let (stdout, _, ok) = run_binary_in(
+        &config_dir(),
+        &[],
+        &[
+            ("CHANGED_FILES", "ci/build.sh base/src/lib.rs"),
+            ("EXCLUDED_MEMBERS", "app"),
+        ],
+    );
+    assert!(ok);
+    let json = parse_json(&stdout);
+    // ci/** comes from the file; both exclusion lists apply.
+    assert_eq!(json["force_all"], true);
+    assert_eq!(
+        json["affected_library_members"],
+        serde_json::json!(["base"])
+    );
+    assert_eq!(json["affected_binary_members"], serde_json::json!([]));
+}
+
+#[test]
+fn config_file_codegen_inputs_mark_crates_changed() {
+    // affected.toml maps fixtures/ to base; app depends on base.
+    let (stdout, _, ok) = run_binary_in(
+        &config_dir(),
+        &[],
+        &[("CHANGED_FILES", "fixtures/golden/input.json")],
+    );
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], false);
+    assert_eq!(json["changed_crates"], serde_json::json!(["base"]));
+    assert_eq!(
+        json["affected_library_members"],
+        serde_json::json!(["base"])
+    );
+}
+
+#[test]
+fn config_file_codegen_inputs_merge_with_env() {
+    let (stdout, _, ok) = run_binary_in(
+        &config_dir(),
+        &[],
+        &[
+            ("CHANGED_FILES", "fixtures/golden/input.json docs/app.md"),
+            ("CODEGEN_INPUTS", r#"{"docs/*.md": ["app"]}"#),
+        ],
+    );
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["changed_crates"], serde_json::json!(["app", "base"]));
+}
+
+// ── .affectedignore ─────────────────────────────────────────────────
+
+#[test]
+fn affectedignore_entries_are_excluded() {
+    let dir: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "tests",
+        "fixtures",
+        "affectedignore",
+    ]
+    .iter()
+    .collect();
+    // .affectedignore lists tools/ and app, with a comment and a blank line.
+    let (stdout, _, ok) = run_binary_in(&dir, &[], &[("CHANGED_FILES", "base/src/lib.rs")]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(
+        json["affected_library_members"],
+        serde_json::json!(["base"])
+    );
+    assert_eq!(json["affected_binary_members"], serde_json::json!([]));
+}
+
+// ── SCOPED_TRIGGERS parsing ─────────────────────────────────────────
+
+#[test]
+fn env_scoped_triggers_add_mapped_crates() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "infra/app-alpha/values.yaml"),
+        (
+            "SCOPED_TRIGGERS",
+            r#"{"infra/app-alpha/**": ["app-alpha"]}"#,
+        ),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], false);
+    assert_eq!(
+        json["affected_binary_members"],
+        serde_json::json!(["app-alpha"])
+    );
+}
+
+#[test]
+fn env_scoped_triggers_invalid_json_fails() {
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "infra/app-alpha/values.yaml"),
+        ("SCOPED_TRIGGERS", "infra/app-alpha/**=app-alpha"),
+    ]);
+    assert!(!ok);
+}
+
+// ── SELECT parsing ──────────────────────────────────────────────────
+
+#[test]
+fn env_select_filters_affected_members() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("SELECT", r#"{"kind": "binary", "path": "tools/**"}"#),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(
+        json["affected_binary_members"],
+        serde_json::json!(["tool-alpha"])
+    );
+    assert_eq!(json["affected_library_members"], serde_json::json!([]));
+}
+
+#[test]
+fn env_select_invalid_fails() {
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("SELECT", r#"{"kind": "service"}"#),
+    ]);
+    assert!(!ok);
+}
+
+// ── MAX_DEPTH parsing ───────────────────────────────────────────────
+
+#[test]
+fn env_max_depth_limits_hops() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("MAX_DEPTH", "1"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let affected: Vec<String> =
+        serde_json::from_value(json["affected_library_members"].clone()).unwrap();
+    assert_eq!(affected, vec!["lib-core", "lib-utils"]);
+    let binaries: Vec<String> =
+        serde_json::from_value(json["affected_binary_members"].clone()).unwrap();
+    assert_eq!(binaries, vec!["tool-alpha"]);
+}
+
+#[test]
+fn env_max_depth_invalid_fails() {
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("MAX_DEPTH", "-1"),
+    ]);
+    assert!(!ok);
+}
+
+// ── EMIT_DEPENDENCIES_OF_CHANGED parsing ────────────────────────────
+
+#[test]
+fn env_emit_dependencies_of_changed() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("EMIT_DEPENDENCIES_OF_CHANGED", "true"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let deps: Vec<String> =
+        serde_json::from_value(json["dependencies_of_changed"].clone()).unwrap();
+    assert_eq!(deps, vec!["lib-utils"]);
+}
+
+// ── EMIT_DEGREES parsing ────────────────────────────────────────────
+
+#[test]
+fn env_emit_degrees() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("EMIT_DEGREES", "1"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["degrees"]["lib-utils"], serde_json::json!([2, 0]));
+}
+
+// ── EMIT_INDEXED parsing ────────────────────────────────────────────
+
+#[test]
+fn env_emit_indexed() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-standalone/src/lib.rs"),
+        ("EMIT_INDEXED", "true"),
+    ]);
+    assert!(ok);
+    assert_eq!(
+        parse_json(&stdout)["indexed"],
+        serde_json::json!([
+            {"index": 0, "name": "app-beta"},
+            {"index": 1, "name": "lib-standalone"},
+        ])
+    );
+}
+
+// ── EMIT_EDITIONS parsing ───────────────────────────────────────────
+
+#[test]
+fn env_emit_editions() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-standalone/src/lib.rs"),
+        ("EMIT_EDITIONS", "1"),
+    ]);
+    assert!(ok);
+    assert_eq!(
+        parse_json(&stdout)["editions"],
+        serde_json::json!({"app-beta": "2021", "lib-standalone": "2021"})
+    );
+}
+
+// ── EMIT_TESTABLE_MEMBERS parsing ───────────────────────────────────
+
+#[test]
+fn env_emit_testable_members() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-standalone/src/lib.rs"),
+        ("EMIT_TESTABLE_MEMBERS", "true"),
+    ]);
+    assert!(ok);
+    assert_eq!(
+        parse_json(&stdout)["testable_members"],
+        serde_json::json!(["app-beta", "lib-standalone"])
+    );
+}
+
+// ── WORKFLOW_CRATE_MAP parsing ──────────────────────────────────────
+
+#[test]
+fn env_workflow_crate_map_seeds_mapped_crate() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", ".github/workflows/ci-app-alpha.yml"),
+        ("FORCE_TRIGGERS", ".github/"),
+        (
+            "WORKFLOW_CRATE_MAP",
+            r#"{"ci-app-alpha.yml": ["app-alpha"]}"#,
+        ),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], false);
+    let binaries: Vec<String> =
+        serde_json::from_value(json["affected_binary_members"].clone()).unwrap();
+    assert_eq!(binaries, vec!["app-alpha"]);
+}
+
+// ── SUBMODULE_CRATE_MAP parsing ─────────────────────────────────────
+
+#[test]
+fn env_submodule_crate_map_seeds_mapped_crate() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "vendor/protocol"),
+        (
+            "SUBMODULE_CRATE_MAP",
+            r#"{"vendor/protocol": ["lib-standalone"]}"#,
+        ),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["changed_crates"][0], "lib-standalone");
+}
+
+// ── PUBLISHED_VERSIONS parsing ──────────────────────────────────────
+
+#[test]
+fn env_published_versions_flags_unbumped_crate() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-standalone/src/lib.rs"),
+        ("PUBLISHED_VERSIONS", r#"{"lib-standalone": "0.1.0"}"#),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(
+        json["needs_version_bump"],
+        serde_json::json!(["lib-standalone"])
+    );
+}
+
+#[test]
+fn env_needs_version_bump_omitted_by_default() {
+    let (stdout, ok) = run_binary(&[("CHANGED_FILES", "lib-standalone/src/lib.rs")]);
+    assert!(ok);
+    assert!(parse_json(&stdout).get("needs_version_bump").is_none());
+}
+
+// ── CODEGEN_INPUTS parsing ──────────────────────────────────────────
+
+#[test]
+fn env_codegen_inputs_seed_mapped_crates() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "schemas/user.json"),
+        ("CODEGEN_INPUTS", r#"{"schemas/*.json": ["lib-core"]}"#),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let changed: Vec<String> = serde_json::from_value(json["changed_crates"].clone()).unwrap();
+    assert_eq!(changed, vec!["lib-core"]);
+    let binaries: Vec<String> =
+        serde_json::from_value(json["affected_binary_members"].clone()).unwrap();
+    assert_eq!(binaries, vec!["app-alpha", "app-beta"]);
+}
+
+// ── EMIT_SOURCE_HASHES parsing ──────────────────────────────────────
+
+#[test]
+fn env_emit_source_hashes() {
+    let (stdout, ok) = run_binary(&[("CHANGED_FILES", "lib-standalone/src/lib.rs")]);
+    assert!(ok);
+    assert!(parse_json(&stdout).get("source_hashes").is_none());
+
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-standalone/src/lib.rs"),
+        ("EMIT_SOURCE_HASHES", "1"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    let hashes = json["source_hashes"].as_object().unwrap();
+    let names: Vec<&String> = hashes.keys().collect();
+    assert_eq!(names, vec!["app-beta", "lib-standalone"]);
+    assert_eq!(hashes["lib-standalone"].as_str().unwrap().len(), 64);
+}
+
+// ── PREVIEW ─────────────────────────────────────────────────────────
+
+#[test]
+fn env_preview_narrates_commands_on_stderr() {
+    let (stdout, stderr, ok) =
+        run_binary_with_stderr(&[("CHANGED_FILES", "lib-core/src/lib.rs"), ("PREVIEW", "1")]);
+    assert!(ok);
+    // stdout stays machine-readable.
+    assert_eq!(parse_json(&stdout)["changed_crates"][0], "lib-core");
+    assert_eq!(
+        stderr,
+        "Because you changed lib-core, CI will run:\n\
+         \x20 cargo test -p lib-core\n\
+         \x20 cargo test -p lib-core-ext\n\
+         \x20 cargo build -p app-alpha\n\
+         \x20 cargo build -p app-beta\n"
+    );
+}
+
+#[test]
+fn env_preview_names_the_force_trigger() {
+    let (_, stderr, ok) = run_binary_with_stderr(&[
+        ("CHANGED_FILES", "infra/deploy.yml"),
+        ("FORCE_TRIGGERS", "infra/"),
+        ("PREVIEW", "1"),
+    ]);
+    assert!(ok);
+    assert!(
+        stderr.starts_with(
+            "Because of the force trigger infra/ (matched infra/deploy.yml), CI will run:\n"
+        ),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn env_preview_names_the_force_all_ratio() {
+    let (stdout, stderr, ok) = run_binary_with_stderr(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("FORCE_ALL_RATIO", "0.5"),
+        ("PREVIEW", "1"),
+    ]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(json["force_all"], true);
+    assert!(json.get("force_trigger_reason").is_none());
+    assert!(
+        stderr.starts_with(
+            "Because 55% of the workspace was affected, at least the force-all ratio of 50%, CI will run:\n"
+        ),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn env_preview_without_changes() {
+    let (_, stderr, ok) = run_binary_with_stderr(&[("PREVIEW", "true")]);
+    assert!(ok);
+    assert_eq!(stderr, "No crates changed, so CI has nothing to run.\n");
+}
+
+#[test]
+fn env_preview_off_by_default() {
+    let (_, stderr, ok) = run_binary_with_stderr(&[("CHANGED_FILES", "lib-core/src/lib.rs")]);
+    assert!(ok);
+    assert!(stderr.is_empty());
+}
+
+// ── Package graph loading ───────────────────────────────────────────
+
+#[test]
+fn outside_cargo_workspace_fails_cleanly() {
+    let dir = std::env::temp_dir().join(format!("test-not-a-workspace-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let (stdout, stderr, ok) =
+        run_binary_in(&dir, &[], &[("CHANGED_FILES", "lib-core/src/lib.rs")]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(
+        stderr.starts_with("rust-affected: not a Cargo workspace or cargo metadata failed: "),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"));
+}
+
+// ── OUTPUT_FORMAT parsing ───────────────────────────────────────────
+
+#[test]
+fn env_output_format_release_order() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("OUTPUT_FORMAT", "release-order"),
+    ]);
+    assert!(ok);
+    // Dependencies first; tool-alpha is `publish = false`.
+    let order: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        order,
+        vec![
+            "lib-utils",
+            "lib-core",
+            "app-alpha",
+            "app-beta",
+            "lib-core-ext"
+        ]
+    );
+}
+
+#[test]
+fn env_output_format_unknown_fails_cleanly() {
+    let (stdout, stderr, ok) = run_binary_with_stderr(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("OUTPUT_FORMAT", "xml"),
+    ]);
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(
+        stderr.starts_with("rust-affected: invalid OUTPUT_FORMAT: unknown format \"xml\""),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"));
+}
+
+#[test]
+fn env_invalid_values_fail_cleanly() {
+    for (name, value) in [
+        ("OUTPUT_KIND", "tests"),
+        ("MAX_DEPTH", "-1"),
+        ("API_DIFF", "[true]"),
+        ("DEPENDENCY_KINDS", "normal runtime"),
+        ("EXIT_CODE_ON_EMPTY", "never"),
+    ] {
+        let (_, stderr, ok) =
+            run_binary_with_stderr(&[("CHANGED_FILES", "lib-utils/src/lib.rs"), (name, value)]);
+        assert!(!ok, "{name}");
+        assert!(
+            stderr.starts_with(&format!("rust-affected: invalid {name}: ")),
+            "{stderr}"
+        );
+        assert!(!stderr.contains("panicked"), "{stderr}");
+    }
+}
+
+#[test]
+fn env_output_format_release_order_without_changes_is_empty() {
+    let (stdout, ok) = run_binary(&[("CHANGED_FILES", ""), ("OUTPUT_FORMAT", "release-order")]);
+    assert!(ok);
+    assert_eq!(stdout, "");
+}
+
+#[test]
+fn env_output_format_workspace_manifest() {
+    let tmp = std::env::temp_dir().join(format!("test-workspace-manifest-{}", std::process::id()));
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("OUTPUT_FORMAT", "workspace-manifest"),
+        ("WORKSPACE_MANIFEST_PATH", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+    // The JSON output is still printed.
+    assert_eq!(parse_json(&stdout)["changed_crates"][0], "lib-core");
+
+    let manifest: toml::Table = std::fs::read_to_string(&tmp).unwrap().parse().unwrap();
+    std::fs::remove_file(&tmp).unwrap();
+    let members = manifest["workspace"]["members"].as_array().unwrap();
+    let members: Vec<&str> = members.iter().map(|m| m.as_str().unwrap()).collect();
+    assert_eq!(
+        members,
+        vec!["app-alpha", "app-beta", "lib-core", "lib-core-ext"]
+    );
+}
+
+#[test]
+fn env_output_format_workspace_manifest_without_changes() {
+    let tmp = std::env::temp_dir().join(format!(
+        "test-workspace-manifest-empty-{}",
+        std::process::id()
+    ));
+    let (_, ok) = run_binary(&[
+        ("OUTPUT_FORMAT", "workspace-manifest"),
+        ("WORKSPACE_MANIFEST_PATH", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+
+    let manifest: toml::Table = std::fs::read_to_string(&tmp).unwrap().parse().unwrap();
+    std::fs::remove_file(&tmp).unwrap();
+    assert!(
+        manifest["workspace"]["members"]
+            .as_array()
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn env_output_format_workspace_manifest_requires_path() {
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("OUTPUT_FORMAT", "workspace-manifest"),
+    ]);
+    assert!(!ok);
+}
+
+#[test]
+fn env_output_format_workspace_manifest_unwritable_path_is_an_error() {
+    let path = std::env::temp_dir()
+        .join(format!("missing-dir-{}", std::process::id()))
+        .join("Cargo.toml");
+    let (_, stderr, ok) = run_binary_in(
+        &fixture_dir(),
+        &[],
+        &[
+            ("CHANGED_FILES", "lib-core/src/lib.rs"),
+            ("OUTPUT_FORMAT", "workspace-manifest"),
+            ("WORKSPACE_MANIFEST_PATH", path.to_str().unwrap()),
+        ],
+    );
+    assert!(!ok);
+    assert!(stderr.contains("failed to write"), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn env_output_format_ndjson() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "lib-core-ext"),
+        ("OUTPUT_FORMAT", "ndjson"),
+    ]);
+    assert!(ok);
+    let lines: Vec<serde_json::Value> = stdout.lines().map(parse_json).collect();
+    assert_eq!(
+        lines,
+        vec![
+            serde_json::json!({"force_all": false}),
+            serde_json::json!({"name": "lib-core", "kind": "library", "changed": true}),
+            serde_json::json!({"name": "app-alpha", "kind": "binary", "changed": false}),
+            serde_json::json!({"name": "app-beta", "kind": "binary", "changed": false}),
+        ]
+    );
+}
+
+#[test]
+fn env_output_format_ndjson_without_changes() {
+    let (stdout, ok) = run_binary(&[("OUTPUT_FORMAT", "ndjson")]);
+    assert!(ok);
+    assert_eq!(stdout, "{\"force_all\":false}\n");
+}
+
+#[test]
+fn env_output_format_dotenv() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "lib-core-ext"),
+        ("OUTPUT_FORMAT", "dotenv"),
+        ("EMIT_DEGREES", "true"),
+    ]);
+    assert!(ok);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines.contains(&"FORCE_ALL=false"));
+    assert!(lines.contains(&"CHANGED_CRATES=lib-core"));
+    assert!(lines.contains(&"AFFECTED_LIBRARY_MEMBERS=lib-core"));
+    assert!(lines.contains(&"AFFECTED_BINARY_MEMBERS=app-alpha,app-beta"));
+    // Map-shaped outputs have no dotenv form.
+    assert!(!stdout.contains("DEGREES="));
+}
+
+#[test]
+fn env_output_format_dotenv_without_changes() {
+    let (stdout, ok) = run_binary(&[("OUTPUT_FORMAT", "dotenv")]);
+    assert!(ok);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines.contains(&"FORCE_ALL=false"));
+    assert!(lines.contains(&"CHANGED_CRATES="));
+}
+
+#[test]
+fn env_output_format_lines() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "lib-core-ext"),
+        ("OUTPUT_FORMAT", "lines"),
+    ]);
+    assert!(ok);
+    assert_eq!(stdout, "lib-core\n");
+}
+
+#[test]
+fn env_output_format_lines_binaries() {
+    let (stdout, _, ok) = run_binary_with_args(
+        &["--kind", "binary"],
+        &[
+            ("CHANGED_FILES", "lib-core/src/lib.rs"),
+            ("OUTPUT_FORMAT", "lines"),
+        ],
+    );
+    assert!(ok);
+    assert_eq!(stdout, "app-alpha\napp-beta\n");
+}
+
+#[test]
+fn env_output_format_lines_without_changes() {
+    let (stdout, ok) = run_binary(&[("OUTPUT_FORMAT", "lines")]);
+    assert!(ok);
+    assert_eq!(stdout, "");
+}
+
+#[test]
+fn cli_unknown_kind_fails() {
+    let (_, stderr, ok) = run_binary_with_args(&["--kind", "tests"], &[]);
+    assert!(!ok);
+    assert!(stderr.starts_with("rust-affected: unknown --kind \"tests\""));
+}
+
+// ── OUTPUT_KIND ─────────────────────────────────────────────────────
+
+#[test]
+fn kind_binary_json_emits_only_binaries() {
+    let (stdout, _, ok) = run_binary_with_args(
+        &["--kind", "binary"],
+        &[("CHANGED_FILES", "lib-core/src/lib.rs")],
+    );
+    assert!(ok);
+    let json = parse_json(&stdout);
+    assert_eq!(
+        json["affected_binary_members"],
+        serde_json::json!(["app-alpha", "app-beta"])
+    );
+    assert!(json.get("affected_library_members").is_none());
+    assert!(json.get("changed_crates").is_none());
+    // Other outputs are kept.
+    assert_eq!(json["force_all"], false);
+}
+
+#[test]
+fn kind_all_json_is_unchanged() {
+    let (all, _, ok) =
+        run_binary_with_args(&["--kind=all"], &[("CHANGED_FILES", "lib-core/src/lib.rs")]);
+    assert!(ok);
+    let (default, ok) = run_binary(&[("CHANGED_FILES", "lib-core/src/lib.rs")]);
+    assert!(ok);
+    assert_eq!(all, default);
+}
+
+#[test]
+fn env_output_kind_changed_lines() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs app-alpha/src/main.rs"),
+        ("OUTPUT_FORMAT", "lines"),
+        ("OUTPUT_KIND", "changed"),
+    ]);
+    assert!(ok);
+    assert_eq!(stdout, "app-alpha\nlib-core\n");
+}
+
+#[test]
+fn env_output_kind_all_lines() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "lib-core-ext"),
+        ("OUTPUT_FORMAT", "lines"),
+        ("OUTPUT_KIND", "all"),
+    ]);
+    assert!(ok);
+    assert_eq!(stdout, "lib-core\napp-alpha\napp-beta\n");
+}
+
+#[test]
+fn env_output_kind_binary_ndjson() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("OUTPUT_FORMAT", "ndjson"),
+        ("OUTPUT_KIND", "binary"),
+    ]);
+    assert!(ok);
+    let lines: Vec<serde_json::Value> = stdout.lines().map(parse_json).collect();
+    assert_eq!(
+        lines,
+        vec![
+            serde_json::json!({"force_all": false}),
+            serde_json::json!({"name": "app-alpha", "kind": "binary", "changed": false}),
+            serde_json::json!({"name": "app-beta", "kind": "binary", "changed": false}),
+        ]
+    );
+}
+
+#[test]
+fn env_output_kind_changed_ndjson() {
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("OUTPUT_FORMAT", "ndjson"),
+        ("OUTPUT_KIND", "changed"),
+    ]);
+    assert!(ok);
+    let lines: Vec<serde_json::Value> = stdout.lines().map(parse_json).collect();
+    assert_eq!(
+        lines,
+        vec![
+            serde_json::json!({"force_all": false}),
+            serde_json::json!({"name": "lib-core", "kind": "library", "changed": true}),
+        ]
+    );
+}
+
+#[test]
+fn cli_kind_overrides_env() {
+    let (stdout, _, ok) = run_binary_with_args(
+        &["--kind", "library"],
+        &[
+            ("CHANGED_FILES", "lib-core/src/lib.rs"),
+            ("EXCLUDED_MEMBERS", "lib-core-ext"),
+            ("OUTPUT_FORMAT", "lines"),
+            ("OUTPUT_KIND", "binary"),
+        ],
+    );
+    assert!(ok);
+    assert_eq!(stdout, "lib-core\n");
+}
+
+#[test]
+fn env_output_kind_unknown_fails() {
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("OUTPUT_KIND", "tests"),
+    ]);
+    assert!(!ok);
+}
+
+#[test]
+fn env_output_format_unknown_fails() {
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-utils/src/lib.rs"),
+        ("OUTPUT_FORMAT", "yaml"),
+    ]);
+    assert!(!ok);
+}
+
+// ── FLAP_STATE ──────────────────────────────────────────────────────
+
+#[test]
+fn env_flap_state_flags_alternating_crate() {
+    let path = std::env::temp_dir().join(format!("test-flap-state-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let state = path.to_str().unwrap();
+
+    let mut flapping = Vec::new();
+    for changed in [
+        "lib-standalone/src/lib.rs",
+        "app-alpha/src/main.rs",
+        "lib-standalone/src/lib.rs",
+        "app-alpha/src/main.rs",
+    ] {
+        let (stdout, ok) = run_binary(&[
+            ("CHANGED_FILES", changed),
+            ("FLAP_STATE", state),
+            ("FLAP_THRESHOLD", "2"),
+        ]);
+        assert!(ok);
+        flapping.push(parse_json(&stdout)["flapping"].clone());
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    // After four alternating runs lib-standalone and app-beta have flipped
+    // three times; app-alpha, first affected in the second run, only twice.
+    assert_eq!(flapping[0], Value::Null);
+    assert_eq!(flapping[2], Value::Null);
+    assert_eq!(
+        flapping[3],
+        serde_json::json!(["app-beta", "lib-standalone"])
+    );
+}
+
+// ── BASELINE ────────────────────────────────────────────────────────
+
+#[test]
+fn env_baseline_prints_json_patch() {
+    let (baseline, ok) = run_binary(&[("CHANGED_FILES", "lib-utils/src/lib.rs")]);
+    assert!(ok);
+
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "app-alpha/src/main.rs"),
+        ("BASELINE", baseline.trim()),
+    ]);
+    assert!(ok);
+    let patch = parse_json(&stdout);
+    assert!(patch.as_array().unwrap().contains(&serde_json::json!({
+        "op": "replace",
+        "path": "/changed_crates",
+        "value": ["app-alpha"],
+    })));
+}
+
+#[test]
+fn env_baseline_unchanged_is_empty_patch() {
+    let (baseline, ok) = run_binary(&[("CHANGED_FILES", "lib-core/src/lib.rs")]);
+    assert!(ok);
+
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("BASELINE", baseline.trim()),
+    ]);
+    assert!(ok);
+    assert_eq!(parse_json(&stdout), serde_json::json!([]));
+}
+
+#[test]
+fn env_baseline_invalid_json_fails() {
+    let (_, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("BASELINE", "not json"),
+    ]);
+    assert!(!ok);
+}
+
+// ── OUTPUT_FILE ─────────────────────────────────────────────────────
+
+#[test]
+fn env_output_file_replaces_stdout() {
+    let dir = std::env::temp_dir().join(format!("test-output-file-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("affected.json");
+    std::fs::write(&path, "stale contents that are longer than nothing").unwrap();
+
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("OUTPUT_FILE", path.to_str().unwrap()),
+    ]);
+    assert!(ok);
+    assert!(stdout.is_empty());
+
+    let json = parse_json(&std::fs::read_to_string(&path).unwrap());
+    assert_eq!(json["changed_crates"][0], "lib-core");
+    // Only the output file is left behind, no temporary files.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn env_output_file_in_missing_directory_is_an_error() {
+    let path = std::env::temp_dir()
+        .join(format!("test-output-file-missing-{}", std::process::id()))
+        .join("affected.json");
+
+    let (stdout, stderr, ok) = run_binary_in(
+        &fixture_dir(),
+        &[],
+        &[
+            ("CHANGED_FILES", "lib-core/src/lib.rs"),
+            ("OUTPUT_FILE", path.to_str().unwrap()),
+        ],
+    );
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(stderr.contains("failed to write"), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn cli_output_flag_replaces_output_file_env() {
+    let dir = std::env::temp_dir().join(format!("test-output-flag-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let flag_path = dir.join("flag.json");
+    let env_path = dir.join("env.json");
+
+    let (stdout, _, ok) = run_binary_with_args(
+        &["--output", flag_path.to_str().unwrap()],
+        &[
+            ("CHANGED_FILES", "lib-core/src/lib.rs"),
+            ("OUTPUT_FILE", env_path.to_str().unwrap()),
+        ],
+    );
+    assert!(ok);
+    assert!(stdout.is_empty());
+
+    let json = parse_json(&std::fs::read_to_string(&flag_path).unwrap());
+    assert_eq!(json["changed_crates"][0], "lib-core");
+    assert!(!env_path.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn github_output_takes_precedence_over_output_file() {
+    let dir = std::env::temp_dir().join(format!("test-output-precedence-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let github_output = dir.join("github_output");
+    std::fs::write(&github_output, "").unwrap();
+    let path = dir.join("affected.json");
+
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("GITHUB_OUTPUT", github_output.to_str().unwrap()),
+        ("OUTPUT_FILE", path.to_str().unwrap()),
+    ]);
+    assert!(ok);
+    assert!(stdout.is_empty());
+    assert!(!path.exists());
+    let written = std::fs::read_to_string(&github_output).unwrap();
+    assert!(written.contains("changed_crates_csv=lib-core\n"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn env_output_file_concurrent_writers_leave_complete_json() {
+    let dir = std::env::temp_dir().join(format!("test-output-file-race-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("affected.json");
+
+    for _ in 0..5 {
+        let writers: Vec<_> = ["lib-utils/src/lib.rs", "lib-standalone/src/lib.rs"]
+            .into_iter()
+            .map(|changed| {
+                Command::new(binary_path())
+                    .current_dir(fixture_dir())
+                    .env_remove("GITHUB_OUTPUT")
+                    .env("CHANGED_FILES", changed)
+                    .env("OUTPUT_FILE", &path)
+                    .spawn()
+                    .expect("Failed to spawn binary")
+            })
+            .collect();
+        for mut writer in writers {
+            assert!(writer.wait().unwrap().success());
+        }
+
+        let json = parse_json(&std::fs::read_to_string(&path).unwrap());
+        let changed = json["changed_crates"][0].as_str().unwrap();
+        assert!(changed == "lib-utils" || changed == "lib-standalone");
+    }
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// ── GITHUB_OUTPUT file-based output ─────────────────────────────────
+
+#[test]
+fn env_missing_github_output_is_an_error() {
+    let path = std::env::temp_dir()
+        .join(format!("test-github-output-missing-{}", std::process::id()))
+        .join("github_output");
+
+    let (_, stderr, ok) = run_binary_in(
+        &fixture_dir(),
+        &[],
+        &[
+            ("CHANGED_FILES", "lib-core/src/lib.rs"),
+            ("GITHUB_OUTPUT", path.to_str().unwrap()),
+        ],
+    );
+    assert!(!ok);
+    assert!(stderr.contains("failed to write"), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn env_missing_github_step_summary_is_an_error() {
+    let path = std::env::temp_dir()
+        .join(format!("test-step-summary-missing-{}", std::process::id()))
+        .join("summary.md");
+
+    let (_, stderr, ok) = run_binary_in(
+        &fixture_dir(),
+        &[],
+        &[
+            ("CHANGED_FILES", "lib-core/src/lib.rs"),
+            ("GITHUB_STEP_SUMMARY", path.to_str().unwrap()),
+        ],
+    );
+    assert!(!ok);
+    assert!(stderr.contains("failed to write"), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn env_github_output_writes_to_file() {
+    let tmp = std::env::temp_dir().join(format!("test-github-output-{}", std::process::id()));
+    // Create the file
+    std::fs::write(&tmp, "").unwrap();
+
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "app-alpha/src/main.rs"),
+        ("GITHUB_OUTPUT", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+    // stdout should be empty when GITHUB_OUTPUT is set
+    assert!(
+        stdout.trim().is_empty(),
+        "stdout should be empty when GITHUB_OUTPUT is set, got: {stdout}"
+    );
+
+    let contents = std::fs::read_to_string(&tmp).unwrap();
+    assert!(contents.contains("changed_crates="));
+    assert!(contents.contains("affected_library_members="));
+    assert!(contents.contains("affected_binary_members="));
+    assert!(contents.contains("force_all="));
+    // Verify the values are valid JSON arrays
+    for line in contents.lines() {
+        if let Some(value) = line.strip_prefix("changed_crates=") {
+            let parsed: Vec<String> = serde_json::from_str(value).unwrap();
+            assert_eq!(parsed, vec!["app-alpha"]);
+        }
+    }
+
+    std::fs::remove_file(&tmp).ok();
+}
+
+#[test]
+fn env_github_output_appends_to_existing_content() {
+    let tmp =
+        std::env::temp_dir().join(format!("test-github-output-append-{}", std::process::id()));
+    std::fs::write(&tmp, "existing_key=existing_value\n").unwrap();
+
+    let (_stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "app-alpha/src/main.rs"),
+        ("GITHUB_OUTPUT", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+
+    let contents = std::fs::read_to_string(&tmp).unwrap();
+    // Existing content is preserved
+    assert!(contents.starts_with("existing_key=existing_value\n"));
+    // New content appended
+    assert!(contents.contains("changed_crates="));
+
+    std::fs::remove_file(&tmp).ok();
+}
+
+#[test]
+fn env_github_output_writes_comma_joined_lists() {
+    let tmp = std::env::temp_dir().join(format!("test-github-output-csv-{}", std::process::id()));
+    std::fs::write(&tmp, "").unwrap();
+
+    let (_stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "lib-core-ext"),
+        ("GITHUB_OUTPUT", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+
+    let contents = std::fs::read_to_string(&tmp).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert!(lines.contains(&"changed_crates_csv=lib-core"));
+    assert!(lines.contains(&"affected_library_members_csv=lib-core"));
+    assert!(lines.contains(&"affected_binary_members_csv=app-alpha,app-beta"));
+    // The JSON forms are unchanged.
+    assert!(lines.contains(&"changed_crates=[\"lib-core\"]"));
+
+    std::fs::remove_file(&tmp).ok();
+}
+
+#[test]
+fn env_github_output_comma_joined_lists_can_be_empty() {
+    let tmp = std::env::temp_dir().join(format!(
+        "test-github-output-csv-empty-{}",
+        std::process::id()
+    ));
+    std::fs::write(&tmp, "").unwrap();
+
+    let (_stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "README.md"),
+        ("GITHUB_OUTPUT", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+
+    let contents = std::fs::read_to_string(&tmp).unwrap();
+    assert!(
+        contents
+            .lines()
+            .any(|line| line == "affected_binary_members_csv=")
+    );
+
+    std::fs::remove_file(&tmp).ok();
+}
+
+// ── OUTPUT_MATRIX ───────────────────────────────────────────────────
+
+/// The value written for `key` in a GITHUB_OUTPUT file, if any.
+fn github_output_value(contents: &str, key: &str) -> Option<serde_json::Value> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("{key}=")))
+        .map(|value| serde_json::from_str(value).unwrap())
+}
+
+#[test]
+fn env_output_matrix_writes_matrix_objects() {
+    let tmp = std::env::temp_dir().join(format!("test-output-matrix-{}", std::process::id()));
+    std::fs::write(&tmp, "").unwrap();
+
+    let (_stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "lib-core-ext"),
+        ("OUTPUT_MATRIX", "true"),
+        ("GITHUB_OUTPUT", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+
+    let contents = std::fs::read_to_string(&tmp).unwrap();
+    assert_eq!(
+        github_output_value(&contents, "matrix"),
+        Some(serde_json::json!({"crate": ["lib-core"]}))
+    );
+    assert_eq!(
+        github_output_value(&contents, "matrix_binary"),
+        Some(serde_json::json!({"crate": ["app-alpha", "app-beta"]}))
+    );
+
+    std::fs::remove_file(&tmp).ok();
+}
+
+#[test]
+fn env_output_matrix_empty_is_empty_object() {
+    let tmp = std::env::temp_dir().join(format!("test-output-matrix-empty-{}", std::process::id()));
+    std::fs::write(&tmp, "").unwrap();
+
+    let (_stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "README.md"),
+        ("OUTPUT_MATRIX", "true"),
+        ("GITHUB_OUTPUT", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+
+    let contents = std::fs::read_to_string(&tmp).unwrap();
+    assert_eq!(
+        github_output_value(&contents, "matrix"),
+        Some(serde_json::json!({}))
+    );
+    assert_eq!(
+        github_output_value(&contents, "matrix_binary"),
+        Some(serde_json::json!({}))
+    );
+
+    std::fs::remove_file(&tmp).ok();
+}
+
+#[test]
+fn env_output_matrix_unset_writes_no_matrix() {
+    let tmp = std::env::temp_dir().join(format!("test-output-matrix-unset-{}", std::process::id()));
+    std::fs::write(&tmp, "").unwrap();
+
+    let (_stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("GITHUB_OUTPUT", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+
+    let contents = std::fs::read_to_string(&tmp).unwrap();
+    assert_eq!(github_output_value(&contents, "matrix"), None);
+
+    std::fs::remove_file(&tmp).ok();
+}
+
+// ── GITHUB_STEP_SUMMARY ─────────────────────────────────────────────
+
+#[test]
+fn env_step_summary_lists_affected_crates() {
+    let tmp = std::env::temp_dir().join(format!("test-step-summary-{}", std::process::id()));
+    std::fs::write(&tmp, "").unwrap();
+
+    let (_stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "lib-core/src/lib.rs"),
+        ("EXCLUDED_MEMBERS", "lib-core-ext"),
+        ("GITHUB_STEP_SUMMARY", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+
+    let contents = std::fs::read_to_string(&tmp).unwrap();
+    // The lists come first and the table is appended after them.
+    let lists = contents.find("### Changed crates\n- `lib-core`\n").unwrap();
+    assert!(contents.contains("### Affected binary members\n- `app-alpha`\n- `app-beta`\n"));
+    let table = contents
+        .find("| Crate | Kind | Directly changed |")
+        .unwrap();
+    assert!(lists < table);
+    assert!(contents.contains("| `lib-core` | library | yes |"));
+    assert!(contents.contains("| `app-alpha` | binary | no |"));
+    assert!(contents.contains("| `app-beta` | binary | no |"));
+    assert!(!contents.contains("lib-utils"));
+
+    std::fs::remove_file(&tmp).ok();
+}
+
+#[test]
+fn env_step_summary_without_affected_crates() {
+    let tmp = std::env::temp_dir().join(format!("test-step-summary-empty-{}", std::process::id()));
+    std::fs::write(&tmp, "").unwrap();
+
+    let (stdout, ok) = run_binary(&[
+        ("CHANGED_FILES", "README.md"),
+        ("GITHUB_STEP_SUMMARY", tmp.to_str().unwrap()),
+    ]);
+    assert!(ok);
+    // The summary is separate from the machine-readable output.
+    assert_eq!(parse_json(&stdout)["changed_crates"], serde_json::json!([]));
+
+    let contents = std::fs::read_to_string(&tmp).unwrap();
+    assert!(contents.contains("### Affected crates\n\n_none_"));
+    assert!(!contents.contains("| Crate |"));
+
+    std::fs::remove_file(&tmp).ok();
+}
+
+// ── JSON stdout output (no GITHUB_OUTPUT) ───────────────────────────
+
+#[test]
+fn stdout_json_contains_all_fields() {
+    let (stdout, ok) = run_binary(&[("CHANGED_FILES", "lib-core/src/lib.rs")]);
+    assert!(ok);
+    let json = parse_json(&stdout);
+    // All four keys present
+    assert!(json.get("changed_crates").is_some());
+    assert!(json.get("affected_library_members").is_some());
+    assert!(json.get("affected_binary_members").is_some());
+    assert!(json.get("force_all").is_some());
+    // Types are correct
+    assert!(json["changed_crates"].is_array());
+    assert!(json["affected_library_members"].is_array());
+    assert!(json["affected_binary_members"].is_array());
+    assert!(json["force_all"].is_boolean());
+}
+
+// ── PRETTY ──────────────────────────────────────────────────────────
+
+#[test]
+fn env_pretty_indents_stdout_json() {
+    let (compact, ok) = run_binary(&[("CHANGED_FILES", "lib-core/src/lib.rs")]);
+    assert!(ok);
+    assert_eq!(compact.lines().count(), 1);
+
+    let (pretty, ok) = run_binary(&[("CHANGED_FILES", "lib-core/src/lib.rs"), ("PRETTY", "true")]);
+    assert!(ok);
+    assert!(pretty.lines().count() > 1);
+    assert!(pretty.contains("\n  \"force_all\": false"));
+    assert_eq!(parse_json(&pretty), parse_json(&compact));
+}
+
+#[test]
+fn cli_pretty_flag_keeps_github_output_compact() {
+    let tmp = std::env::temp_dir().join(format!("test-pretty-output-{}", std::process::id()));
+    std::fs::write(&tmp, "").unwrap();
+
+    let (_, _, ok) = run_binary_with_args(
+        &["--pretty"],
+        &[
+            ("CHANGED_FILES", "lib-core/src/lib.rs"),
+            ("GITHUB_OUTPUT", tmp.to_str().unwrap()),
+        ],
+    );
+    assert!(ok);
+
+    let contents = std::fs::read_to_string(&tmp).unwrap();
+    assert!(contents.contains("changed_crates=[\"lib-core\"]\n"));
+    std::fs::remove_file(&tmp).ok();
 }
 
 // ── Combined env var interaction ────────────────────────────────────
@@ -0,0 +1,3 @@
+pub fn modern() {
+    legacy::legacy();
+}
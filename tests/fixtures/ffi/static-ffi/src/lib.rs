@@ -0,0 +1 @@
+pub fn static_ffi() {}
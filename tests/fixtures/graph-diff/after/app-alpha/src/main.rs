@@ -0,0 +1,4 @@
+fn main() {
+    println!("{}", lib_core::core_logic());
+    println!("{}", lib_utils::util_helper());
+}
@@ -0,0 +1,4 @@
+pub fn core_logic() -> &'static str {
+    let _ = lib_utils::util_helper();
+    "hello from lib-core"
+}
@@ -0,0 +1,3 @@
+pub fn util_helper() -> &'static str {
+    "hello from lib-utils"
+}
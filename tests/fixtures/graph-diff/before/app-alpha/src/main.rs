@@ -0,0 +1,3 @@
+fn main() {
+    println!("{}", lib_core::core_logic());
+}
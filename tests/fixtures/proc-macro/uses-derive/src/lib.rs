@@ -0,0 +1 @@
+pub fn uses_derive() {}
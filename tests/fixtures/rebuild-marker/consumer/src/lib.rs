@@ -0,0 +1,3 @@
+pub fn consumer() {
+    marked::marked();
+}
@@ -0,0 +1,5 @@
+pub fn portable_fn() -> &'static str {
+    #[cfg(windows)]
+    let _ = lib_win_sys::win_helper();
+    "hello from lib-portable"
+}
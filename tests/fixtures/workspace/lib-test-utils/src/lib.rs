@@ -0,0 +1,3 @@
+pub fn expected_helper() -> u32 {
+    42
+}
@@ -0,0 +1,3 @@
+pub fn win_helper() -> &'static str {
+    "hello from lib-win-sys"
+}
@@ -1,4 +1,7 @@
 #[test]
 fn it_works() {
-    assert_eq!(lib_with_tests::helper(), 42);
+    assert_eq!(
+        lib_with_tests::helper(),
+        lib_test_utils::expected_helper()
+    );
 }
@@ -0,0 +1,253 @@
+use rust_affected::git::{
+    changed_files_in_range, changed_files_since_reflog, file_at_rev, parse_porcelain,
+};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Create an empty git repository in a fresh temp directory.
+fn temp_repo(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rust-affected-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    git(&dir, &["init", "--quiet"]);
+    dir
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args([
+            "-c",
+            "user.name=rust-affected",
+            "-c",
+            "user.email=rust-affected@example.com",
+            "-c",
+            "commit.gpgsign=false",
+        ])
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .expect("Failed to run git");
+    assert!(status.success(), "git {args:?} failed");
+}
+
+fn commit_file(dir: &Path, path: &str, contents: &str) {
+    let full = dir.join(path);
+    std::fs::create_dir_all(full.parent().unwrap()).unwrap();
+    std::fs::write(full, contents).unwrap();
+    git(dir, &["add", "-A"]);
+    git(dir, &["commit", "--quiet", "-m", path]);
+}
+
+// ── Reflog-based diffing ────────────────────────────────────────────
+
+#[test]
+fn reflog_diff_against_previous_head() {
+    let dir = temp_repo("reflog-previous");
+    commit_file(&dir, "lib-core/src/lib.rs", "one");
+    commit_file(&dir, "lib-utils/src/lib.rs", "two");
+
+    let changed = changed_files_since_reflog(&dir).unwrap();
+    assert_eq!(changed, Some(vec!["lib-utils/src/lib.rs".to_string()]));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn reflog_diff_after_amended_commit() {
+    let dir = temp_repo("reflog-amend");
+    commit_file(&dir, "lib-core/src/lib.rs", "one");
+    commit_file(&dir, "app-alpha/src/main.rs", "two");
+    // Rewrite the last commit the way a rebase + force-push would: the previous
+    // HEAD touched app-alpha, the rewritten one touches lib-standalone instead.
+    std::fs::remove_file(dir.join("app-alpha/src/main.rs")).unwrap();
+    std::fs::create_dir_all(dir.join("lib-standalone/src")).unwrap();
+    std::fs::write(dir.join("lib-standalone/src/lib.rs"), "three").unwrap();
+    git(&dir, &["add", "-A"]);
+    git(&dir, &["commit", "--quiet", "--amend", "-m", "rewritten"]);
+
+    let changed = changed_files_since_reflog(&dir).unwrap();
+    assert_eq!(
+        changed,
+        Some(vec![
+            "app-alpha/src/main.rs".to_string(),
+            "lib-standalone/src/lib.rs".to_string(),
+        ])
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn reflog_without_previous_entry_returns_none() {
+    let dir = temp_repo("reflog-none");
+    commit_file(&dir, "lib-core/src/lib.rs", "one");
+
+    assert_eq!(changed_files_since_reflog(&dir).unwrap(), None);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn reflog_paths_are_relative_to_a_nested_workspace() {
+    let dir = temp_repo("reflog-nested");
+    commit_file(&dir, "rust/lib-core/src/lib.rs", "one");
+    commit_file(&dir, "rust/lib-core/src/lib.rs", "two");
+
+    let changed = changed_files_since_reflog(&dir.join("rust")).unwrap();
+    assert_eq!(changed, Some(vec!["lib-core/src/lib.rs".to_string()]));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn reflog_diff_keeps_rename_sources() {
+    let dir = temp_repo("reflog-rename");
+    commit_file(
+        &dir,
+        "lib-core/src/lib.rs",
+        "pub fn one() {}\npub fn two() {}\n",
+    );
+    std::fs::create_dir_all(dir.join("lib-utils/src")).unwrap();
+    git(&dir, &["mv", "lib-core/src/lib.rs", "lib-utils/src/lib.rs"]);
+    git(&dir, &["commit", "--quiet", "-m", "move"]);
+
+    let changed = changed_files_since_reflog(&dir).unwrap();
+    assert_eq!(
+        changed,
+        Some(vec![
+            "lib-core/src/lib.rs".to_string(),
+            "lib-utils/src/lib.rs".to_string(),
+        ])
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+// ── Range-based diffing ─────────────────────────────────────────────
+
+#[test]
+fn range_lists_files_changed_between_refs() {
+    let dir = temp_repo("range-basic");
+    commit_file(&dir, "lib-core/src/lib.rs", "one");
+    git(&dir, &["tag", "base"]);
+    commit_file(&dir, "lib-utils/src/lib.rs", "two");
+    commit_file(&dir, "app-alpha/src/main.rs", "three");
+
+    let changed = changed_files_in_range(&dir, "base..HEAD").unwrap();
+    assert_eq!(
+        changed,
+        vec![
+            "app-alpha/src/main.rs".to_string(),
+            "lib-utils/src/lib.rs".to_string(),
+        ]
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn range_reports_both_paths_of_a_rename() {
+    let dir = temp_repo("range-rename");
+    commit_file(
+        &dir,
+        "lib-core/src/parser.rs",
+        "fn parse() {}\n".repeat(20).as_str(),
+    );
+    git(&dir, &["tag", "base"]);
+    std::fs::create_dir_all(dir.join("lib-utils/src")).unwrap();
+    git(
+        &dir,
+        &["mv", "lib-core/src/parser.rs", "lib-utils/src/parser.rs"],
+    );
+    git(&dir, &["commit", "--quiet", "-m", "move parser"]);
+
+    let changed = changed_files_in_range(&dir, "base..HEAD").unwrap();
+    assert_eq!(
+        changed,
+        vec![
+            "lib-core/src/parser.rs".to_string(),
+            "lib-utils/src/parser.rs".to_string(),
+        ]
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn range_paths_are_relative_to_a_nested_workspace() {
+    let dir = temp_repo("range-nested");
+    commit_file(&dir, "rust/lib-core/src/lib.rs", "one");
+    git(&dir, &["tag", "base"]);
+    commit_file(&dir, "rust/lib-core/src/lib.rs", "two");
+    commit_file(&dir, "docs/index.md", "three");
+
+    let changed = changed_files_in_range(&dir.join("rust"), "base..HEAD").unwrap();
+    assert_eq!(changed, vec!["lib-core/src/lib.rs".to_string()]);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn range_with_unknown_ref_is_an_error() {
+    let dir = temp_repo("range-unknown");
+    commit_file(&dir, "lib-core/src/lib.rs", "one");
+
+    let err = changed_files_in_range(&dir, "nope..HEAD").unwrap_err();
+    assert!(err.to_string().starts_with("git diff"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+// ── Files at a revision ─────────────────────────────────────────────
+
+#[test]
+fn file_at_rev_reads_the_old_contents() {
+    let dir = temp_repo("file-at-rev");
+    commit_file(&dir, "rust/Cargo.lock", "one");
+    git(&dir, &["tag", "base"]);
+    commit_file(&dir, "rust/Cargo.lock", "two");
+
+    assert_eq!(
+        file_at_rev(&dir.join("rust"), "base", "Cargo.lock").unwrap(),
+        "one"
+    );
+    assert!(file_at_rev(&dir, "base", "Cargo.lock").is_err());
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+// ── Porcelain status lines ──────────────────────────────────────────
+
+#[test]
+fn porcelain_status_codes_are_stripped() {
+    let output = "M lib-core/src/lib.rs\nA  lib-new/src/lib.rs\n D app/src/main.rs\n";
+    assert_eq!(
+        parse_porcelain(output, false),
+        vec![
+            "lib-core/src/lib.rs",
+            "lib-new/src/lib.rs",
+            "app/src/main.rs"
+        ]
+    );
+}
+
+#[test]
+fn porcelain_rename_yields_destination() {
+    let output = "R  lib-a/src/old.rs -> lib-b/src/new.rs\n";
+    assert_eq!(parse_porcelain(output, false), vec!["lib-b/src/new.rs"]);
+}
+
+#[test]
+fn porcelain_rename_sources_yields_both_paths() {
+    let output = "R  lib-a/src/old.rs -> lib-b/src/new.rs\n";
+    assert_eq!(
+        parse_porcelain(output, true),
+        vec!["lib-a/src/old.rs", "lib-b/src/new.rs"]
+    );
+}
+
+#[test]
+fn porcelain_skips_blank_lines_and_unquotes_paths() {
+    let output = "\n?? \"docs/with space.md\"\n\n";
+    assert_eq!(parse_porcelain(output, false), vec!["docs/with space.md"]);
+}
@@ -1,19 +1,29 @@
-use guppy::{MetadataCommand, graph::PackageGraph};
-use rust_affected::{AffectedResult, compute_affected};
-use std::collections::HashSet;
-use std::path::PathBuf;
+use guppy::{DependencyKind, MetadataCommand, graph::PackageGraph};
+use rust_affected::{
+    AffectedConfig, AffectedError, AffectedOptions, AffectedResult, FlapHistory, FlapState,
+    GraphDiff, MemberDiff, MemberKind, RatioDenominator, SCHEMA_VERSION, SchemaVersion, Selector,
+    affected_levels, cached_package_graph, check_force_triggers, compute_affected,
+    compute_affected_from_path, crates_for_file, force_trigger_reason, graph_diff, is_excluded,
+    json_patch, normalize_changed_file, release_order, source_hash, to_json_value,
+    workspace_manifest, workspace_members,
+};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 
 fn fixture_graph() -> &'static PackageGraph {
     static GRAPH: OnceLock<PackageGraph> = OnceLock::new();
-    GRAPH.get_or_init(|| {
-        let fixture_dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "workspace"]
-            .iter()
-            .collect();
-        let mut cmd = MetadataCommand::new();
-        cmd.current_dir(&fixture_dir);
-        PackageGraph::from_command(&mut cmd).expect("Failed to load fixture package graph")
-    })
+    GRAPH.get_or_init(|| load_graph(&["workspace"]))
+}
+
+fn load_graph(fixture: &[&str]) -> PackageGraph {
+    let mut fixture_dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures"]
+        .iter()
+        .collect();
+    fixture_dir.extend(fixture);
+    let mut cmd = MetadataCommand::new();
+    cmd.current_dir(&fixture_dir);
+    PackageGraph::from_command(&mut cmd).expect("Failed to load fixture package graph")
 }
 
 fn s(v: &[&str]) -> Vec<String> {
@@ -33,25 +43,80 @@ fn excludes(names: &[&str]) -> HashSet<String> {
 #[test]
 fn empty_changed_files_produces_empty_result() {
     let graph = fixture_graph();
-    let result = compute_affected(graph, &[], &[], &no_excludes());
+    let result = compute_affected(graph, &[], &[], &no_excludes()).unwrap();
     assert_eq!(
         result,
         AffectedResult {
+            schema_version: SchemaVersion,
             force_all: false,
+            force_trigger_reason: None,
+            force_all_cause: None,
+            forced_kind: None,
+            any_affected: false,
+            removed_crates: vec![],
             changed_crates: vec![],
             affected_library_members: vec![],
             affected_binary_members: vec![],
+            affected_bin_targets: vec![],
+            affected_build_order: vec![],
+            transitively_affected_members: vec![],
+            affected_proc_macro_members: vec![],
+            affected_cdylib_members: vec![],
+            affected_paths: BTreeMap::new(),
+            source_hashes: BTreeMap::new(),
+            dependencies_of_changed: vec![],
+            degrees: BTreeMap::new(),
+            affected_build: vec![],
+            affected_test: vec![],
+            editions: BTreeMap::new(),
+            testable_members: vec![],
+            needs_version_bump: vec![],
+            indexed: vec![],
+            flapping: vec![],
         }
     );
 }
 
+#[test]
+fn blank_and_invalid_changed_files_count_as_empty() {
+    let graph = fixture_graph();
+    let changed = s(&["", "  ", "lib-core/src/\0lib.rs"]);
+    let result = AffectedOptions::new()
+        .always_affected(["app-alpha"])
+        .compute(graph, &changed)
+        .unwrap();
+    assert_eq!(result, AffectedResult::default());
+}
+
+// ── Duplicate changed files ─────────────────────────────────────────
+
+#[test]
+fn duplicate_and_blank_changed_files_are_ignored() {
+    let graph = fixture_graph();
+    let changed = s(&[
+        "lib-core/src/lib.rs",
+        "",
+        "./lib-core/src/lib.rs",
+        "lib-core/src/lib.rs",
+        " ",
+        "lib-standalone/src/lib.rs",
+    ]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    let deduped = s(&["lib-core/src/lib.rs", "lib-standalone/src/lib.rs"]);
+    assert_eq!(
+        result,
+        compute_affected(graph, &deduped, &[], &no_excludes()).unwrap()
+    );
+}
+
 // ── Leaf library change (lib-utils) ─────────────────────────────────
 
 #[test]
 fn change_leaf_lib_affects_all_dependents() {
     let graph = fixture_graph();
     let changed = s(&["lib-utils/src/lib.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(result.changed_crates, vec!["lib-utils"]);
     assert_eq!(
@@ -71,7 +136,7 @@ fn change_leaf_lib_affects_all_dependents() {
 fn change_mid_tree_lib_affects_its_dependents() {
     let graph = fixture_graph();
     let changed = s(&["lib-core/src/lib.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(result.changed_crates, vec!["lib-core"]);
     assert_eq!(
@@ -90,7 +155,7 @@ fn change_mid_tree_lib_affects_its_dependents() {
 fn change_isolated_lib_only_affects_its_dependent() {
     let graph = fixture_graph();
     let changed = s(&["lib-standalone/src/lib.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(result.changed_crates, vec!["lib-standalone"]);
     assert_eq!(
@@ -106,7 +171,7 @@ fn change_isolated_lib_only_affects_its_dependent() {
 fn change_binary_only_affects_itself() {
     let graph = fixture_graph();
     let changed = s(&["app-alpha/src/main.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(result.changed_crates, vec!["app-alpha"]);
     assert!(result.affected_library_members.is_empty());
@@ -119,7 +184,7 @@ fn change_binary_only_affects_itself() {
 fn change_multiple_crates_unions_affected() {
     let graph = fixture_graph();
     let changed = s(&["lib-standalone/src/lib.rs", "app-alpha/src/main.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(result.changed_crates, vec!["app-alpha", "lib-standalone"]);
     assert_eq!(
@@ -138,7 +203,7 @@ fn change_multiple_crates_unions_affected() {
 fn change_cargo_toml_detects_crate() {
     let graph = fixture_graph();
     let changed = s(&["lib-core/Cargo.toml"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(result.changed_crates, vec!["lib-core"]);
     assert_eq!(
@@ -147,6 +212,56 @@ fn change_cargo_toml_detects_crate() {
     );
 }
 
+#[test]
+fn cosmetic_manifest_change_does_not_mark_crate_changed() {
+    let graph = fixture_graph();
+    let current = std::fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workspace/lib-core/Cargo.toml"),
+    )
+    .unwrap();
+    // The base differs in formatting, comments and `[package.metadata]`.
+    let base = format!(
+        "# The core library.\n{}\n[package.metadata.docs.rs]\nall-features = true\n",
+        current.replace(" = ", "=")
+    );
+    let result = AffectedOptions::new()
+        .base_manifests([("lib-core/Cargo.toml", base)])
+        .compute(graph, &s(&["lib-core/Cargo.toml"]))
+        .unwrap();
+
+    assert!(result.changed_crates.is_empty());
+    assert!(result.affected_library_members.is_empty());
+    assert!(result.affected_binary_members.is_empty());
+}
+
+#[test]
+fn dependency_edit_in_manifest_marks_crate_changed() {
+    let graph = fixture_graph();
+    let base = "[package]\nname = \"lib-core\"\nversion = \"0.1.0\"\nedition = \"2021\"\n";
+    let result = AffectedOptions::new()
+        .base_manifests([("lib-core/Cargo.toml", base)])
+        .compute(graph, &s(&["lib-core/Cargo.toml"]))
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-core"]);
+}
+
+#[test]
+fn cosmetic_manifest_change_still_matches_force_triggers() {
+    let graph = fixture_graph();
+    let current = std::fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workspace/lib-core/Cargo.toml"),
+    )
+    .unwrap();
+    let result = AffectedOptions::new()
+        .force_triggers(["**/Cargo.toml"])
+        .base_manifests([("lib-core/Cargo.toml", current)])
+        .compute(graph, &s(&["lib-core/Cargo.toml"]))
+        .unwrap();
+
+    assert!(result.force_all);
+}
+
 // ── Force triggers: matching ─────────────────────────────────────────
 
 #[test]
@@ -154,7 +269,7 @@ fn force_trigger_match_returns_all_members() {
     let graph = fixture_graph();
     let changed = s(&["infra/deploy.yml"]);
     let triggers = s(&["infra/"]);
-    let result = compute_affected(graph, &changed, &triggers, &no_excludes());
+    let result = compute_affected(graph, &changed, &triggers, &no_excludes()).unwrap();
 
     assert!(result.force_all);
     assert_eq!(
@@ -162,8 +277,11 @@ fn force_trigger_match_returns_all_members() {
         vec![
             "lib-core",
             "lib-core-ext",
+            "lib-portable",
             "lib-standalone",
+            "lib-test-utils",
             "lib-utils",
+            "lib-win-sys",
             "lib-with-tests",
         ]
     );
@@ -178,13 +296,261 @@ fn force_trigger_glob_pattern_match() {
     let graph = fixture_graph();
     let changed = s(&["ci/workflow.yml"]);
     let triggers = s(&["ci/*.yml"]);
-    let result = compute_affected(graph, &changed, &triggers, &no_excludes());
+    let result = compute_affected(graph, &changed, &triggers, &no_excludes()).unwrap();
+
+    assert!(result.force_all);
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta", "tool-alpha"]
+    );
+}
+
+#[test]
+fn force_trigger_reason_names_pattern_and_file() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-core/src/lib.rs", "infra/deploy.yml"]);
+    let triggers = s(&["Cargo.lock", "infra/", "**/*.yml"]);
+    let result = compute_affected(graph, &changed, &triggers, &no_excludes()).unwrap();
+
+    assert_eq!(
+        result.force_trigger_reason.as_deref(),
+        Some("infra/ (matched infra/deploy.yml)")
+    );
+    let json = serde_json::to_value(&result).unwrap();
+    assert_eq!(
+        json["force_trigger_reason"],
+        "infra/ (matched infra/deploy.yml)"
+    );
+}
+
+#[test]
+fn force_trigger_reason_skips_vetoed_files() {
+    let changed = s(&["docs/internal/notes.md", "docs/guide.md"]);
+    let triggers = s(&["docs/**", "!docs/internal/**"]);
+    assert_eq!(
+        force_trigger_reason(&changed, &triggers)
+            .unwrap()
+            .as_deref(),
+        Some("docs/** (matched docs/guide.md)")
+    );
+}
+
+#[test]
+fn no_force_trigger_reason_without_match() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .force_triggers(["infra/"])
+        .force_all_ratio(0.1)
+        .compute(graph, &s(&["lib-utils/src/lib.rs"]))
+        .unwrap();
+
+    assert!(result.force_all);
+    assert_eq!(result.force_trigger_reason, None);
+    assert_eq!(
+        result.force_all_cause.as_deref(),
+        Some("55% of the workspace was affected, at least the force-all ratio of 10%")
+    );
+}
+
+#[test]
+fn default_force_triggers_are_on_by_default() {
+    let graph = fixture_graph();
+    let changed = s(&["rust-toolchain.toml"]);
+    assert!(
+        !AffectedOptions::new()
+            .default_force_triggers(false)
+            .compute(graph, &changed)
+            .unwrap()
+            .force_all
+    );
+
+    let result = AffectedOptions::new().compute(graph, &changed).unwrap();
+    assert!(result.force_all);
+    assert_eq!(
+        result.force_trigger_reason.as_deref(),
+        Some("rust-toolchain.toml (matched rust-toolchain.toml)")
+    );
+}
+
+// ── Force triggers: negation ─────────────────────────────────────────
+
+#[test]
+fn negative_trigger_vetoes_file_matching_both() {
+    let triggers = s(&["docs/**", "!docs/internal/**"]);
+    // Matches the positive and the negative pattern.
+    assert!(!check_force_triggers(&s(&["docs/internal/notes.md"]), &triggers).unwrap());
+    assert!(check_force_triggers(&s(&["docs/guide.md"]), &triggers).unwrap());
+}
 
+#[test]
+fn negative_trigger_only_vetoes_the_file_it_matches() {
+    let graph = fixture_graph();
+    let changed = s(&["docs/internal/notes.md", "docs/guide.md"]);
+    let triggers = s(&["docs/**", "!docs/internal/**"]);
+    let result = compute_affected(graph, &changed, &triggers, &no_excludes()).unwrap();
     assert!(result.force_all);
+}
+
+#[test]
+fn negative_triggers_alone_never_match() {
+    let triggers = s(&["!docs/**"]);
+    assert!(!check_force_triggers(&s(&["src/main.rs"]), &triggers).unwrap());
+}
+
+#[test]
+fn invalid_negative_trigger_is_an_error() {
+    let err = check_force_triggers(&s(&["docs/guide.md"]), &s(&["docs/**", "![oops"])).unwrap_err();
+    assert!(matches!(err, AffectedError::InvalidGlob { pattern, .. } if pattern == "[oops"));
+}
+
+// ── Scoped triggers ──────────────────────────────────────────────────
+
+#[test]
+fn scoped_trigger_adds_only_mapped_crates() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .scoped_triggers([("infra/app-alpha/**", ["app-alpha"])])
+        .compute(graph, &s(&["infra/app-alpha/values.yaml"]))
+        .unwrap();
+
+    assert!(!result.force_all);
+    assert!(result.changed_crates.is_empty());
+    assert!(result.affected_library_members.is_empty());
+    assert_eq!(result.affected_binary_members, vec!["app-alpha"]);
+}
+
+#[test]
+fn scoped_trigger_does_not_pull_in_dependents() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .scoped_triggers([("infra/core.toml", ["lib-core", "not-a-member"])])
+        .compute(graph, &s(&["infra/core.toml"]))
+        .unwrap();
+
+    assert_eq!(result.affected_library_members, vec!["lib-core"]);
+    assert!(result.affected_binary_members.is_empty());
+}
+
+#[test]
+fn non_matching_scoped_trigger_adds_nothing() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .scoped_triggers([("infra/app-alpha/**", ["app-alpha"])])
+        .compute(graph, &s(&["infra/app-beta/values.yaml"]))
+        .unwrap();
+
+    assert!(result.affected_binary_members.is_empty());
+}
+
+// ── Force triggers: limited to a member kind ─────────────────────────
+
+#[test]
+fn library_trigger_forces_only_libraries() {
+    let graph = fixture_graph();
+    let result = compute_affected(
+        graph,
+        &s(&["infra/shared.tf"]),
+        &s(&["infra/ => libraries"]),
+        &no_excludes(),
+    )
+    .unwrap();
+
+    assert!(!result.force_all);
+    assert_eq!(result.forced_kind, Some(MemberKind::Library));
+    assert_eq!(result.force_trigger_reason, None);
+    assert_eq!(result.affected_library_members.len(), 8);
+    assert!(result.affected_binary_members.is_empty());
+    assert!(result.any_affected);
+}
+
+#[test]
+fn library_trigger_leaves_binaries_to_the_traversal() {
+    let graph = fixture_graph();
+    let result = compute_affected(
+        graph,
+        &s(&["infra/shared.tf", "lib-standalone/src/lib.rs"]),
+        &s(&["infra/=>libraries"]),
+        &no_excludes(),
+    )
+    .unwrap();
+
+    assert_eq!(result.affected_library_members.len(), 8);
+    assert_eq!(result.affected_binary_members, vec!["app-beta"]);
+}
+
+#[test]
+fn binary_trigger_forces_only_binaries() {
+    let graph = fixture_graph();
+    let result = compute_affected(
+        graph,
+        &s(&["deploy/base.yaml"]),
+        &s(&["deploy/** => binaries"]),
+        &no_excludes(),
+    )
+    .unwrap();
+
+    assert_eq!(result.forced_kind, Some(MemberKind::Binary));
+    assert!(result.affected_library_members.is_empty());
     assert_eq!(
         result.affected_binary_members,
         vec!["app-alpha", "app-beta", "tool-alpha"]
     );
+    assert_eq!(
+        serde_json::to_value(&result).unwrap()["forced_kind"],
+        "binary"
+    );
+}
+
+#[test]
+fn both_kinds_matching_force_all() {
+    let graph = fixture_graph();
+    let result = compute_affected(
+        graph,
+        &s(&["infra/shared.tf", "deploy/base.yaml"]),
+        &s(&["infra/ => libraries", "deploy/ => binaries"]),
+        &no_excludes(),
+    )
+    .unwrap();
+
+    assert!(result.force_all);
+    assert_eq!(result.forced_kind, None);
+    assert_eq!(
+        result.force_trigger_reason.as_deref(),
+        Some("infra/ => libraries (matched infra/shared.tf)")
+    );
+}
+
+#[test]
+fn negative_trigger_applies_to_kind_triggers() {
+    let graph = fixture_graph();
+    let result = compute_affected(
+        graph,
+        &s(&["infra/README.md"]),
+        &s(&["infra/ => libraries", "!infra/*.md"]),
+        &no_excludes(),
+    )
+    .unwrap();
+
+    assert_eq!(result.forced_kind, None);
+    assert!(result.affected_library_members.is_empty());
+}
+
+#[test]
+fn unknown_trigger_kind_is_an_error() {
+    let err = check_force_triggers(&s(&["infra/x"]), &s(&["infra/ => services"])).unwrap_err();
+    assert!(
+        matches!(&err, AffectedError::InvalidTriggerKind { trigger } if trigger == "infra/ => services")
+    );
+    let err = AffectedOptions::new()
+        .force_triggers(["infra/ => services"])
+        .validate()
+        .unwrap_err();
+    assert!(
+        matches!(err, AffectedError::InvalidTriggerKind { .. }),
+        "{err}"
+    );
+    // The kind is ignored when only checking for a match.
+    assert!(check_force_triggers(&s(&["infra/x"]), &s(&["infra/ => binaries"])).unwrap());
 }
 
 // ── Force triggers: non-matching ─────────────────────────────────────
@@ -194,13 +560,149 @@ fn force_trigger_no_match_behaves_normally() {
     let graph = fixture_graph();
     let changed = s(&["app-alpha/src/main.rs"]);
     let triggers = s(&["infra/"]);
-    let result = compute_affected(graph, &changed, &triggers, &no_excludes());
+    let result = compute_affected(graph, &changed, &triggers, &no_excludes()).unwrap();
 
     assert!(!result.force_all);
     assert_eq!(result.changed_crates, vec!["app-alpha"]);
     assert_eq!(result.affected_binary_members, vec!["app-alpha"]);
 }
 
+#[test]
+fn force_trigger_blank_entries_are_ignored() {
+    let graph = fixture_graph();
+    let changed = s(&["app-alpha/src/main.rs"]);
+    let triggers = s(&["", "  ", "infra/", "\n"]);
+    let result = compute_affected(graph, &changed, &triggers, &no_excludes()).unwrap();
+
+    assert!(!result.force_all);
+    assert_eq!(result.affected_binary_members, vec!["app-alpha"]);
+}
+
+#[test]
+fn force_trigger_only_blank_entries_never_force() {
+    let changed = s(&["app-alpha/src/main.rs", "Cargo.lock"]);
+    assert!(!check_force_triggers(&changed, &s(&["", " "])).unwrap());
+}
+
+#[test]
+fn force_trigger_invalid_glob_is_an_error() {
+    let changed = s(&["Cargo.lock"]);
+    let err = check_force_triggers(&changed, &s(&["Cargo.lock", "src/[a-"])).unwrap_err();
+
+    let AffectedError::InvalidGlob { pattern, .. } = &err else {
+        panic!("expected InvalidGlob, got {err:?}");
+    };
+    assert_eq!(pattern, "src/[a-");
+    assert!(
+        err.to_string()
+            .starts_with("invalid glob pattern \"src/[a-\": ")
+    );
+    assert!(std::error::Error::source(&err).is_some());
+}
+
+// ── Serialization ───────────────────────────────────────────────────
+
+#[test]
+fn result_serializes_with_output_keys() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-standalone/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    let json = serde_json::to_value(&result).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "schema_version": SCHEMA_VERSION,
+            "force_all": false,
+            "any_affected": true,
+            "changed_crates": ["lib-standalone"],
+            "removed_crates": [],
+            "affected_library_members": ["lib-standalone"],
+            "affected_binary_members": ["app-beta"],
+            "affected_bin_targets": [{"crate": "app-beta", "bin": "app-beta"}],
+            "affected_build_order": ["lib-standalone"],
+            "transitively_affected_members": [],
+            "affected_proc_macro_members": [],
+            "affected_cdylib_members": [],
+            "affected_paths": {
+                "app-beta": "app-beta",
+                "lib-standalone": "lib-standalone",
+            },
+        })
+    );
+}
+
+#[test]
+fn schema_version_is_first_and_current() {
+    let result = AffectedResult::default();
+    assert_eq!(result.schema_version.get(), 1);
+    let json = serde_json::to_string(&result).unwrap();
+    assert!(json.starts_with("{\"schema_version\":1,"));
+}
+
+#[test]
+fn to_json_value_matches_the_serialized_result() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    let value = to_json_value(&result);
+    assert_eq!(value, serde_json::to_value(&result).unwrap());
+    assert_eq!(value["changed_crates"], serde_json::json!(["lib-utils"]));
+    assert!(value.get("source_hashes").is_none());
+}
+
+#[test]
+fn affected_bin_targets_list_every_binary_target() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-core/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    // app-alpha has a second `[[bin]]` target besides its default binary.
+    assert_eq!(
+        result.affected_bin_targets,
+        vec![
+            ("app-alpha".to_string(), "alpha-migrate".to_string()),
+            ("app-alpha".to_string(), "app-alpha".to_string()),
+            ("app-beta".to_string(), "app-beta".to_string()),
+        ]
+    );
+    assert_eq!(
+        to_json_value(&result)["affected_bin_targets"][0],
+        serde_json::json!({"crate": "app-alpha", "bin": "alpha-migrate"})
+    );
+}
+
+#[test]
+fn affected_bin_targets_skip_library_members() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-standalone/src/lib.rs"]);
+    let mut result = compute_affected(graph, &changed, &[], &excludes(&["app-beta"])).unwrap();
+    assert!(result.affected_bin_targets.is_empty());
+
+    result = compute_affected(
+        graph,
+        &s(&["app-alpha/src/bin/migrate.rs"]),
+        &[],
+        &no_excludes(),
+    )
+    .unwrap();
+    assert_eq!(result.changed_crates, vec!["app-alpha"]);
+    assert_eq!(result.affected_bin_targets.len(), 2);
+}
+
+#[test]
+fn affected_paths_map_members_to_their_directories() {
+    let graph = fixture_graph();
+    let changed = s(&["tools/tool-alpha/src/main.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(
+        result.affected_paths,
+        BTreeMap::from([("tool-alpha".to_string(), "tools/tool-alpha".to_string())])
+    );
+}
+
 // ── Excluded members ────────────────────────────────────────────────
 
 #[test]
@@ -208,7 +710,7 @@ fn excluded_member_removed_from_all_lists() {
     let graph = fixture_graph();
     let changed = s(&["lib-utils/src/lib.rs"]);
     let excluded = excludes(&["lib-core"]);
-    let result = compute_affected(graph, &changed, &[], &excluded);
+    let result = compute_affected(graph, &changed, &[], &excluded).unwrap();
 
     assert_eq!(result.changed_crates, vec!["lib-utils"]);
     assert!(
@@ -244,7 +746,7 @@ fn excluded_binary_removed_from_binary_list() {
     let graph = fixture_graph();
     let changed = s(&["lib-core/src/lib.rs"]);
     let excluded = excludes(&["app-alpha"]);
-    let result = compute_affected(graph, &changed, &[], &excluded);
+    let result = compute_affected(graph, &changed, &[], &excluded).unwrap();
 
     assert!(
         !result
@@ -259,7 +761,7 @@ fn excluded_changed_crate_removed_from_changed_list() {
     let graph = fixture_graph();
     let changed = s(&["lib-utils/src/lib.rs"]);
     let excluded = excludes(&["lib-utils"]);
-    let result = compute_affected(graph, &changed, &[], &excluded);
+    let result = compute_affected(graph, &changed, &[], &excluded).unwrap();
 
     assert!(result.changed_crates.is_empty());
     assert!(
@@ -269,37 +771,213 @@ fn excluded_changed_crate_removed_from_changed_list() {
     );
 }
 
-// ── File outside any crate ──────────────────────────────────────────
+#[test]
+fn excluded_members_lists_matching_workspace_members() {
+    let graph = fixture_graph();
+    let options = AffectedOptions::new().excluded(["tools/", "lib-core", "no-such-crate"]);
+    assert_eq!(
+        options.excluded_members(graph),
+        vec!["lib-core", "tool-alpha"]
+    );
+}
+
+// ── Included members ────────────────────────────────────────────────
 
 #[test]
-fn file_outside_any_crate_produces_no_changes() {
+fn included_members_restrict_all_lists() {
     let graph = fixture_graph();
-    let changed = s(&["README.md"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = AffectedOptions::new()
+        .included(["lib-core", "tools/"])
+        .compute(graph, &s(&["lib-utils/src/lib.rs"]))
+        .unwrap();
 
+    // lib-utils changed but is outside the allowlist.
     assert!(result.changed_crates.is_empty());
-    assert!(result.affected_library_members.is_empty());
-    assert!(result.affected_binary_members.is_empty());
+    assert_eq!(result.affected_library_members, vec!["lib-core"]);
+    assert_eq!(result.affected_binary_members, vec!["tool-alpha"]);
 }
 
-// ── Force trigger with excluded members ─────────────────────────────
-
 #[test]
-fn force_all_respects_exclusions() {
+fn exclusions_apply_after_inclusions() {
     let graph = fixture_graph();
-    let changed = s(&["infra/deploy.yml"]);
-    let triggers = s(&["infra/"]);
-    let excluded = excludes(&["app-alpha", "lib-standalone"]);
-    let result = compute_affected(graph, &changed, &triggers, &excluded);
+    let result = AffectedOptions::new()
+        .included(["lib-core", "lib-core-ext", "app-alpha"])
+        .excluded(["lib-core-ext"])
+        .compute(graph, &s(&["lib-core/src/lib.rs"]))
+        .unwrap();
 
-    assert!(result.force_all);
-    assert!(
-        !result
-            .affected_library_members
-            .contains(&"app-alpha".to_string())
+    assert_eq!(result.changed_crates, vec!["lib-core"]);
+    assert_eq!(result.affected_library_members, vec!["lib-core"]);
+    assert_eq!(result.affected_binary_members, vec!["app-alpha"]);
+}
+
+#[test]
+fn empty_include_list_keeps_everything() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-core/src/lib.rs"]);
+    let included = AffectedOptions::new()
+        .included(Vec::<String>::new())
+        .compute(graph, &changed)
+        .unwrap();
+    assert_eq!(
+        included,
+        compute_affected(graph, &changed, &[], &no_excludes()).unwrap()
     );
-    assert!(
-        !result
+}
+
+// ── Exclusions from a reader ────────────────────────────────────────
+
+/// A generated exclusion list: `count` unrelated names, then `extra` lines.
+fn generated_exclusions(count: usize, extra: &str) -> String {
+    let mut contents = String::from("# generated\n\n");
+    for i in 0..count {
+        contents.push_str(&format!("generated-crate-{i}\n"));
+    }
+    contents.push_str(extra);
+    contents
+}
+
+#[test]
+fn large_exclusion_file_is_applied() {
+    let graph = fixture_graph();
+    let contents = generated_exclusions(50_000, "lib-core-ext\ntools/ app-beta\n");
+    let options = AffectedOptions::new()
+        .excluded(["lib-standalone"])
+        .excluded_from_reader(std::io::Cursor::new(contents))
+        .unwrap();
+    let result = options
+        .compute(graph, &s(&["lib-utils/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-utils"]
+    );
+    assert_eq!(result.affected_binary_members, vec!["app-alpha"]);
+}
+
+#[test]
+fn exclusion_reader_skips_comments_and_blank_lines() {
+    let graph = fixture_graph();
+    let contents = "# lib-core\n\n   \n  # app-alpha\nlib-core-ext\n";
+    let result = AffectedOptions::new()
+        .excluded_from_reader(contents.as_bytes())
+        .unwrap()
+        .compute(graph, &s(&["lib-core/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.affected_library_members, vec!["lib-core"]);
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta"]
+    );
+}
+
+#[test]
+fn exclusion_reader_handles_crlf_line_endings() {
+    let graph = fixture_graph();
+    let contents = "# excluded\r\n\r\n  \r\nlib-core-*\r\n# app-alpha\r\ntools/\r\n";
+    let options = AffectedOptions::new()
+        .excluded_from_reader(contents.as_bytes())
+        .unwrap();
+
+    assert_eq!(
+        options.excluded_members(graph),
+        vec!["lib-core-ext", "tool-alpha"]
+    );
+}
+
+/// Streams `lines` generated exclusion lines in CRLF form, with a comment and
+/// a blank line every thousand, without holding them in memory.
+struct GeneratedExclusions {
+    lines: usize,
+    next: usize,
+    pending: Vec<u8>,
+}
+
+impl std::io::Read for GeneratedExclusions {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pending.is_empty() {
+            if self.next == self.lines {
+                return Ok(0);
+            }
+            let line = match self.next % 1000 {
+                0 => "# generated\r\n".to_string(),
+                1 => "\r\n".to_string(),
+                _ => format!("generated-crate-{}\r\n", self.next),
+            };
+            self.pending = line.into_bytes();
+            self.next += 1;
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+#[test]
+fn streamed_exclusion_file_is_applied() {
+    let graph = fixture_graph();
+    let generated = GeneratedExclusions {
+        lines: 200_000,
+        next: 0,
+        pending: Vec::new(),
+    };
+    let reader = std::io::Read::chain(generated, "lib-standalone\r\ntools/*\r\n".as_bytes());
+    let options = AffectedOptions::new()
+        .excluded_from_reader(std::io::BufReader::new(reader))
+        .unwrap();
+
+    assert_eq!(
+        options.excluded_members(graph),
+        vec!["lib-standalone", "tool-alpha"]
+    );
+}
+
+// ── File outside any crate ──────────────────────────────────────────
+
+#[test]
+fn file_outside_any_crate_produces_no_changes() {
+    let graph = fixture_graph();
+    let changed = s(&["README.md"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert!(result.changed_crates.is_empty());
+    assert!(result.affected_library_members.is_empty());
+    assert!(!result.any_affected);
+    assert!(result.affected_binary_members.is_empty());
+}
+
+#[test]
+fn force_all_counts_as_any_affected() {
+    let graph = fixture_graph();
+    let changed = s(&["README.md"]);
+    let triggers = s(&["README.md"]);
+    let result = compute_affected(graph, &changed, &triggers, &no_excludes()).unwrap();
+
+    assert!(result.force_all);
+    assert!(result.any_affected);
+}
+
+// ── Force trigger with excluded members ─────────────────────────────
+
+#[test]
+fn force_all_respects_exclusions() {
+    let graph = fixture_graph();
+    let changed = s(&["infra/deploy.yml"]);
+    let triggers = s(&["infra/"]);
+    let excluded = excludes(&["app-alpha", "lib-standalone"]);
+    let result = compute_affected(graph, &changed, &triggers, &excluded).unwrap();
+
+    assert!(result.force_all);
+    assert!(
+        !result
+            .affected_library_members
+            .contains(&"app-alpha".to_string())
+    );
+    assert!(
+        !result
             .affected_library_members
             .contains(&"lib-standalone".to_string())
     );
@@ -315,7 +993,7 @@ fn force_all_respects_exclusions() {
 fn transitive_chain_fully_resolved() {
     let graph = fixture_graph();
     let changed = s(&["lib-utils/src/lib.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     // lib-utils → lib-core → lib-core-ext, app-alpha, app-beta, tool-alpha
     // All transitive library dependents must appear in affected_library_members;
@@ -365,7 +1043,7 @@ fn transitive_chain_fully_resolved() {
 fn nested_file_path_matches_crate() {
     let graph = fixture_graph();
     let changed = s(&["lib-core/src/submodule/deep/file.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(result.changed_crates, vec!["lib-core"]);
     assert_eq!(
@@ -384,7 +1062,7 @@ fn nested_file_path_matches_crate() {
 fn leaf_binary_does_not_pull_unrelated() {
     let graph = fixture_graph();
     let changed = s(&["app-alpha/src/main.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(result.changed_crates, vec!["app-alpha"]);
     assert!(result.affected_library_members.is_empty());
@@ -425,7 +1103,7 @@ fn all_crates_changed_no_duplicates() {
         "app-alpha/src/main.rs",
         "app-beta/src/main.rs",
     ]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(
         result.changed_crates,
@@ -456,7 +1134,7 @@ fn force_trigger_with_normal_change_overlap() {
     let graph = fixture_graph();
     let changed = s(&["lib-core/src/lib.rs", "infra/deploy.yml"]);
     let triggers = s(&["infra/"]);
-    let result = compute_affected(graph, &changed, &triggers, &no_excludes());
+    let result = compute_affected(graph, &changed, &triggers, &no_excludes()).unwrap();
 
     assert!(result.force_all);
     // Direct change to lib-core is still tracked
@@ -467,8 +1145,11 @@ fn force_trigger_with_normal_change_overlap() {
         vec![
             "lib-core",
             "lib-core-ext",
+            "lib-portable",
             "lib-standalone",
+            "lib-test-utils",
             "lib-utils",
+            "lib-win-sys",
             "lib-with-tests",
         ]
     );
@@ -481,7 +1162,7 @@ fn excluded_binary_not_in_any_list() {
     let graph = fixture_graph();
     let changed = s(&["lib-utils/src/lib.rs"]);
     let excluded = excludes(&["app-alpha"]);
-    let result = compute_affected(graph, &changed, &[], &excluded);
+    let result = compute_affected(graph, &changed, &[], &excluded).unwrap();
 
     assert!(
         !result
@@ -513,7 +1194,7 @@ fn excluded_mid_graph_crate_still_traversed() {
     let graph = fixture_graph();
     let changed = s(&["lib-utils/src/lib.rs"]);
     let excluded = excludes(&["lib-core"]);
-    let result = compute_affected(graph, &changed, &[], &excluded);
+    let result = compute_affected(graph, &changed, &[], &excluded).unwrap();
 
     // lib-core is excluded from output…
     assert!(!result.changed_crates.contains(&"lib-core".to_string()));
@@ -548,7 +1229,7 @@ fn excluded_mid_graph_crate_still_traversed() {
 fn build_script_in_crate_dir_detected() {
     let graph = fixture_graph();
     let changed = s(&["lib-core/build.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(result.changed_crates, vec!["lib-core"]);
     assert_eq!(
@@ -563,7 +1244,7 @@ fn build_script_in_crate_dir_detected() {
 fn path_prefix_no_false_positive() {
     let graph = fixture_graph();
     let changed = s(&["lib-core-ext/src/lib.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     // Only lib-core-ext itself should be in changed_crates, NOT lib-core
     assert_eq!(result.changed_crates, vec!["lib-core-ext"]);
@@ -573,6 +1254,50 @@ fn path_prefix_no_false_positive() {
     assert!(result.affected_binary_members.is_empty());
 }
 
+// ── Case-insensitive paths ──────────────────────────────────────────
+
+#[test]
+fn case_mismatched_path_matches_nothing_by_default() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .compute(graph, &s(&["Lib-Core/src/lib.rs"]))
+        .unwrap();
+    assert!(result.changed_crates.is_empty());
+}
+
+#[test]
+fn case_insensitive_paths_match_crate_directories() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .case_insensitive_paths(true)
+        .compute(
+            graph,
+            &s(&["Lib-Core/src/lib.rs", "TOOLS/Tool-Alpha/src/main.rs"]),
+        )
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-core", "tool-alpha"]);
+}
+
+#[test]
+fn case_insensitive_paths_keep_exclusions_case_sensitive() {
+    let graph = fixture_graph();
+    let changed = s(&["TOOLS/tool-alpha/src/main.rs"]);
+    let result = AffectedOptions::new()
+        .case_insensitive_paths(true)
+        .excluded(["TOOLS/"])
+        .compute(graph, &changed)
+        .unwrap();
+    assert_eq!(result.changed_crates, vec!["tool-alpha"]);
+
+    let result = AffectedOptions::new()
+        .case_insensitive_paths(true)
+        .excluded(["tools/"])
+        .compute(graph, &changed)
+        .unwrap();
+    assert!(result.changed_crates.is_empty());
+}
+
 // ── Path-based exclusion ────────────────────────────────────────────
 
 #[test]
@@ -583,7 +1308,7 @@ fn path_prefix_excludes_nested_crate() {
     // should remove it from all output lists.
     let changed = s(&["lib-utils/src/lib.rs"]);
     let excluded = excludes(&["tools/"]);
-    let result = compute_affected(graph, &changed, &[], &excluded);
+    let result = compute_affected(graph, &changed, &[], &excluded).unwrap();
 
     assert!(
         !result
@@ -614,7 +1339,7 @@ fn path_prefix_excludes_nested_crate_from_force_all() {
     let changed = s(&["infra/deploy.yml"]);
     let triggers = s(&["infra/"]);
     let excluded = excludes(&["tools/"]);
-    let result = compute_affected(graph, &changed, &triggers, &excluded);
+    let result = compute_affected(graph, &changed, &triggers, &excluded).unwrap();
 
     assert!(result.force_all);
     assert!(
@@ -640,7 +1365,7 @@ fn path_prefix_excludes_direct_change_in_nested_crate() {
     let graph = fixture_graph();
     let changed = s(&["tools/tool-alpha/src/main.rs"]);
     let excluded = excludes(&["tools/"]);
-    let result = compute_affected(graph, &changed, &[], &excluded);
+    let result = compute_affected(graph, &changed, &[], &excluded).unwrap();
 
     assert!(result.changed_crates.is_empty());
     assert!(result.affected_library_members.is_empty());
@@ -653,7 +1378,7 @@ fn exact_path_excludes_specific_nested_crate() {
     // Exclude by exact relative directory path (no trailing slash)
     let changed = s(&["lib-utils/src/lib.rs"]);
     let excluded = excludes(&["tools/tool-alpha"]);
-    let result = compute_affected(graph, &changed, &[], &excluded);
+    let result = compute_affected(graph, &changed, &[], &excluded).unwrap();
 
     assert!(
         !result
@@ -671,20 +1396,74 @@ fn exact_path_excludes_specific_nested_crate() {
 fn nested_crate_detected_when_directly_changed() {
     let graph = fixture_graph();
     let changed = s(&["tools/tool-alpha/src/main.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(result.changed_crates, vec!["tool-alpha"]);
     assert!(result.affected_library_members.is_empty());
     assert_eq!(result.affected_binary_members, vec!["tool-alpha"]);
 }
 
+/// The fixture workspace loaded with its workspace root reported with a
+/// trailing separator, as some platforms and cargo versions do.
+fn trailing_separator_graph() -> &'static PackageGraph {
+    static GRAPH: OnceLock<PackageGraph> = OnceLock::new();
+    GRAPH.get_or_init(|| {
+        let output = std::process::Command::new(env!("CARGO"))
+            .args(["metadata", "--format-version", "1"])
+            .current_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workspace"))
+            .output()
+            .expect("Failed to run cargo metadata");
+        assert!(output.status.success());
+        let mut metadata: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let root = metadata["workspace_root"].as_str().unwrap().to_string();
+        metadata["workspace_root"] = format!("{root}/").into();
+        PackageGraph::from_json(metadata.to_string()).expect("Failed to build package graph")
+    })
+}
+
+#[test]
+fn path_prefix_exclusion_stable_with_trailing_separator_root() {
+    let graph = trailing_separator_graph();
+    assert!(graph.workspace().root().as_str().ends_with('/'));
+
+    let changed = s(&["lib-utils/src/lib.rs", "tools/tool-alpha/src/main.rs"]);
+    for excluded in [
+        excludes(&["tools/"]),
+        excludes(&["tools/tool-alpha"]),
+        excludes(&["tools/tool-alpha/"]),
+    ] {
+        let result = compute_affected(graph, &changed, &[], &excluded).unwrap();
+        assert_eq!(result.changed_crates, vec!["lib-utils"], "{excluded:?}");
+        assert!(
+            !result
+                .affected_binary_members
+                .contains(&"tool-alpha".to_string()),
+            "{excluded:?}"
+        );
+        assert_eq!(
+            result,
+            compute_affected(fixture_graph(), &changed, &[], &excluded).unwrap()
+        );
+    }
+}
+
+#[test]
+fn attribution_stable_with_trailing_separator_root() {
+    let graph = trailing_separator_graph();
+    assert_eq!(
+        crate_names(graph, "tools/tool-alpha/src/main.rs"),
+        vec!["tool-alpha"]
+    );
+    assert_eq!(crate_names(graph, "lib-core/src/lib.rs"), vec!["lib-core"]);
+}
+
 // ── Library crate with integration tests is not a binary ──────────────
 
 #[test]
 fn library_with_tests_is_not_binary() {
     let graph = fixture_graph();
     let changed = s(&["lib-with-tests/src/lib.rs"]);
-    let result = compute_affected(graph, &changed, &[], &no_excludes());
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
 
     assert_eq!(result.changed_crates, vec!["lib-with-tests"]);
     assert_eq!(result.affected_library_members, vec!["lib-with-tests"]);
@@ -698,7 +1477,7 @@ fn library_with_tests_excluded_from_binaries_on_force_all() {
     let graph = fixture_graph();
     let changed = s(&["infra/deploy.yml"]);
     let triggers = s(&["infra/"]);
-    let result = compute_affected(graph, &changed, &triggers, &no_excludes());
+    let result = compute_affected(graph, &changed, &triggers, &no_excludes()).unwrap();
 
     assert!(result.force_all);
     // lib-with-tests should appear in library members but NOT binary members
@@ -713,3 +1492,2634 @@ fn library_with_tests_excluded_from_binaries_on_force_all() {
             .contains(&"lib-with-tests".to_string())
     );
 }
+
+// ── Platform-specific resolution ────────────────────────────────────
+
+#[test]
+fn no_target_follows_platform_specific_edges() {
+    let graph = fixture_graph();
+    // lib-portable depends on lib-win-sys only under cfg(windows)
+    let changed = s(&["lib-win-sys/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-win-sys"]);
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-portable", "lib-win-sys"]
+    );
+}
+
+#[test]
+fn target_skips_edges_for_other_platforms() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-win-sys/src/lib.rs"]);
+    let result = AffectedOptions::new()
+        .target("x86_64-unknown-linux-gnu")
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-win-sys"]);
+    assert_eq!(result.affected_library_members, vec!["lib-win-sys"]);
+}
+
+#[test]
+fn target_follows_edges_for_matching_platform() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-win-sys/src/lib.rs"]);
+    let result = AffectedOptions::new()
+        .target("x86_64-pc-windows-msvc")
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-portable", "lib-win-sys"]
+    );
+}
+
+#[test]
+fn target_does_not_affect_unconditional_edges() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = AffectedOptions::new()
+        .target("aarch64-apple-darwin")
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-core-ext", "lib-utils"]
+    );
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta", "tool-alpha"]
+    );
+}
+
+// ── Dev-dependency edges ────────────────────────────────────────────
+
+#[test]
+fn dev_dependents_affected_by_default() {
+    let graph = fixture_graph();
+    // lib-with-tests dev-depends on lib-test-utils
+    let changed = s(&["lib-test-utils/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-test-utils"]);
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-test-utils", "lib-with-tests"]
+    );
+}
+
+#[test]
+fn dev_dependents_not_affected_when_dev_edges_disabled() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-test-utils/src/lib.rs"]);
+    let result = AffectedOptions::new()
+        .include_dev(false)
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-test-utils"]);
+    assert_eq!(result.affected_library_members, vec!["lib-test-utils"]);
+}
+
+#[test]
+fn disabling_dev_edges_keeps_normal_dependents() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = AffectedOptions::new()
+        .include_dev(false)
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-core-ext", "lib-utils"]
+    );
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta", "tool-alpha"]
+    );
+}
+
+// ── Split build / test sets ─────────────────────────────────────────
+
+#[test]
+fn dev_dependent_only_in_affected_test() {
+    let graph = fixture_graph();
+    // lib-with-tests dev-depends on lib-test-utils
+    let result = AffectedOptions::new()
+        .split_build_test(true)
+        .compute(graph, &s(&["lib-test-utils/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.affected_build, vec!["lib-test-utils"]);
+    assert_eq!(
+        result.affected_test,
+        vec!["lib-test-utils", "lib-with-tests"]
+    );
+}
+
+#[test]
+fn split_build_test_lists_binaries_too() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .split_build_test(true)
+        .excluded(["tools/"])
+        .compute(graph, &s(&["lib-utils/src/lib.rs"]))
+        .unwrap();
+
+    let expected = vec![
+        "app-alpha",
+        "app-beta",
+        "lib-core",
+        "lib-core-ext",
+        "lib-utils",
+    ];
+    assert_eq!(result.affected_build, expected);
+    assert_eq!(result.affected_test, expected);
+}
+
+#[test]
+fn split_build_test_ignores_include_dev() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .split_build_test(true)
+        .include_dev(false)
+        .compute(graph, &s(&["lib-test-utils/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.affected_library_members, vec!["lib-test-utils"]);
+    assert_eq!(
+        result.affected_test,
+        vec!["lib-test-utils", "lib-with-tests"]
+    );
+}
+
+#[test]
+fn split_build_test_off_by_default() {
+    let graph = fixture_graph();
+    let result = compute_affected(
+        graph,
+        &s(&["lib-test-utils/src/lib.rs"]),
+        &[],
+        &no_excludes(),
+    )
+    .unwrap();
+    assert!(result.affected_build.is_empty());
+    assert!(result.affected_test.is_empty());
+}
+
+// ── Graph diff ──────────────────────────────────────────────────────
+
+#[test]
+fn graph_diff_reports_added_edge() {
+    let before = load_graph(&["graph-diff", "before"]);
+    let after = load_graph(&["graph-diff", "after"]);
+
+    assert_eq!(
+        graph_diff(&before, &after),
+        GraphDiff {
+            changed_members: vec![MemberDiff {
+                name: "app-alpha".to_string(),
+                added: vec!["lib-utils".to_string()],
+                removed: vec![],
+            }],
+            deps_removed: vec![],
+        }
+    );
+}
+
+#[test]
+fn graph_diff_reports_removed_edge() {
+    let before = load_graph(&["graph-diff", "before"]);
+    let after = load_graph(&["graph-diff", "after"]);
+
+    let diff = graph_diff(&after, &before);
+    assert_eq!(diff.changed_members.len(), 1);
+    assert_eq!(diff.changed_members[0].name, "app-alpha");
+    assert!(diff.changed_members[0].added.is_empty());
+    assert_eq!(diff.changed_members[0].removed, vec!["lib-utils"]);
+}
+
+#[test]
+fn graph_diff_flags_members_that_lost_a_dependency() {
+    let before = load_graph(&["graph-diff", "before"]);
+    let after = load_graph(&["graph-diff", "after"]);
+
+    assert_eq!(graph_diff(&after, &before).deps_removed, vec!["app-alpha"]);
+    // Gaining a dependency is not a removal.
+    assert!(graph_diff(&before, &after).deps_removed.is_empty());
+}
+
+#[test]
+fn graph_diff_identical_graphs_is_empty() {
+    let graph = fixture_graph();
+    assert_eq!(graph_diff(graph, graph), GraphDiff::default());
+}
+
+// ── Pruning excluded crates from traversal ──────────────────────────
+
+#[test]
+fn prune_excluded_cuts_traversal_through_excluded_crate() {
+    let graph = fixture_graph();
+    // lib-utils → lib-core → lib-core-ext, app-alpha, app-beta; lib-utils → tool-alpha
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let excluded = excludes(&["lib-core"]);
+    let result = AffectedOptions::new()
+        .excluded(excluded)
+        .prune_excluded(true)
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-utils"]);
+    assert_eq!(result.affected_library_members, vec!["lib-utils"]);
+    assert_eq!(result.affected_binary_members, vec!["tool-alpha"]);
+}
+
+#[test]
+fn prune_excluded_drops_excluded_starting_point() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-core/src/lib.rs"]);
+    let excluded = excludes(&["lib-core"]);
+    let result = AffectedOptions::new()
+        .excluded(excluded)
+        .prune_excluded(true)
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert!(result.changed_crates.is_empty());
+    assert!(result.affected_library_members.is_empty());
+    assert!(result.affected_binary_members.is_empty());
+}
+
+#[test]
+fn prune_excluded_by_path_prefix() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-standalone/src/lib.rs"]);
+    let excluded = excludes(&["app-beta/"]);
+    let result = AffectedOptions::new()
+        .excluded(excluded)
+        .prune_excluded(true)
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(result.affected_library_members, vec!["lib-standalone"]);
+    assert!(result.affected_binary_members.is_empty());
+}
+
+#[test]
+fn prune_excluded_ignored_on_force_all() {
+    let graph = fixture_graph();
+    let changed = s(&["infra/deploy.yml"]);
+    let triggers = s(&["infra/"]);
+    let excluded = excludes(&["lib-core"]);
+    let result = AffectedOptions::new()
+        .force_triggers(triggers)
+        .excluded(excluded)
+        .prune_excluded(true)
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert!(result.force_all);
+    assert!(
+        result
+            .affected_library_members
+            .contains(&"lib-core-ext".to_string())
+    );
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta", "tool-alpha"]
+    );
+}
+
+// ── Release order ───────────────────────────────────────────────────
+
+#[test]
+fn release_order_puts_dependencies_first() {
+    let graph = fixture_graph();
+    // lib-utils → lib-core → lib-core-ext, app-alpha, app-beta; lib-utils → tool-alpha
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    // tool-alpha is `publish = false`, so it is left out.
+    assert_eq!(
+        release_order(graph, &result),
+        vec![
+            "lib-utils",
+            "lib-core",
+            "app-alpha",
+            "app-beta",
+            "lib-core-ext"
+        ]
+    );
+}
+
+#[test]
+fn release_order_skips_unpublishable_changed_crate() {
+    let graph = fixture_graph();
+    let changed = s(&["tools/tool-alpha/src/main.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(result.affected_binary_members, vec!["tool-alpha"]);
+    assert!(release_order(graph, &result).is_empty());
+}
+
+// ── Public API gate ─────────────────────────────────────────────────
+
+#[test]
+fn api_unchanged_crate_does_not_propagate() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-core/src/lib.rs"]);
+    let result = AffectedOptions::new()
+        .api_changed([("lib-core", false)])
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-core"]);
+    assert!(
+        result
+            .affected_library_members
+            .contains(&"lib-core".to_string())
+    );
+    assert!(result.affected_binary_members.is_empty());
+}
+
+#[test]
+fn api_changed_crate_propagates_as_usual() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-core/src/lib.rs"]);
+    let result = AffectedOptions::new()
+        .api_changed([("lib-core", true)])
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-core-ext"]
+    );
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta"]
+    );
+}
+
+#[test]
+fn api_unchanged_crate_still_reached_through_other_change() {
+    let graph = fixture_graph();
+    // lib-utils changed its API, so lib-core's dependents are affected through it.
+    let changed = s(&["lib-utils/src/lib.rs", "lib-core/src/lib.rs"]);
+    let result = AffectedOptions::new()
+        .api_changed([("lib-core", false)])
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-core-ext", "lib-utils"]
+    );
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta", "tool-alpha"]
+    );
+}
+
+// ── Build order ─────────────────────────────────────────────────────
+
+#[test]
+fn build_order_puts_dependencies_first() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-core-ext", "lib-utils"]
+    );
+    assert_eq!(
+        result.affected_build_order,
+        vec!["lib-utils", "lib-core", "lib-core-ext"]
+    );
+}
+
+#[test]
+fn build_order_on_force_all_covers_every_library() {
+    let graph = fixture_graph();
+    let changed = s(&["infra/deploy.yml"]);
+    let triggers = s(&["infra/"]);
+    let result = compute_affected(graph, &changed, &triggers, &no_excludes()).unwrap();
+
+    // Independent libraries sort alphabetically; dependents follow their dependencies.
+    // lib-with-tests only dev-depends on lib-test-utils, which doesn't order builds.
+    assert_eq!(
+        result.affected_build_order,
+        vec![
+            "lib-standalone",
+            "lib-test-utils",
+            "lib-utils",
+            "lib-win-sys",
+            "lib-with-tests",
+            "lib-core",
+            "lib-portable",
+            "lib-core-ext",
+        ]
+    );
+}
+
+// ── Source hashes ───────────────────────────────────────────────────
+
+#[test]
+fn source_hashes_are_off_by_default() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-core/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert!(result.source_hashes.is_empty());
+}
+
+#[test]
+fn source_hashes_cover_every_affected_member() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-core/src/lib.rs"]);
+    let options = AffectedOptions::new().source_hashes(true);
+    let result = options.compute(graph, &changed).unwrap();
+
+    let names: Vec<&str> = result.source_hashes.keys().map(String::as_str).collect();
+    assert_eq!(
+        names,
+        vec!["app-alpha", "app-beta", "lib-core", "lib-core-ext"]
+    );
+    // Stable across runs over an unchanged tree.
+    assert_eq!(
+        options.compute(graph, &changed).unwrap().source_hashes,
+        result.source_hashes
+    );
+}
+
+#[test]
+fn source_hash_changes_with_file_contents() {
+    let dir =
+        std::env::temp_dir().join(format!("rust-affected-source-hash-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src/lib.rs"), "pub fn a() {}\n").unwrap();
+
+    let before = source_hash(&dir).unwrap();
+    assert_eq!(source_hash(&dir).unwrap(), before);
+
+    // Build output is ignored.
+    std::fs::create_dir_all(dir.join("target/debug")).unwrap();
+    std::fs::write(dir.join("target/debug/out"), "artifact").unwrap();
+    assert_eq!(source_hash(&dir).unwrap(), before);
+
+    std::fs::write(dir.join("src/lib.rs"), "pub fn b() {}\n").unwrap();
+    let after = source_hash(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_ne!(after, before);
+}
+
+// ── Dependency levels ───────────────────────────────────────────────
+
+#[test]
+fn levels_group_affected_crates_by_depth() {
+    let graph = fixture_graph();
+    // lib-utils → lib-core → lib-core-ext, app-alpha, app-beta; lib-utils → tool-alpha
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(
+        affected_levels(graph, &result),
+        vec![
+            s(&["lib-utils"]),
+            s(&["lib-core", "tool-alpha"]),
+            s(&["app-alpha", "app-beta", "lib-core-ext"]),
+        ]
+    );
+}
+
+#[test]
+fn levels_put_independent_changes_on_the_first_level() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-core/src/lib.rs", "lib-standalone/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    let levels = affected_levels(graph, &result);
+    assert_eq!(levels[0], result.changed_crates);
+    assert_eq!(levels[1..], [s(&["app-alpha", "app-beta", "lib-core-ext"])]);
+}
+
+#[test]
+fn levels_are_empty_without_changes() {
+    let graph = fixture_graph();
+    let result = compute_affected(graph, &[], &[], &no_excludes()).unwrap();
+
+    assert!(affected_levels(graph, &result).is_empty());
+}
+
+// ── Reverse dependent counts ────────────────────────────────────────
+
+#[test]
+fn reverse_dependent_counts_per_changed_crate() {
+    let graph = fixture_graph();
+    let changed = s(&[
+        "lib-utils/src/lib.rs",
+        "lib-core/src/lib.rs",
+        "app-alpha/src/main.rs",
+    ]);
+    let counts = AffectedOptions::new()
+        .reverse_dependent_counts(graph, &changed)
+        .unwrap();
+
+    // lib-utils reaches lib-core, lib-core-ext, app-alpha, app-beta and tool-alpha.
+    assert_eq!(
+        counts,
+        BTreeMap::from([
+            ("app-alpha".to_string(), 0),
+            ("lib-core".to_string(), 3),
+            ("lib-utils".to_string(), 5),
+        ])
+    );
+}
+
+#[test]
+fn reverse_dependent_counts_respect_exclusions() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs", "tools/tool-alpha/src/main.rs"]);
+    let counts = AffectedOptions::new()
+        .excluded(["tools/", "lib-core-ext"])
+        .reverse_dependent_counts(graph, &changed)
+        .unwrap();
+
+    assert_eq!(counts, BTreeMap::from([("lib-utils".to_string(), 3)]));
+}
+
+// ── Codegen inputs ──────────────────────────────────────────────────
+
+#[test]
+fn codegen_input_change_propagates_to_dependents() {
+    let graph = fixture_graph();
+    let changed = s(&["schemas/user.json"]);
+    let result = AffectedOptions::new()
+        .codegen_inputs([("schemas/*.json", ["lib-core"])])
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-core"]);
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-core-ext"]
+    );
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta"]
+    );
+}
+
+#[test]
+fn codegen_input_not_matching_has_no_effect() {
+    let graph = fixture_graph();
+    let changed = s(&["schemas/README.md"]);
+    let result = AffectedOptions::new()
+        .codegen_inputs([("schemas/*.json", ["lib-core"])])
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert!(result.changed_crates.is_empty());
+    assert!(result.affected_library_members.is_empty());
+}
+
+// ── Workspace manifest ──────────────────────────────────────────────
+
+#[test]
+fn workspace_manifest_lists_affected_member_paths() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    let manifest: toml::Table = workspace_manifest(graph, &result).unwrap().parse().unwrap();
+    let members: Vec<&str> = manifest["workspace"]["members"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m.as_str().unwrap())
+        .collect();
+    assert_eq!(
+        members,
+        vec![
+            "app-alpha",
+            "app-beta",
+            "lib-core",
+            "lib-core-ext",
+            "lib-utils",
+            "tools/tool-alpha",
+        ]
+    );
+    assert_eq!(manifest["workspace"]["resolver"].as_str(), Some("2"));
+}
+
+#[test]
+fn workspace_manifest_keeps_the_rest_of_the_root_manifest() {
+    let graph = workspace_dependencies_graph();
+    let result = compute_affected(graph, &s(&["util/src/lib.rs"]), &[], &no_excludes()).unwrap();
+
+    let manifest: toml::Table = workspace_manifest(graph, &result).unwrap().parse().unwrap();
+    let workspace = manifest["workspace"].as_table().unwrap();
+    assert_eq!(workspace["resolver"].as_str(), Some("2"));
+    assert_eq!(
+        workspace["dependencies"]["util"]["default-features"].as_bool(),
+        Some(false)
+    );
+    let members: Vec<&str> = workspace["members"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m.as_str().unwrap())
+        .collect();
+    assert!(members.contains(&"util"), "{members:?}");
+    assert!(!members.contains(&"base"), "{members:?}");
+}
+
+// ── Maximum traversal depth ─────────────────────────────────────────
+
+#[test]
+fn max_depth_zero_keeps_only_changed_crates() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = AffectedOptions::new()
+        .max_depth(0)
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(result.affected_library_members, vec!["lib-utils"]);
+    assert!(result.affected_binary_members.is_empty());
+}
+
+#[test]
+fn max_depth_one_adds_immediate_dependents() {
+    let graph = fixture_graph();
+    // lib-utils → lib-core, tool-alpha; lib-core → lib-core-ext, app-alpha, app-beta
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = AffectedOptions::new()
+        .max_depth(1)
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-utils"]
+    );
+    assert_eq!(result.affected_binary_members, vec!["tool-alpha"]);
+}
+
+#[test]
+fn max_depth_beyond_graph_matches_unlimited() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let limited = AffectedOptions::new()
+        .max_depth(10)
+        .compute(graph, &changed)
+        .unwrap();
+    let unlimited = AffectedOptions::new().compute(graph, &changed).unwrap();
+
+    assert_eq!(limited, unlimited);
+}
+
+// ── Transitively affected members ───────────────────────────────────
+
+#[test]
+fn transitively_affected_excludes_changed_crates() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(
+        result.transitively_affected_members,
+        vec!["lib-core", "lib-core-ext"]
+    );
+}
+
+#[test]
+fn changed_dependent_counts_as_changed_not_transitive() {
+    let graph = fixture_graph();
+    // lib-core depends on lib-utils, but changed itself.
+    let changed = s(&["lib-utils/src/lib.rs", "lib-core/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-core", "lib-utils"]);
+    assert_eq!(result.transitively_affected_members, vec!["lib-core-ext"]);
+}
+
+// ── Force-all ratio escalation ──────────────────────────────────────
+
+#[test]
+fn affected_ratio_under_each_denominator() {
+    let graph = fixture_graph();
+    // 6 of 11 members are affected; 5 unrelated members are excluded.
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let unrelated = [
+        "lib-standalone",
+        "lib-with-tests",
+        "lib-test-utils",
+        "lib-win-sys",
+        "lib-portable",
+    ];
+    let all = AffectedOptions::new().excluded(unrelated);
+    let non_excluded = all.clone().ratio_denominator(RatioDenominator::NonExcluded);
+    let result = all.compute(graph, &changed).unwrap();
+
+    assert_eq!(all.affected_ratio(graph, &result), 6.0 / 11.0);
+    assert_eq!(non_excluded.affected_ratio(graph, &result), 1.0);
+}
+
+#[test]
+fn force_all_ratio_escalation_depends_on_denominator() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let options = AffectedOptions::new()
+        .excluded(["lib-standalone", "lib-win-sys", "lib-portable"])
+        .force_all_ratio(0.75);
+
+    // 6 / 11 ≈ 0.55 stays below the threshold.
+    let result = options.compute(graph, &changed).unwrap();
+    assert!(!result.force_all);
+    assert_eq!(result.affected_binary_members.len(), 3);
+
+    // 6 / 8 = 0.75 reaches it, so the whole (non-excluded) workspace is affected.
+    let result = options
+        .ratio_denominator(RatioDenominator::NonExcluded)
+        .compute(graph, &changed)
+        .unwrap();
+    assert!(result.force_all);
+    assert_eq!(
+        result.affected_library_members,
+        vec![
+            "lib-core",
+            "lib-core-ext",
+            "lib-test-utils",
+            "lib-utils",
+            "lib-with-tests",
+        ]
+    );
+}
+
+// ── Dependencies of changed crates ──────────────────────────────────
+
+#[test]
+fn dependencies_of_changed_are_off_by_default() {
+    let graph = fixture_graph();
+    let changed = s(&["app-alpha/src/main.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert!(result.dependencies_of_changed.is_empty());
+}
+
+#[test]
+fn dependencies_of_changed_follow_forward_edges() {
+    let graph = fixture_graph();
+    // app-alpha → lib-core → lib-utils
+    let changed = s(&["app-alpha/src/main.rs"]);
+    let result = AffectedOptions::new()
+        .dependencies_of_changed(true)
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(
+        result.dependencies_of_changed,
+        vec!["lib-core", "lib-utils"]
+    );
+    // The reverse traversal is unchanged.
+    assert_eq!(result.affected_binary_members, vec!["app-alpha"]);
+}
+
+#[test]
+fn dependencies_of_changed_include_own_dev_dependencies() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-with-tests/src/lib.rs"]);
+    let options = AffectedOptions::new().dependencies_of_changed(true);
+
+    let result = options.clone().compute(graph, &changed).unwrap();
+    assert_eq!(result.dependencies_of_changed, vec!["lib-test-utils"]);
+
+    let result = options.include_dev(false).compute(graph, &changed).unwrap();
+    assert!(result.dependencies_of_changed.is_empty());
+}
+
+// ── is_excluded ─────────────────────────────────────────────────────
+
+#[test]
+fn is_excluded_matches_names_and_path_prefixes() {
+    let excluded = excludes(&["resource-clone", "tools/", "services/api"]);
+
+    assert!(is_excluded(
+        "resource-clone",
+        Path::new("anywhere/else"),
+        &excluded
+    ));
+    assert!(is_excluded(
+        "tool-alpha",
+        Path::new("tools/tool-alpha"),
+        &excluded
+    ));
+    assert!(is_excluded("tools-root", Path::new("tools"), &excluded));
+    assert!(is_excluded("api", Path::new("services/api"), &excluded));
+
+    // Prefixes match whole path components only.
+    assert!(!is_excluded(
+        "api-v2",
+        Path::new("services/api-v2"),
+        &excluded
+    ));
+    assert!(!is_excluded("toolsmith", Path::new("toolsmith"), &excluded));
+    // Names don't match directories, and paths don't match names.
+    assert!(!is_excluded(
+        "lib-core",
+        Path::new("resource-clone"),
+        &excluded
+    ));
+    assert!(!is_excluded("tools", Path::new("lib/tools"), &excluded));
+}
+
+#[test]
+fn is_excluded_matches_glob_entries() {
+    let excluded = excludes(&["*-generated", "tools/*-gen", "lib-[xy]"]);
+
+    assert!(is_excluded("api-generated", Path::new("api"), &excluded));
+    assert!(is_excluded("lib-x", Path::new("lib-x"), &excluded));
+    assert!(is_excluded(
+        "proto",
+        Path::new("tools/proto-gen"),
+        &excluded
+    ));
+    assert!(is_excluded(
+        "proto-inner",
+        Path::new("tools/proto-gen/inner"),
+        &excluded
+    ));
+
+    assert!(!is_excluded("generated", Path::new("generated"), &excluded));
+    assert!(!is_excluded("lib-z", Path::new("lib-z"), &excluded));
+    // `*` does not cross a path separator.
+    assert!(!is_excluded(
+        "proto",
+        Path::new("tools/nested/proto-gen"),
+        &excluded
+    ));
+}
+
+#[test]
+fn glob_exclusion_drops_matching_crates() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-core/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &excludes(&["app-*"])).unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-core"]);
+    assert!(
+        result
+            .affected_library_members
+            .contains(&"lib-core".to_string())
+    );
+    assert!(result.affected_binary_members.is_empty());
+}
+
+// ── Degrees ─────────────────────────────────────────────────────────
+
+#[test]
+fn degrees_count_direct_workspace_edges() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = AffectedOptions::new()
+        .degrees(true)
+        .compute(graph, &changed)
+        .unwrap();
+
+    // lib-utils ← lib-core, tool-alpha; lib-core ← lib-core-ext, app-alpha, app-beta
+    assert_eq!(
+        result.degrees,
+        BTreeMap::from([
+            ("app-alpha".to_string(), (0, 1)),
+            ("app-beta".to_string(), (0, 2)),
+            ("lib-core".to_string(), (3, 1)),
+            ("lib-core-ext".to_string(), (0, 1)),
+            ("lib-utils".to_string(), (2, 0)),
+            ("tool-alpha".to_string(), (0, 1)),
+        ])
+    );
+}
+
+#[test]
+fn degrees_are_off_by_default() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-utils/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert!(result.degrees.is_empty());
+}
+
+// ── Indexed members ─────────────────────────────────────────────────
+
+#[test]
+fn indexed_members_are_contiguous_in_sorted_order() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .indexed(true)
+        .compute(graph, &s(&["lib-core/src/lib.rs"]))
+        .unwrap();
+
+    let mut sorted: Vec<String> = result
+        .affected_library_members
+        .iter()
+        .chain(&result.affected_binary_members)
+        .cloned()
+        .collect();
+    sorted.sort();
+    let expected: Vec<(usize, String)> = sorted.into_iter().enumerate().collect();
+    assert_eq!(result.indexed, expected);
+    assert_eq!(
+        result.indexed,
+        vec![
+            (0, "app-alpha".to_string()),
+            (1, "app-beta".to_string()),
+            (2, "lib-core".to_string()),
+            (3, "lib-core-ext".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn indexed_serializes_as_index_name_objects() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .indexed(true)
+        .compute(graph, &s(&["lib-standalone/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(
+        serde_json::to_value(&result).unwrap()["indexed"],
+        serde_json::json!([
+            {"index": 0, "name": "app-beta"},
+            {"index": 1, "name": "lib-standalone"},
+        ])
+    );
+}
+
+#[test]
+fn indexed_is_off_by_default() {
+    let graph = fixture_graph();
+    let result =
+        compute_affected(graph, &s(&["lib-core/src/lib.rs"]), &[], &no_excludes()).unwrap();
+    assert!(result.indexed.is_empty());
+    assert!(
+        serde_json::to_value(&result)
+            .unwrap()
+            .get("indexed")
+            .is_none()
+    );
+}
+
+// ── Workflow crate map ──────────────────────────────────────────────
+
+#[test]
+fn mapped_workflow_change_seeds_crate_instead_of_force_all() {
+    let graph = fixture_graph();
+    let changed = s(&[".github/workflows/ci-app-alpha.yml"]);
+    let result = AffectedOptions::new()
+        .force_triggers([".github/"])
+        .workflow_crate_map([("ci-app-alpha.yml", ["app-alpha"])])
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert!(!result.force_all);
+    assert_eq!(result.changed_crates, vec!["app-alpha"]);
+    assert_eq!(result.affected_binary_members, vec!["app-alpha"]);
+}
+
+#[test]
+fn unmapped_workflow_change_falls_back_to_force_trigger() {
+    let graph = fixture_graph();
+    let changed = s(&[".github/workflows/release.yml"]);
+    let result = AffectedOptions::new()
+        .force_triggers([".github/"])
+        .workflow_crate_map([("ci-app-alpha.yml", ["app-alpha"])])
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert!(result.force_all);
+}
+
+#[test]
+fn workflow_map_accepts_full_paths() {
+    let graph = fixture_graph();
+    let changed = s(&["ci/lib-core.yml"]);
+    let result = AffectedOptions::new()
+        .force_triggers(["ci/"])
+        .workflow_crate_map([("ci/lib-core.yml", ["lib-core"])])
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert!(!result.force_all);
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-core-ext"]
+    );
+}
+
+// ── Submodule crate map ─────────────────────────────────────────────
+
+#[test]
+fn submodule_pointer_change_seeds_mapped_crates() {
+    let graph = fixture_graph();
+    // A submodule bump lists only the submodule path, not the files inside it.
+    let changed = s(&["vendor/protocol"]);
+    let result = AffectedOptions::new()
+        .submodule_crate_map([("vendor/protocol", ["lib-core"])])
+        .compute(graph, &changed)
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-core"]);
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-core-ext"]
+    );
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta"]
+    );
+}
+
+#[test]
+fn file_inside_submodule_seeds_mapped_crates() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .submodule_crate_map([("vendor/protocol/", ["lib-standalone"])])
+        .compute(graph, &s(&["vendor/protocol/schema.proto"]))
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-standalone"]);
+}
+
+#[test]
+fn unmapped_submodule_change_seeds_nothing() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .submodule_crate_map([("vendor/protocol", ["lib-core"])])
+        .compute(graph, &s(&["vendor/protocol-v2"]))
+        .unwrap();
+
+    assert!(result.changed_crates.is_empty());
+}
+
+// ── Published versions ──────────────────────────────────────────────
+
+#[test]
+fn unbumped_changed_crate_needs_version_bump() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .published_versions([
+            ("lib-core", "0.1.0"),
+            ("lib-core-ext", "0.0.9"),
+            ("app-alpha", "0.1.0"),
+        ])
+        .compute(graph, &s(&["lib-core/src/lib.rs"]))
+        .unwrap();
+
+    // lib-core-ext was bumped past its published version; app-beta isn't listed.
+    assert_eq!(result.needs_version_bump, vec!["app-alpha", "lib-core"]);
+}
+
+#[test]
+fn unaffected_crate_never_needs_version_bump() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .published_versions([("lib-standalone", "0.1.0")])
+        .compute(graph, &s(&["lib-core/src/lib.rs"]))
+        .unwrap();
+
+    assert!(result.needs_version_bump.is_empty());
+}
+
+// ── Selector ────────────────────────────────────────────────────────
+
+#[test]
+fn select_intersects_kind_and_path() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .select(Selector {
+            kind: Some(MemberKind::Binary),
+            path: Some("tools/**".to_string()),
+        })
+        .compute(graph, &s(&["lib-utils/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.affected_binary_members, vec!["tool-alpha"]);
+    assert!(result.affected_library_members.is_empty());
+    assert!(result.affected_build_order.is_empty());
+    // The changed crates are reported regardless of the selector.
+    assert_eq!(result.changed_crates, vec!["lib-utils"]);
+}
+
+#[test]
+fn select_kind_only() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .select(Selector {
+            kind: Some(MemberKind::Library),
+            path: None,
+        })
+        .compute(graph, &s(&["lib-core/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-core-ext"]
+    );
+    assert!(result.affected_binary_members.is_empty());
+}
+
+#[test]
+fn select_path_only() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .select(Selector {
+            kind: None,
+            path: Some("lib-core*".to_string()),
+        })
+        .compute(graph, &s(&["lib-utils/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(
+        result.affected_library_members,
+        vec!["lib-core", "lib-core-ext"]
+    );
+    assert!(result.affected_binary_members.is_empty());
+}
+
+#[test]
+fn selector_deserializes_from_json() {
+    let selector: Selector =
+        serde_json::from_str(r#"{"kind": "binary", "path": "services/**"}"#).unwrap();
+    assert_eq!(
+        selector,
+        Selector {
+            kind: Some(MemberKind::Binary),
+            path: Some("services/**".to_string()),
+        }
+    );
+    assert!(serde_json::from_str::<Selector>(r#"{"kind": "service"}"#).is_err());
+    assert!(serde_json::from_str::<Selector>(r#"{"paths": "tools/"}"#).is_err());
+}
+
+// ── Workspace lints ─────────────────────────────────────────────────
+
+#[test]
+fn root_manifest_change_forces_all_with_lints_flag() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .default_force_triggers(false)
+        .lints_change_forces_all(true)
+        .compute(graph, &s(&["Cargo.toml"]))
+        .unwrap();
+
+    assert!(result.force_all);
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta", "tool-alpha"]
+    );
+    assert_eq!(
+        result.affected_library_members,
+        vec![
+            "lib-core",
+            "lib-core-ext",
+            "lib-portable",
+            "lib-standalone",
+            "lib-test-utils",
+            "lib-utils",
+            "lib-win-sys",
+            "lib-with-tests",
+        ]
+    );
+}
+
+#[test]
+fn root_manifest_change_ignored_without_lints_flag() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .default_force_triggers(false)
+        .compute(graph, &s(&["Cargo.toml"]))
+        .unwrap();
+    assert!(!result.force_all);
+    assert!(result.affected_library_members.is_empty());
+}
+
+#[test]
+fn member_manifest_change_does_not_trigger_lints_flag() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .lints_change_forces_all(true)
+        .compute(graph, &s(&["lib-standalone/Cargo.toml"]))
+        .unwrap();
+
+    assert!(!result.force_all);
+    assert_eq!(result.changed_crates, vec!["lib-standalone"]);
+}
+
+// ── Lockfile refinement ─────────────────────────────────────────────
+
+/// The fixture's `Cargo.lock`, as `cargo metadata` left it.
+fn fixture_lockfile() -> String {
+    fixture_graph();
+    std::fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workspace/Cargo.lock"),
+    )
+    .unwrap()
+}
+
+#[test]
+fn lockfile_change_only_affects_dependents_of_changed_packages() {
+    let graph = fixture_graph();
+    // lib-standalone was locked at another version before the change.
+    let base = fixture_lockfile().replace(
+        "name = \"lib-standalone\"\nversion = \"0.1.0\"",
+        "name = \"lib-standalone\"\nversion = \"0.0.9\"",
+    );
+    let result = AffectedOptions::new()
+        .force_triggers(["Cargo.lock"])
+        .base_lockfile(Some(base))
+        .compute(graph, &s(&["Cargo.lock"]))
+        .unwrap();
+
+    assert!(!result.force_all);
+    assert!(result.changed_crates.is_empty());
+    assert_eq!(result.affected_library_members, vec!["lib-standalone"]);
+    assert_eq!(result.affected_binary_members, vec!["app-beta"]);
+}
+
+#[test]
+fn lockfile_dependency_list_change_affects_the_package() {
+    let graph = fixture_graph();
+    let base = fixture_lockfile().replace(
+        "name = \"app-alpha\"\nversion = \"0.1.0\"\ndependencies = [\n \"lib-core\",\n]",
+        "name = \"app-alpha\"\nversion = \"0.1.0\"",
+    );
+    let result = AffectedOptions::new()
+        .base_lockfile(Some(base))
+        .compute(graph, &s(&["Cargo.lock"]))
+        .unwrap();
+
+    assert!(result.affected_library_members.is_empty());
+    assert_eq!(result.affected_binary_members, vec!["app-alpha"]);
+}
+
+#[test]
+fn unchanged_lockfile_affects_nothing() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .force_triggers(["Cargo.lock"])
+        .base_lockfile(Some(fixture_lockfile()))
+        .compute(graph, &s(&["Cargo.lock"]))
+        .unwrap();
+
+    assert!(!result.force_all);
+    assert!(result.affected_library_members.is_empty());
+    assert!(result.affected_binary_members.is_empty());
+}
+
+#[test]
+fn lockfile_change_forces_all_without_base() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .base_lockfile(None)
+        .compute(graph, &s(&["Cargo.lock"]))
+        .unwrap();
+    assert!(result.force_all);
+
+    let result = AffectedOptions::new()
+        .base_lockfile(Some("not a [lockfile".to_string()))
+        .compute(graph, &s(&["Cargo.lock"]))
+        .unwrap();
+    assert!(result.force_all);
+}
+
+// ── Flap state ──────────────────────────────────────────────────────
+
+#[test]
+fn alternating_crate_is_flagged_as_flapping() {
+    let mut state = FlapState::default();
+    // lib-core is affected every run; lib-utils comes and goes.
+    for run in 0..5 {
+        if run % 2 == 0 {
+            state.record(&["lib-core", "lib-utils"]);
+        } else {
+            state.record(&["lib-core"]);
+        }
+    }
+
+    assert_eq!(
+        state.crates["lib-utils"],
+        FlapHistory {
+            runs: 5,
+            affected_runs: 3,
+            flips: 4,
+            last_affected: true,
+        }
+    );
+    assert_eq!(state.crates["lib-core"].flips, 0);
+    assert_eq!(state.flapping(2), vec!["lib-utils"]);
+    assert!(state.flapping(4).is_empty());
+}
+
+#[test]
+fn crate_first_affected_later_does_not_flip() {
+    let mut state = FlapState::default();
+    state.record::<&str>(&[]);
+    state.record(&["lib-core"]);
+    state.record(&["lib-core"]);
+
+    assert_eq!(state.crates["lib-core"].runs, 2);
+    assert_eq!(state.crates["lib-core"].flips, 0);
+}
+
+#[test]
+fn flap_state_round_trips_through_json() {
+    let mut state = FlapState::default();
+    state.record(&["lib-core"]);
+    state.record::<&str>(&[]);
+
+    let json = serde_json::to_value(&state).unwrap();
+    assert_eq!(json["lib-core"]["flips"], 1);
+    assert_eq!(serde_json::from_value::<FlapState>(json).unwrap(), state);
+}
+
+// ── crates_for_file ─────────────────────────────────────────────────
+
+fn crate_names(graph: &PackageGraph, file: &str) -> Vec<String> {
+    crates_for_file(graph, file)
+        .iter()
+        .map(|id| graph.metadata(id).unwrap().name().to_string())
+        .collect()
+}
+
+#[test]
+fn crates_for_file_attributes_to_owning_crate() {
+    let graph = fixture_graph();
+
+    assert_eq!(crate_names(graph, "lib-core/src/lib.rs"), vec!["lib-core"]);
+    assert_eq!(
+        crate_names(graph, "tools/tool-alpha/Cargo.toml"),
+        vec!["tool-alpha"]
+    );
+    // Component-wise matching: lib-core-ext is not part of lib-core.
+    assert_eq!(
+        crate_names(graph, "lib-core-ext/src/lib.rs"),
+        vec!["lib-core-ext"]
+    );
+}
+
+#[test]
+fn crates_for_file_outside_any_crate_is_empty() {
+    let graph = fixture_graph();
+
+    assert!(crates_for_file(graph, "README.md").is_empty());
+    assert!(crates_for_file(graph, "tools/README.md").is_empty());
+}
+
+#[test]
+fn unmatched_files_follow_compute_attribution() {
+    let graph = fixture_graph();
+    let files = s(&[
+        "LIB-CORE/src/lib.rs",
+        "schemas/user.json",
+        "infra/app-alpha/deploy.yml",
+        ".github/workflows/deploy.yml",
+        "vendor/sdk/include/sdk.h",
+        "docs/guide.md",
+        "docs/internal/notes.md",
+        "Cargo.lock",
+        "typo/lib-core/src/lib.rs",
+    ]);
+    let options = AffectedOptions::new()
+        .case_insensitive_paths(true)
+        .default_force_triggers(true)
+        .force_triggers(["docs/** => binaries", "!docs/internal/**"])
+        .codegen_inputs([("schemas/*.json", ["lib-core"])])
+        .scoped_triggers([("infra/app-alpha/**", ["app-alpha"])])
+        .workflow_crate_map([("deploy.yml", ["app-alpha"])])
+        .submodule_crate_map([("vendor/sdk", ["lib-core"])]);
+
+    assert_eq!(
+        options.unmatched_files(graph, &files).unwrap(),
+        vec!["docs/internal/notes.md", "typo/lib-core/src/lib.rs"]
+    );
+    // Without the default triggers, the lockfile is attributed to nothing.
+    assert_eq!(
+        options
+            .default_force_triggers(false)
+            .unmatched_files(graph, &s(&["Cargo.lock"]))
+            .unwrap(),
+        vec!["Cargo.lock"]
+    );
+}
+
+#[test]
+fn unmatched_files_reports_invalid_trigger() {
+    let graph = fixture_graph();
+    let err = AffectedOptions::new()
+        .force_triggers(["docs/[.md"])
+        .unmatched_files(graph, &s(&["README.md"]))
+        .unwrap_err();
+    assert!(matches!(err, AffectedError::InvalidGlob { .. }), "{err}");
+}
+
+#[test]
+fn file_owners_follow_compute_attribution() {
+    let graph = fixture_graph();
+    let files = s(&[
+        "LIB-CORE/src/lib.rs",
+        "README.md",
+        "tools/tool-alpha/src/main.rs",
+    ]);
+    let names = |options: AffectedOptions| -> Vec<Option<String>> {
+        options
+            .file_owners(graph, &files)
+            .iter()
+            .map(|id| {
+                id.as_ref()
+                    .map(|id| graph.metadata(id).unwrap().name().to_string())
+            })
+            .collect()
+    };
+
+    assert_eq!(
+        names(AffectedOptions::new()),
+        vec![None, None, Some("tool-alpha".to_string())]
+    );
+    assert_eq!(
+        names(AffectedOptions::new().case_insensitive_paths(true)),
+        vec![
+            Some("lib-core".to_string()),
+            None,
+            Some("tool-alpha".to_string())
+        ]
+    );
+}
+
+// ── Nested crates ───────────────────────────────────────────────────
+
+fn nested_graph() -> &'static PackageGraph {
+    static GRAPH: OnceLock<PackageGraph> = OnceLock::new();
+    GRAPH.get_or_init(|| load_graph(&["nested"]))
+}
+
+#[test]
+fn nested_crate_file_attributed_to_deepest_crate() {
+    let graph = nested_graph();
+    // plugins/foo is its own crate inside the plugins crate's directory.
+    let changed = s(&["plugins/foo/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(result.changed_crates, vec!["plugin-foo"]);
+    assert_eq!(result.affected_library_members, vec!["plugin-foo"]);
+    assert!(result.affected_binary_members.is_empty());
+    assert_eq!(
+        crate_names(graph, "plugins/foo/src/lib.rs"),
+        vec!["plugin-foo"]
+    );
+}
+
+#[test]
+fn outer_crate_file_attributed_to_outer_crate() {
+    let graph = nested_graph();
+    let changed = s(&["plugins/src/lib.rs"]);
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(result.changed_crates, vec!["plugins"]);
+    assert_eq!(result.affected_library_members, vec!["plugins"]);
+    assert_eq!(result.affected_binary_members, vec!["host"]);
+}
+
+/// The nested-loop attribution: the deepest member directory the file lies in.
+fn deepest_owner(graph: &PackageGraph, file: &str) -> Vec<String> {
+    let root = graph.workspace().root().as_std_path();
+    graph
+        .workspace()
+        .iter()
+        .map(|pkg| {
+            let dir = pkg.manifest_path().parent().unwrap().as_std_path();
+            (dir.strip_prefix(root).unwrap().to_path_buf(), pkg)
+        })
+        .filter(|(dir, _)| Path::new(file).starts_with(dir))
+        .max_by_key(|(dir, _)| dir.components().count())
+        .map(|(_, pkg)| pkg.name().to_string())
+        .into_iter()
+        .collect()
+}
+
+#[test]
+fn crates_for_file_matches_nested_loop_attribution() {
+    let files = [
+        "README.md",
+        "Cargo.toml",
+        "lib-core",
+        "lib-core/src/lib.rs",
+        "lib-core-ext/src/lib.rs",
+        "lib-core/../lib-utils/src/lib.rs",
+        "tools/README.md",
+        "tools/tool-alpha",
+        "tools/tool-alpha/src/main.rs",
+        "plugins/src/lib.rs",
+        "plugins/foo/src/lib.rs",
+        "plugins/foo-bar/src/lib.rs",
+        "host/src/main.rs",
+    ];
+    for graph in [fixture_graph(), nested_graph()] {
+        for file in files {
+            assert_eq!(
+                crate_names(graph, file),
+                deepest_owner(graph, file),
+                "{file}"
+            );
+        }
+    }
+}
+// ── Always-affected members ─────────────────────────────────────────
+
+#[test]
+fn always_affected_member_added_without_its_dependents() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .always_affected(["lib-standalone"])
+        .compute(graph, &s(&["app-alpha/src/main.rs"]))
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["app-alpha"]);
+    assert_eq!(result.affected_library_members, vec!["lib-standalone"]);
+    // app-beta depends on lib-standalone but nothing it uses changed.
+    assert_eq!(result.affected_binary_members, vec!["app-alpha"]);
+}
+
+#[test]
+fn excluded_wins_over_always_affected() {
+    let graph = fixture_graph();
+    let options = AffectedOptions::new()
+        .always_affected(["lib-standalone", "tool-alpha"])
+        .excluded(["lib-standalone", "tools/"]);
+    let result = options
+        .compute(graph, &s(&["app-alpha/src/main.rs"]))
+        .unwrap();
+
+    assert!(result.affected_library_members.is_empty());
+    assert_eq!(result.affected_binary_members, vec!["app-alpha"]);
+    assert_eq!(
+        options.always_affected_conflicts(graph),
+        vec!["lib-standalone", "tool-alpha"]
+    );
+}
+
+#[test]
+fn always_affected_without_exclusions_has_no_conflicts() {
+    let graph = fixture_graph();
+    let options = AffectedOptions::new().always_affected(["lib-standalone"]);
+    assert!(options.always_affected_conflicts(graph).is_empty());
+}
+
+// ── Absolute paths ──────────────────────────────────────────────────
+
+#[test]
+fn absolute_path_under_workspace_root_resolves_to_crate() {
+    let graph = fixture_graph();
+    let root = graph.workspace().root();
+    let absolute = root.join("lib-core/src/lib.rs").to_string();
+    let result =
+        compute_affected(graph, std::slice::from_ref(&absolute), &[], &no_excludes()).unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-core"]);
+    assert_eq!(crate_names(graph, &absolute), vec!["lib-core"]);
+}
+
+#[test]
+fn absolute_and_relative_paths_mix() {
+    let graph = fixture_graph();
+    let absolute = graph
+        .workspace()
+        .root()
+        .join("lib-standalone/src/lib.rs")
+        .to_string();
+    let changed = vec![absolute, "lib-utils/src/lib.rs".to_string()];
+    let result = compute_affected(graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-standalone", "lib-utils"]);
+}
+
+#[test]
+fn absolute_path_outside_workspace_root_is_ignored() {
+    let graph = fixture_graph();
+    let result = compute_affected(
+        graph,
+        &s(&["/somewhere/else/lib-core/src/lib.rs"]),
+        &[],
+        &no_excludes(),
+    )
+    .unwrap();
+    assert!(result.changed_crates.is_empty());
+    assert!(crates_for_file(graph, "/lib-core/src/lib.rs").is_empty());
+}
+
+// ── Windows path separators ─────────────────────────────────────────
+
+#[test]
+fn backslash_path_matches_forward_slash_crate_dir() {
+    let graph = fixture_graph();
+    let windows =
+        compute_affected(graph, &s(&[r"lib-core\src\lib.rs"]), &[], &no_excludes()).unwrap();
+    let unix = compute_affected(graph, &s(&["lib-core/src/lib.rs"]), &[], &no_excludes()).unwrap();
+
+    assert_eq!(windows.changed_crates, vec!["lib-core"]);
+    assert_eq!(windows, unix);
+}
+
+#[test]
+fn backslash_path_matches_nested_crate_dir() {
+    let graph = fixture_graph();
+    let result = compute_affected(
+        graph,
+        &s(&[r"tools\tool-alpha\src\main.rs"]),
+        &[],
+        &no_excludes(),
+    )
+    .unwrap();
+    assert_eq!(result.changed_crates, vec!["tool-alpha"]);
+    assert_eq!(
+        crate_names(graph, r"tools\tool-alpha\src\main.rs"),
+        vec!["tool-alpha"]
+    );
+}
+
+#[test]
+fn backslash_path_matches_force_trigger() {
+    let graph = fixture_graph();
+    let result = compute_affected(
+        graph,
+        &s(&[r".github\workflows\ci.yml"]),
+        &s(&[".github/"]),
+        &no_excludes(),
+    )
+    .unwrap();
+    assert!(result.force_all);
+}
+
+#[test]
+fn normalize_changed_file_converts_backslashes() {
+    assert_eq!(
+        normalize_changed_file(r"lib-core\src\lib.rs"),
+        "lib-core/src/lib.rs"
+    );
+    assert_eq!(
+        normalize_changed_file("lib-core/src/lib.rs"),
+        "lib-core/src/lib.rs"
+    );
+}
+
+// ── Path cleanup ────────────────────────────────────────────────────
+
+#[test]
+fn leading_dot_slash_resolves_to_crate() {
+    let graph = fixture_graph();
+    let result =
+        compute_affected(graph, &s(&["./lib-core/src/lib.rs"]), &[], &no_excludes()).unwrap();
+    assert_eq!(result.changed_crates, vec!["lib-core"]);
+    assert_eq!(
+        crate_names(graph, "./lib-core/src/lib.rs"),
+        vec!["lib-core"]
+    );
+}
+
+#[test]
+fn internal_dot_components_resolve_to_crate() {
+    let graph = fixture_graph();
+    let result = compute_affected(
+        graph,
+        &s(&["lib-core/./src//lib.rs", "././lib-utils/src/lib.rs"]),
+        &[],
+        &no_excludes(),
+    )
+    .unwrap();
+    assert_eq!(result.changed_crates, vec!["lib-core", "lib-utils"]);
+}
+
+#[test]
+fn normalize_changed_file_cleans_dot_components() {
+    assert_eq!(
+        normalize_changed_file("./lib-core/src/lib.rs"),
+        "lib-core/src/lib.rs"
+    );
+    assert_eq!(
+        normalize_changed_file(r".\lib-core\.\src\lib.rs"),
+        "lib-core/src/lib.rs"
+    );
+    // `..` is never resolved, so a path can't be made to escape into a crate.
+    assert_eq!(
+        normalize_changed_file("../lib-core/src/lib.rs"),
+        "../lib-core/src/lib.rs"
+    );
+}
+
+#[test]
+fn parent_components_do_not_resolve_into_crate() {
+    let graph = fixture_graph();
+    let result =
+        compute_affected(graph, &s(&["../lib-core/src/lib.rs"]), &[], &no_excludes()).unwrap();
+    assert!(result.changed_crates.is_empty());
+}
+
+// ── Editions ────────────────────────────────────────────────────────
+
+fn editions_graph() -> &'static PackageGraph {
+    static GRAPH: OnceLock<PackageGraph> = OnceLock::new();
+    GRAPH.get_or_init(|| load_graph(&["editions"]))
+}
+
+#[test]
+fn editions_reported_for_affected_members() {
+    let graph = editions_graph();
+    // modern (2021) depends on legacy (2015).
+    let result = AffectedOptions::new()
+        .editions(true)
+        .compute(graph, &s(&["legacy/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(
+        result.editions,
+        BTreeMap::from([
+            ("legacy".to_string(), "2015".to_string()),
+            ("modern".to_string(), "2021".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn editions_only_cover_affected_members() {
+    let graph = editions_graph();
+    let result = AffectedOptions::new()
+        .editions(true)
+        .compute(graph, &s(&["modern/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(
+        result.editions,
+        BTreeMap::from([("modern".to_string(), "2021".to_string())])
+    );
+}
+
+#[test]
+fn editions_are_off_by_default() {
+    let graph = editions_graph();
+    let result = compute_affected(graph, &s(&["legacy/src/lib.rs"]), &[], &no_excludes()).unwrap();
+    assert!(result.editions.is_empty());
+}
+
+// ── Testable members ────────────────────────────────────────────────
+
+#[test]
+fn testable_members_add_changed_libraries_to_binaries() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .excluded(["lib-core-ext"])
+        .testable_members(true)
+        .compute(graph, &s(&["lib-core/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta"]
+    );
+    assert_eq!(
+        result.testable_members,
+        vec!["app-alpha", "app-beta", "lib-core"]
+    );
+}
+
+#[test]
+fn testable_members_skip_changed_crates_without_tests() {
+    let graph = fixture_graph();
+    // lib-test-utils sets `test = false` on its library.
+    let result = AffectedOptions::new()
+        .testable_members(true)
+        .compute(graph, &s(&["lib-test-utils/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["lib-test-utils"]);
+    assert!(result.testable_members.is_empty());
+}
+
+#[test]
+fn testable_members_are_off_by_default() {
+    let graph = fixture_graph();
+    let result =
+        compute_affected(graph, &s(&["lib-core/src/lib.rs"]), &[], &no_excludes()).unwrap();
+    assert!(result.testable_members.is_empty());
+}
+
+// ── JSON Patch against a baseline ───────────────────────────────────
+
+/// Apply the `add`, `remove` and `replace` operations `json_patch` emits.
+fn apply_patch(doc: &mut serde_json::Value, patch: &serde_json::Value) {
+    for op in patch.as_array().unwrap() {
+        let path = op["path"].as_str().unwrap();
+        if path.is_empty() {
+            *doc = op["value"].clone();
+            continue;
+        }
+        let (parent, key) = path.rsplit_once('/').unwrap();
+        let key = key.replace("~1", "/").replace("~0", "~");
+        let target = doc.pointer_mut(parent).unwrap().as_object_mut().unwrap();
+        match op["op"].as_str().unwrap() {
+            "add" | "replace" => {
+                target.insert(key, op["value"].clone());
+            }
+            "remove" => {
+                target.remove(&key);
+            }
+            other => panic!("unexpected op {other}"),
+        }
+    }
+}
+
+#[test]
+fn json_patch_reproduces_current_result() {
+    let graph = fixture_graph();
+    let baseline = serde_json::to_value(
+        AffectedOptions::new()
+            .compute(graph, &s(&["lib-utils/src/lib.rs"]))
+            .unwrap(),
+    )
+    .unwrap();
+    let current = serde_json::to_value(
+        AffectedOptions::new()
+            .degrees(true)
+            .compute(graph, &s(&["app-alpha/src/main.rs"]))
+            .unwrap(),
+    )
+    .unwrap();
+
+    let patch = json_patch(&baseline, &current);
+    assert!(
+        patch
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|op| op["op"] == "add" && op["path"] == "/degrees")
+    );
+
+    let mut patched = baseline.clone();
+    apply_patch(&mut patched, &patch);
+    assert_eq!(patched, current);
+
+    // And back again.
+    let mut reverted = current.clone();
+    apply_patch(&mut reverted, &json_patch(&current, &baseline));
+    assert_eq!(reverted, baseline);
+}
+
+#[test]
+fn json_patch_of_identical_results_is_empty() {
+    let graph = fixture_graph();
+    let result = serde_json::to_value(
+        AffectedOptions::new()
+            .compute(graph, &s(&["lib-core/src/lib.rs"]))
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(json_patch(&result, &result), serde_json::json!([]));
+}
+
+#[test]
+fn json_patch_escapes_keys() {
+    let baseline = serde_json::json!({"source_hashes": {}});
+    let current = serde_json::json!({"source_hashes": {"a/b~c": "00"}});
+    assert_eq!(
+        json_patch(&baseline, &current),
+        serde_json::json!([{"op": "add", "path": "/source_hashes/a~1b~0c", "value": "00"}])
+    );
+}
+
+// ── Rebuild markers ─────────────────────────────────────────────────
+
+fn rebuild_marker_graph() -> &'static PackageGraph {
+    static GRAPH: OnceLock<PackageGraph> = OnceLock::new();
+    GRAPH.get_or_init(|| load_graph(&["rebuild-marker"]))
+}
+
+#[test]
+fn rebuild_marker_seeds_marked_crate_as_changed() {
+    let graph = rebuild_marker_graph();
+    // `marked` holds a `.rebuild` file; consumer depends on it.
+    let result = AffectedOptions::new()
+        .rebuild_marker(".rebuild")
+        .compute(graph, &s(&["other/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["marked", "other"]);
+    assert_eq!(
+        result.affected_library_members,
+        vec!["consumer", "marked", "other"]
+    );
+}
+
+#[test]
+fn rebuild_marker_with_other_name_is_ignored() {
+    let graph = rebuild_marker_graph();
+    let result = AffectedOptions::new()
+        .rebuild_marker(".force-build")
+        .compute(graph, &s(&["other/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["other"]);
+}
+
+#[test]
+fn rebuild_marker_is_off_by_default() {
+    let graph = rebuild_marker_graph();
+    let result = AffectedOptions::new()
+        .compute(graph, &s(&["other/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["other"]);
+    assert_eq!(result.affected_library_members, vec!["other"]);
+}
+
+// ── Default members ─────────────────────────────────────────────────
+
+fn default_members_graph() -> &'static PackageGraph {
+    static GRAPH: OnceLock<PackageGraph> = OnceLock::new();
+    GRAPH.get_or_init(|| load_graph(&["default-members"]))
+}
+
+#[test]
+fn only_default_members_drops_other_members() {
+    let graph = default_members_graph();
+    // `tool` depends on `base` but isn't in `default-members`.
+    let result = AffectedOptions::new()
+        .only_default_members(true)
+        .compute(graph, &s(&["base/src/lib.rs", "tool/src/main.rs"]))
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["base"]);
+    assert_eq!(result.affected_library_members, vec!["base"]);
+    assert_eq!(result.affected_binary_members, vec!["app"]);
+}
+
+#[test]
+fn all_members_reported_by_default() {
+    let graph = default_members_graph();
+    let result = AffectedOptions::new()
+        .compute(graph, &s(&["base/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.affected_binary_members, vec!["app", "tool"]);
+}
+
+#[test]
+fn only_default_members_keeps_every_member_without_default_members() {
+    let graph = fixture_graph();
+    let result = AffectedOptions::new()
+        .only_default_members(true)
+        .compute(graph, &s(&["lib-utils/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(
+        result.affected_binary_members,
+        vec!["app-alpha", "app-beta", "tool-alpha"]
+    );
+}
+
+// ── Publishable members ─────────────────────────────────────────────
+
+fn publish_graph() -> &'static PackageGraph {
+    static GRAPH: OnceLock<PackageGraph> = OnceLock::new();
+    GRAPH.get_or_init(|| load_graph(&["publish"]))
+}
+
+#[test]
+fn only_publishable_drops_private_members() {
+    let graph = publish_graph();
+    // `base` has `publish = false`; `internal` may only go to its own registry.
+    let result = AffectedOptions::new()
+        .only_publishable(true)
+        .compute(graph, &s(&["base/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["base"]);
+    assert_eq!(result.affected_library_members, vec!["internal"]);
+    assert_eq!(result.affected_binary_members, vec!["app"]);
+}
+
+#[test]
+fn private_members_reported_by_default() {
+    let graph = publish_graph();
+    let result = AffectedOptions::new()
+        .compute(graph, &s(&["base/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.affected_library_members, vec!["base", "internal"]);
+}
+
+// ── Proc-macro members ──────────────────────────────────────────────
+
+#[test]
+fn proc_macro_members_listed_separately() {
+    let graph = load_graph(&["proc-macro"]);
+    let result = compute_affected(&graph, &s(&["derive/src/lib.rs"]), &[], &no_excludes()).unwrap();
+
+    assert_eq!(
+        result.affected_library_members,
+        vec!["derive", "uses-derive"]
+    );
+    assert_eq!(result.affected_proc_macro_members, vec!["derive"]);
+    assert_eq!(result.affected_binary_members, vec!["app"]);
+}
+
+#[test]
+fn proc_macro_members_empty_without_proc_macros() {
+    let graph = fixture_graph();
+    let result =
+        compute_affected(graph, &s(&["lib-utils/src/lib.rs"]), &[], &no_excludes()).unwrap();
+    assert!(result.affected_proc_macro_members.is_empty());
+}
+
+// ── FFI members ─────────────────────────────────────────────────────
+
+#[test]
+fn cdylib_and_staticlib_members_listed() {
+    let graph = load_graph(&["ffi"]);
+    let result = compute_affected(&graph, &s(&["base/src/lib.rs"]), &[], &no_excludes()).unwrap();
+
+    // `ffi` builds an rlib and a cdylib, `static-ffi` only a staticlib.
+    assert_eq!(
+        result.affected_library_members,
+        vec!["base", "ffi", "static-ffi"]
+    );
+    assert_eq!(result.affected_cdylib_members, vec!["ffi", "static-ffi"]);
+    assert_eq!(result.affected_binary_members, vec!["cli"]);
+}
+
+#[test]
+fn cdylib_members_only_lists_affected_members() {
+    let graph = load_graph(&["ffi"]);
+    let result = compute_affected(&graph, &s(&["cli/src/main.rs"]), &[], &no_excludes()).unwrap();
+    assert!(result.affected_cdylib_members.is_empty());
+}
+
+// ── Removed crates ──────────────────────────────────────────────────
+
+#[test]
+fn deleted_crate_directory_is_reported_as_removed() {
+    let graph = load_graph(&["removed-crate"]);
+    let changed = s(&["crates/legacy/Cargo.toml", "crates/legacy/src/lib.rs"]);
+    let result = compute_affected(&graph, &changed, &[], &no_excludes()).unwrap();
+
+    assert_eq!(result.removed_crates, vec!["legacy"]);
+    assert!(result.changed_crates.is_empty());
+}
+
+#[test]
+fn removed_crate_named_after_its_base_manifest() {
+    let graph = load_graph(&["removed-crate"]);
+    let base = "[package]\nname = \"legacy-client\"\nversion = \"0.1.0\"\n";
+    let result = AffectedOptions::new()
+        .base_manifests([("crates/legacy/Cargo.toml", base)])
+        .compute(&graph, &s(&["crates/legacy/Cargo.toml"]))
+        .unwrap();
+
+    assert_eq!(result.removed_crates, vec!["legacy-client"]);
+}
+
+#[test]
+fn existing_member_manifest_is_not_removed() {
+    let graph = load_graph(&["removed-crate"]);
+    let result = compute_affected(&graph, &s(&["base/Cargo.toml"]), &[], &no_excludes()).unwrap();
+
+    assert!(result.removed_crates.is_empty());
+    assert_eq!(result.changed_crates, vec!["base"]);
+}
+
+// ── Directory name differs from package name ────────────────────────
+
+#[test]
+fn files_attributed_by_manifest_directory_not_name() {
+    // `engine` lives in `crates/core-impl`; `app` (in `crates/service`)
+    // depends on it under the renamed import `engine-alias`.
+    let graph = load_graph(&["renamed"]);
+    let result = compute_affected(
+        &graph,
+        &s(&["crates/core-impl/src/lib.rs"]),
+        &[],
+        &no_excludes(),
+    )
+    .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["engine"]);
+    assert_eq!(result.affected_library_members, vec!["engine"]);
+    assert_eq!(result.affected_binary_members, vec!["app"]);
+    assert_eq!(result.affected_paths["engine"], "crates/core-impl");
+    assert_eq!(result.affected_paths["app"], "crates/service");
+}
+
+#[test]
+fn package_name_as_directory_matches_nothing() {
+    let graph = load_graph(&["renamed"]);
+    assert!(crate_names(&graph, "engine/src/lib.rs").is_empty());
+    assert_eq!(
+        crate_names(&graph, "crates/service/src/main.rs"),
+        vec!["app"]
+    );
+}
+
+#[test]
+fn workspace_members_lists_names_and_directories() {
+    let graph = load_graph(&["renamed"]);
+    assert_eq!(
+        workspace_members(&graph),
+        vec![
+            ("app".to_string(), PathBuf::from("crates/service")),
+            ("engine".to_string(), PathBuf::from("crates/core-impl")),
+        ]
+    );
+}
+
+// ── Symlinked member directories ────────────────────────────────────
+
+fn symlinked_graph() -> &'static PackageGraph {
+    static GRAPH: OnceLock<PackageGraph> = OnceLock::new();
+    // `vendor` links to `third_party`, and `vendor/shim` is a member.
+    GRAPH.get_or_init(|| load_graph(&["symlinked"]))
+}
+
+#[test]
+fn symlinked_member_owns_files_under_its_declared_path() {
+    let graph = symlinked_graph();
+    let result =
+        compute_affected(graph, &s(&["vendor/shim/src/lib.rs"]), &[], &no_excludes()).unwrap();
+
+    assert_eq!(result.changed_crates, vec!["shim"]);
+    assert_eq!(result.affected_binary_members, vec!["app"]);
+    assert_eq!(result.affected_paths["shim"], "vendor/shim");
+}
+
+#[test]
+fn symlinked_member_owns_files_under_its_real_path() {
+    // git reports changes below the symlink's target.
+    let graph = symlinked_graph();
+    let result = compute_affected(
+        graph,
+        &s(&["third_party/shim/src/lib.rs"]),
+        &[],
+        &no_excludes(),
+    )
+    .unwrap();
+
+    assert_eq!(result.changed_crates, vec!["shim"]);
+    assert_eq!(result.affected_binary_members, vec!["app"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn absolute_path_through_a_symlinked_root_is_attributed() {
+    let graph = symlinked_graph();
+    let link = std::env::temp_dir().join(format!("test-symlinked-root-{}", std::process::id()));
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(graph.workspace().root(), &link).unwrap();
+
+    let app = link.join("app/src/main.rs");
+    let deleted = link.join("vendor/shim/src/removed.rs");
+    let names = crate_names(graph, app.to_str().unwrap());
+    let deleted_names = crate_names(graph, deleted.to_str().unwrap());
+    std::fs::remove_file(&link).unwrap();
+
+    assert_eq!(names, vec!["app"]);
+    // A file that no longer exists still resolves through the link.
+    assert_eq!(deleted_names, vec!["shim"]);
+}
+
+// ── Skipping through package metadata ───────────────────────────────
+
+#[test]
+fn metadata_skip_excludes_the_member() {
+    let graph = load_graph(&["skip-metadata"]);
+    // `gen` sets `[package.metadata.affected] skip = true`.
+    let result = compute_affected(&graph, &s(&["base/src/lib.rs"]), &[], &no_excludes()).unwrap();
+    assert_eq!(result.affected_library_members, vec!["base"]);
+    assert_eq!(result.affected_binary_members, vec!["app"]);
+
+    let result =
+        compute_affected(&graph, &s(&["tools/gen/src/lib.rs"]), &[], &no_excludes()).unwrap();
+    assert!(result.changed_crates.is_empty());
+    assert!(result.affected_library_members.is_empty());
+}
+
+#[test]
+fn metadata_skip_is_listed_with_excluded_members() {
+    let graph = load_graph(&["skip-metadata"]);
+    assert_eq!(AffectedOptions::new().excluded_members(&graph), vec!["gen"]);
+}
+
+// ── Root manifest changes ───────────────────────────────────────────
+
+fn workspace_dependencies_graph() -> &'static PackageGraph {
+    static GRAPH: OnceLock<PackageGraph> = OnceLock::new();
+    GRAPH.get_or_init(|| load_graph(&["workspace-dependencies"]))
+}
+
+fn root_manifest() -> String {
+    std::fs::read_to_string(
+        [
+            env!("CARGO_MANIFEST_DIR"),
+            "tests",
+            "fixtures",
+            "workspace-dependencies",
+            "Cargo.toml",
+        ]
+        .iter()
+        .collect::<PathBuf>(),
+    )
+    .unwrap()
+}
+
+/// The fixture's root manifest before `util` dropped its default features.
+fn root_manifest_with_util_defaults() -> String {
+    root_manifest().replace(", default-features = false", "")
+}
+
+#[test]
+fn workspace_dependency_change_affects_inheriting_members() {
+    let graph = workspace_dependencies_graph();
+    let result = AffectedOptions::new()
+        .default_force_triggers(true)
+        .base_manifests([("Cargo.toml", root_manifest_with_util_defaults())])
+        .compute(graph, &s(&["Cargo.toml"]))
+        .unwrap();
+
+    assert!(!result.force_all);
+    assert!(result.changed_crates.is_empty());
+    assert_eq!(result.affected_library_members, vec!["uses-util"]);
+    assert_eq!(result.affected_binary_members, vec!["app"]);
+}
+
+#[test]
+fn other_root_manifest_change_forces_all() {
+    let graph = workspace_dependencies_graph();
+    let result = AffectedOptions::new()
+        .base_manifests([(
+            "Cargo.toml",
+            root_manifest_with_util_defaults() + "\n[workspace.package]\nedition = \"2018\"\n",
+        )])
+        .compute(graph, &s(&["Cargo.toml"]))
+        .unwrap();
+
+    assert!(result.force_all);
+}
+
+#[test]
+fn workspace_metadata_change_affects_nothing() {
+    let graph = workspace_dependencies_graph();
+    let result = AffectedOptions::new()
+        .base_manifests([(
+            "Cargo.toml",
+            root_manifest() + "\n[workspace.metadata.ci]\nowner = \"infra\"\n",
+        )])
+        .compute(graph, &s(&["Cargo.toml"]))
+        .unwrap();
+
+    assert!(!result.force_all);
+    assert!(!result.any_affected);
+}
+
+#[test]
+fn root_manifest_without_base_is_left_to_force_triggers() {
+    let graph = workspace_dependencies_graph();
+    let changed = s(&["Cargo.toml"]);
+    assert!(
+        !AffectedOptions::new()
+            .default_force_triggers(false)
+            .compute(graph, &changed)
+            .unwrap()
+            .any_affected
+    );
+    assert!(
+        AffectedOptions::new()
+            .compute(graph, &changed)
+            .unwrap()
+            .force_all
+    );
+}
+
+// ── Dependency kinds ────────────────────────────────────────────────
+
+fn dependency_kinds_graph() -> &'static PackageGraph {
+    static GRAPH: OnceLock<PackageGraph> = OnceLock::new();
+    GRAPH.get_or_init(|| load_graph(&["dependency-kinds"]))
+}
+
+#[test]
+fn build_dependency_change_propagates_by_default() {
+    let graph = dependency_kinds_graph();
+    // `base` build-depends on codegen, dev-depends on testkit and depends on helpers.
+    let result = AffectedOptions::new()
+        .compute(graph, &s(&["codegen/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.affected_library_members, vec!["base", "codegen"]);
+    assert_eq!(result.affected_binary_members, vec!["app"]);
+}
+
+#[test]
+fn normal_only_skips_build_and_dev_dependents() {
+    let graph = dependency_kinds_graph();
+    let options = AffectedOptions::new().dependency_kinds([DependencyKind::Normal]);
+
+    let result = options.compute(graph, &s(&["codegen/src/lib.rs"])).unwrap();
+    assert_eq!(result.affected_library_members, vec!["codegen"]);
+    assert!(result.affected_binary_members.is_empty());
+
+    let result = options.compute(graph, &s(&["testkit/src/lib.rs"])).unwrap();
+    assert_eq!(result.affected_library_members, vec!["testkit"]);
+
+    let result = options.compute(graph, &s(&["helpers/src/lib.rs"])).unwrap();
+    assert_eq!(result.affected_library_members, vec!["base", "helpers"]);
+    assert_eq!(result.affected_binary_members, vec!["app"]);
+}
+
+#[test]
+fn include_dev_after_dependency_kinds_adds_dev_edges() {
+    let graph = dependency_kinds_graph();
+    let result = AffectedOptions::new()
+        .dependency_kinds([DependencyKind::Normal])
+        .include_dev(true)
+        .compute(graph, &s(&["testkit/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.affected_library_members, vec!["base", "testkit"]);
+}
+
+#[test]
+fn split_build_test_ignores_dependency_kinds() {
+    let graph = dependency_kinds_graph();
+    let result = AffectedOptions::new()
+        .dependency_kinds([DependencyKind::Normal])
+        .split_build_test(true)
+        .compute(graph, &s(&["codegen/src/lib.rs", "testkit/src/lib.rs"]))
+        .unwrap();
+
+    assert_eq!(result.affected_library_members, vec!["codegen", "testkit"]);
+    // base build-depends on codegen and dev-depends on testkit.
+    assert_eq!(
+        result.affected_build,
+        vec!["app", "base", "codegen", "testkit"]
+    );
+    assert_eq!(
+        result.affected_test,
+        vec!["app", "base", "codegen", "testkit"]
+    );
+
+    let result = AffectedOptions::new()
+        .dependency_kinds([DependencyKind::Normal])
+        .split_build_test(true)
+        .compute(graph, &s(&["testkit/src/lib.rs"]))
+        .unwrap();
+    assert_eq!(result.affected_build, vec!["testkit"]);
+    assert_eq!(result.affected_test, vec!["app", "base", "testkit"]);
+}
+
+// ── Config file ─────────────────────────────────────────────────────
+
+#[test]
+fn config_file_is_loaded_from_workspace_root() {
+    let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "config"]
+        .iter()
+        .collect();
+    let config = AffectedConfig::load(&dir).unwrap();
+    assert_eq!(
+        config,
+        AffectedConfig {
+            force_triggers: s(&["ci/**"]),
+            excluded_members: s(&["tools/"]),
+            included_members: vec![],
+            codegen_inputs: [("fixtures/".to_string(), s(&["base"]))].into(),
+        }
+    );
+}
+
+#[test]
+fn missing_config_file_gives_empty_config() {
+    let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "workspace"]
+        .iter()
+        .collect();
+    assert_eq!(
+        AffectedConfig::load(&dir).unwrap(),
+        AffectedConfig::default()
+    );
+}
+
+#[test]
+fn invalid_config_file_is_an_error() {
+    let dir = std::env::temp_dir().join(format!("test-config-invalid-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(".affected.toml"), "force_trigger = [\"ci/**\"]\n").unwrap();
+
+    let err = AffectedConfig::load(&dir).unwrap_err();
+    std::fs::remove_dir_all(&dir).ok();
+    assert!(matches!(err, AffectedError::Config { .. }));
+    assert!(err.to_string().contains("force_trigger"));
+}
+
+// ── Computing from a path ───────────────────────────────────────────
+
+#[test]
+fn compute_from_path_loads_the_workspace() {
+    let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "workspace"]
+        .iter()
+        .collect();
+    let changed = s(&["lib-core/src/lib.rs"]);
+    let result = compute_affected_from_path(&dir, &changed, &AffectedOptions::new()).unwrap();
+
+    assert_eq!(
+        result,
+        compute_affected(fixture_graph(), &changed, &[], &no_excludes()).unwrap()
+    );
+}
+
+#[test]
+fn compute_from_path_outside_a_workspace_is_an_error() {
+    let dir =
+        std::env::temp_dir().join(format!("rust-affected-no-workspace-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let err =
+        compute_affected_from_path(&dir, &s(&["src/lib.rs"]), &AffectedOptions::new()).unwrap_err();
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert!(matches!(err, AffectedError::Metadata(_)));
+}
+
+#[test]
+fn compute_from_path_reports_invalid_globs() {
+    let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "workspace"]
+        .iter()
+        .collect();
+    let options = AffectedOptions::new().scoped_triggers([("infra/[", ["app-alpha"])]);
+    let err = compute_affected_from_path(&dir, &s(&["infra/x"]), &options).unwrap_err();
+
+    assert!(matches!(err, AffectedError::InvalidGlob { .. }));
+}
+
+#[test]
+fn validate_reports_invalid_select_glob() {
+    let options = AffectedOptions::new().select(Selector {
+        kind: None,
+        path: Some("services/[".to_string()),
+    });
+    let err = options.validate().unwrap_err();
+    assert!(matches!(err, AffectedError::InvalidGlob { pattern, .. } if pattern == "services/["));
+}
+
+#[test]
+fn validate_reports_invalid_target_triple() {
+    let err = AffectedOptions::new()
+        .target("bogus")
+        .validate()
+        .unwrap_err();
+    assert!(matches!(&err, AffectedError::InvalidTarget { triple, .. } if triple == "bogus"));
+    assert!(
+        err.to_string()
+            .starts_with("invalid target triple \"bogus\"")
+    );
+
+    assert!(
+        AffectedOptions::new()
+            .target("x86_64-unknown-linux-gnu")
+            .validate()
+            .is_ok()
+    );
+}
+
+#[test]
+fn compute_returns_errors_for_invalid_options() {
+    let graph = fixture_graph();
+    let changed = s(&["lib-core/src/lib.rs"]);
+
+    let err = AffectedOptions::new()
+        .force_triggers(["infra/ => services"])
+        .compute(graph, &changed)
+        .unwrap_err();
+    assert!(
+        matches!(err, AffectedError::InvalidTriggerKind { .. }),
+        "{err}"
+    );
+
+    let err = AffectedOptions::new()
+        .scoped_triggers([("infra/[", ["app-alpha"])])
+        .compute(graph, &changed)
+        .unwrap_err();
+    assert!(matches!(err, AffectedError::InvalidGlob { pattern, .. } if pattern == "infra/["));
+
+    let err = AffectedOptions::new()
+        .target("bogus")
+        .compute(graph, &changed)
+        .unwrap_err();
+    assert!(matches!(err, AffectedError::InvalidTarget { .. }), "{err}");
+}
+
+// ── Metadata cache ──────────────────────────────────────────────────
+
+/// Copy a fixture workspace into a fresh temporary directory.
+fn copy_fixture(fixture: &str, name: &str) -> PathBuf {
+    fn copy_dir(from: &Path, to: &Path) {
+        std::fs::create_dir_all(to).unwrap();
+        for entry in std::fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_name() == "target" {
+                continue;
+            }
+            let target = to.join(entry.file_name());
+            if entry.file_type().unwrap().is_dir() {
+                copy_dir(&entry.path(), &target);
+            } else {
+                std::fs::copy(entry.path(), &target).unwrap();
+            }
+        }
+    }
+    let source: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", fixture]
+        .iter()
+        .collect();
+    let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    copy_dir(&source, &dir);
+    dir
+}
+
+fn cached_version(workspace: &Path, cache_dir: &Path, name: &str) -> String {
+    let mut cmd = MetadataCommand::new();
+    cmd.current_dir(workspace);
+    let graph = cached_package_graph(&cmd, workspace, cache_dir).unwrap();
+    graph
+        .workspace()
+        .member_by_name(name)
+        .unwrap()
+        .version()
+        .to_string()
+}
+
+/// Rewrite the version of `name` inside every cache entry, so that a graph
+/// loaded from the cache can be told apart from a fresh one.
+fn tamper_cache(cache_dir: &Path, name: &str, version: &str) {
+    for entry in std::fs::read_dir(cache_dir).unwrap() {
+        let path = entry.unwrap().path();
+        let mut json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        for pkg in json["metadata"]["packages"].as_array_mut().unwrap() {
+            if pkg["name"] == name {
+                pkg["version"] = version.into();
+            }
+        }
+        std::fs::write(&path, json.to_string()).unwrap();
+    }
+}
+
+#[test]
+fn metadata_cache_reuses_output_until_a_manifest_changes() {
+    let workspace = copy_fixture("editions", "test-metadata-cache");
+    let cache_dir = workspace.join("cache");
+
+    assert_eq!(cached_version(&workspace, &cache_dir, "modern"), "0.1.0");
+    assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 1);
+
+    // A second run is served from the cache.
+    tamper_cache(&cache_dir, "modern", "9.9.9");
+    assert_eq!(cached_version(&workspace, &cache_dir, "modern"), "9.9.9");
+
+    // Touching a member manifest invalidates the entry.
+    let manifest = std::fs::File::options()
+        .append(true)
+        .open(workspace.join("modern/Cargo.toml"))
+        .unwrap();
+    manifest
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+        .unwrap();
+    assert_eq!(cached_version(&workspace, &cache_dir, "modern"), "0.1.0");
+
+    std::fs::remove_dir_all(&workspace).unwrap();
+}
+
+#[test]
+fn metadata_cache_ignores_corrupt_entries() {
+    let workspace = copy_fixture("editions", "test-metadata-cache-corrupt");
+    let cache_dir = workspace.join("cache");
+
+    assert_eq!(cached_version(&workspace, &cache_dir, "legacy"), "0.1.0");
+    for entry in std::fs::read_dir(&cache_dir).unwrap() {
+        std::fs::write(entry.unwrap().path(), "not json").unwrap();
+    }
+    assert_eq!(cached_version(&workspace, &cache_dir, "legacy"), "0.1.0");
+
+    std::fs::remove_dir_all(&workspace).unwrap();
+}